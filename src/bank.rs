@@ -1,7 +1,8 @@
 use cosmwasm_std::{
-    to_binary, Addr, Coin, CosmosMsg, Deps, Env, QueryRequest, SubMsg, Uint128, WasmMsg,
+    to_binary, Addr, Coin, CosmosMsg, Deps, Env, QueryRequest, StdResult, SubMsg, Uint128, WasmMsg,
     WasmQuery,
 };
+use cw20::Cw20ExecuteMsg;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -31,21 +32,57 @@ pub enum QueryBankMsg {
     IsAssetWhitelisted { asset: String },
 }
 
-pub fn pay_in(env: Env, bank_address: Addr, pay_in_amount: u128, pay_in_denom: String) -> SubMsg {
+/// Zero-amount coins are rejected by the bank module, so send no funds instead.
+fn escrow_funds(amount: u128, denom: String) -> Vec<Coin> {
+    if amount == 0 {
+        return vec![];
+    }
+    vec![Coin {
+        denom,
+        amount: Uint128::from(amount),
+    }]
+}
+
+pub fn pay_in(
+    env: Env,
+    bank_address: Addr,
+    pay_in_amount: u128,
+    pay_in_denom: String,
+) -> StdResult<SubMsg> {
     let expected = SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: bank_address.to_string(),
-        funds: vec![Coin {
-            denom: pay_in_denom.clone(),
-            amount: Uint128::from(pay_in_amount),
-        }],
+        funds: escrow_funds(pay_in_amount, pay_in_denom.clone()),
         msg: to_binary(&ExecuteBankMsg::PayIn {
             sender: env.contract.address,
             escrowed_amount: pay_in_amount,
             escrowed_asset: pay_in_denom,
-        })
-        .unwrap(),
+        })?,
+    }));
+    Ok(expected)
+}
+
+/// cw20 assets are escrowed by sending them to the bank with `PayIn` as the
+/// receive hook.
+pub fn pay_in_cw20(
+    env: Env,
+    bank_address: Addr,
+    token: Addr,
+    pay_in_amount: u128,
+) -> StdResult<SubMsg> {
+    let expected = SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: token.to_string(),
+        funds: vec![],
+        msg: to_binary(&Cw20ExecuteMsg::Send {
+            contract: bank_address.to_string(),
+            amount: Uint128::from(pay_in_amount),
+            msg: to_binary(&ExecuteBankMsg::PayIn {
+                sender: env.contract.address,
+                escrowed_amount: pay_in_amount,
+                escrowed_asset: token.to_string(),
+            })?,
+        })?,
     }));
-    expected
+    Ok(expected)
 }
 
 pub fn pay_out(
@@ -55,50 +92,40 @@ pub fn pay_out(
     pay_in_denom: String,
     recipient: Addr,
     total_amount: u128,
-) -> SubMsg {
+) -> StdResult<SubMsg> {
     let expected = SubMsg::new(CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: bank_address.to_string(),
-        funds: vec![Coin {
-            denom: pay_in_denom.clone(),
-            amount: Uint128::from(pay_in_amount),
-        }],
+        funds: escrow_funds(pay_in_amount, pay_in_denom.clone()),
         msg: to_binary(&ExecuteBankMsg::PayOut {
             sender: env.contract.address,
             escrowed_amount: pay_in_amount,
             escrowed_asset: pay_in_denom,
             recipient: recipient,
             total_amount: total_amount,
-        })
-        .unwrap(),
+        })?,
     }));
-    expected
+    Ok(expected)
 }
 
 ///// add to response of tx like Response.new().add_submessage(submsg)
 ///
 ///Get Contract query response
-pub fn get_max_wager(deps: Deps, bank_address: Addr, asset: String) -> Uint128 {
+pub fn get_max_wager(deps: Deps, bank_address: Addr, asset: String) -> StdResult<Uint128> {
     let query_msg = QueryBankMsg::GetMaxWager { asset: asset };
-    let query_response: Uint128 = deps
-        .querier
-        .query(&QueryRequest::Wasm(WasmQuery::Smart {
-            contract_addr: bank_address.to_string(),
-            msg: to_binary(&query_msg).unwrap(),
-        }))
-        .unwrap();
+    let query_response: Uint128 = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: bank_address.to_string(),
+        msg: to_binary(&query_msg)?,
+    }))?;
 
-    query_response
+    Ok(query_response)
 }
 
-pub fn is_asset_whitelisted(deps: Deps, bank_address: Addr, asset: String) -> bool {
+pub fn is_asset_whitelisted(deps: Deps, bank_address: Addr, asset: String) -> StdResult<bool> {
     let query_msg = QueryBankMsg::IsAssetWhitelisted { asset: asset };
-    let query_response: bool = deps
-        .querier
-        .query(&QueryRequest::Wasm(WasmQuery::Smart {
-            contract_addr: bank_address.to_string(),
-            msg: to_binary(&query_msg).unwrap(),
-        }))
-        .unwrap();
+    let query_response: bool = deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: bank_address.to_string(),
+        msg: to_binary(&query_msg)?,
+    }))?;
 
-    query_response
+    Ok(query_response)
 }
@@ -1,33 +1,57 @@
 use std::str::FromStr;
 
-use crate::bank::pay_out;
+use crate::bank::is_asset_whitelisted;
+use crate::custody::load_custody;
 //use crate::events::{DecreasePositionEvent, DecreaseReservedAmount};
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, Addr, Api, Binary, Deps, DepsMut, Env, Event, Int128, MessageInfo, Response, StdResult,
-    SubMsg, Uint128,
+    from_binary, to_binary, Addr, Api, Binary, Deps, DepsMut, Env, Event, MessageInfo, Reply,
+    Response, StdResult, SubMsg, SubMsgResult, Uint128, Uint256,
 };
-use cw2::set_contract_version;
+use cw2::{get_contract_version, set_contract_version};
+use cw20::Cw20ReceiveMsg;
 
 use crate::error::ContractError;
 use crate::helpers::{
-    _collect_margin_fees, _decreaseGlobalShortSize, _decreaseGuaranteedUsd, _decreasePoolAmount,
-    _decreaseReservedAmount, _decreaseUsdgAmount, _increaseGuaranteedUsd, _increasePoolAmount,
-    _increaseReservedAmount, _increaseUsdgAmount, _validateTokens, balance_cw20_tokens,
-    getBuyUsdgFeeBasisPoints, getSellUsdgFeeBasisPoints, getSwapFeeBasisPoints, get_delta,
-    get_entry_funding_rate, get_max_price, get_min_price, get_next_average_price,
-    get_next_global_short_average_price, token_to_usd_min, transfer_cw20_tokens,
-    updateCumulativeFundingRate, update_token_bal, usdToTokenMax, usd_to_token_min,
-    validLiquidation, validate,
+    _collect_margin_fees, _decreaseGlobalLongSize, _decreaseGlobalShortSize,
+    _decreaseGuaranteedUsd, _decreasePoolAmount, _decreaseReservedAmount, _decreaseUsdgAmount,
+    _increaseGlobalLongSize, _increaseGlobalShortSize, _increaseGuaranteedUsd, _increasePoolAmount,
+    _increaseReservedAmount, _increaseUsdgAmount, balance_cw20_tokens, burn_cw20_tokens,
+    cw20_balance_of, decimals_cw20_tokens, mint_cw20_tokens, only_role, total_supply_cw20_tokens,
+    transfer_cw20_tokens, transfer_in, updateCumulativeFundingRate, validate, validate_manager,
+    when_not_paused,
+};
+use crate::math::{mul_div, Rounding};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, ReceiveMsg};
+use crate::pricing::{
+    apply_fee, getNextFundingRate, get_lp_aum_in_usdg, get_next_global_long_average_price,
+    get_next_global_short_average_price, get_token_price, token_to_usd, usd_to_token_min,
+    validLiquidation,
+};
+use crate::query::{
+    check_whitelisted_token, get_position, get_position_key, has_role, query_aum,
+    query_balance_drift, query_check_invariants, query_config, query_fee_recipient_info,
+    query_fee_recipients, query_global_short_pnl, query_liquidation_price, query_liquidator_stats,
+    query_liquidators, query_liquidity_info, query_manager, query_managers, query_max_leverage,
+    query_open_interest, query_pause_status, query_pending_fees, query_position_delta,
+    query_role_members, query_simulate_buy_usdg, query_simulate_decrease_position,
+    query_simulate_increase_position, query_simulate_sell_usdg, query_simulate_swap,
+    query_usdg_supply,
+};
+use crate::simulate::{
+    quote_buy_usdg, quote_decrease_position, quote_increase_position, quote_sell_usdg, quote_swap,
+    ReduceCollateralQuote,
 };
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::query::{check_whitelisted_token, get_position, get_position_key, query_config};
 use crate::state::{
-    Config, State, ADMIN, BUFFERAMOUNT, CONFIG, CUMULATIVEFUNDINGRATE, FEERESERVED,
-    GLOBALSHORTAVERAGEPRICE, GLOBALSHORTSIZE, ISLIQUIDATOR, ISMANAGER, LASTFUNDINTIME,
-    MAXGLOBALSHORTSIZE, MAXUSDGAMOUNT, MINPROFITBASISPOINT, POOLAMOUNT, POSITION, RESERVEDAMOUNTS,
-    SHORTABLETOKEN, STABLETOKEN, STATE, TOKENDECIMAL, TOKENWEIGHT, USDGAMOUNT, WHITELISTEDTOKEN,
+    Config, CustodyMode, FeeRecipient, LiquidityConfig, PauseInfo, PauseTarget, PendingPayout,
+    Role, State, TokenBurn, ADMIN, BANKDEPOSITS, BUFFERAMOUNT, CONFIG, CUMULATIVEFUNDINGRATE,
+    FEERECIPIENTS, FEERECIPIENTSTATS, FEERESERVED, FEESDISTRIBUTED, FEETOKEN,
+    GLOBALLONGAVERAGEPRICE, GLOBALSHORTAVERAGEPRICE, GLOBALSHORTSIZE, GUARANTEEUSD, ISLIQUIDATOR,
+    ISMANAGER, LASTADDEDAT, LASTFUNDINTIME, LIQUIDATORSTATS, LIQUIDITYCONFIG, MAXGLOBALLONGSIZE,
+    MAXGLOBALSHORTSIZE, MAXUSDGAMOUNT, MINPROFITBASISPOINT, NEXTPAYOUTID, PAUSED, PENDINGPAYOUTS,
+    POOLAMOUNT, POSITION, RESERVEDAMOUNTS, ROLES, SHORTABLETOKEN, STABLETOKEN, STATE, TOKENBALANCE,
+    TOKENDECIMAL, TOKENMAXLEVERAGE, TOKENWEIGHT, UNCLEAREDUSDGDEBT, USDGAMOUNT, WHITELISTEDTOKEN,
 };
 
 // version info for migration info
@@ -36,14 +60,15 @@ const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 const UNINITIALIZED_ADDRESS: &str = "UNINITIALIZED";
 
 pub const BASIS_POINTS_DIVISOR: Uint128 = Uint128::new(10000);
-const FUNDING_RATE_PRECISION: Uint128 = Uint128::new(1000000);
-const PRICE_PRECISION: Uint128 = Uint128::new(10u128.pow(30));
+pub const FUNDING_RATE_PRECISION: Uint128 = Uint128::new(1000000);
+pub const PRICE_PRECISION: Uint128 = Uint128::new(10u128.pow(30));
 const MIN_LEVERAGE: Uint128 = Uint128::new(10000);
-const USDG_DECIMALS: Uint128 = Uint128::new(6);
+pub const USDG_DECIMALS: Uint128 = Uint128::new(6);
 const MAX_FEE_BASIS_POINTS: Uint128 = Uint128::new(500);
 const MAX_LIQUIDATION_FEE_USD: Uint128 = Uint128::new(100);
 const MIN_FUNDING_RATE_INTERVAL: u128 = 1 * 60 * 60; // 1 hour in seconds
 const MAX_FUNDING_RATE_FACTOR: u128 = 10000;
+const MAX_COOLDOWN_DURATION: u64 = 48 * 60 * 60; // 48 hours in seconds
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -56,12 +81,11 @@ pub fn instantiate(
 
     let state = State {
         bank_addr: _msg._bankAddr,
+        custody: _msg._custody,
     };
 
     STATE.save(_deps.storage, &state)?;
 
-    ADMIN.set(_deps, Some(_info.clone().sender))?;
-
     let _config = Config {
         is_initialized: true,
         is_swap_enabled: true,
@@ -71,7 +95,7 @@ pub fn instantiate(
         router: _msg._router,
         price_feed: _msg._priceFeed,
         usdg: _msg._usdg,
-        gov: _info.sender,
+        gov: _info.sender.clone(),
         whitelisted_token_count: Default::default(),
         all_whitelisted_tokens: Vec::new(),
         max_leverage: Uint128::new(50 * 10000 as u128),
@@ -95,17 +119,51 @@ pub fn instantiate(
         max_gas_price: Default::default(),
     };
 
+    CONFIG.save(_deps.storage, &_config)?;
+
+    ADMIN.set(_deps, Some(_info.clone().sender))?;
+
     Ok(Response::default())
 }
 
+/// USD totals summed across positions moved from Uint128 to Uint256. Both
+/// serialise to the same decimal string, so stored values load as they are
+/// and only the contract version needs updating.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(_deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(_deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::CannotMigrate {
+            contract: stored.contract,
+        });
+    }
+
+    set_contract_version(_deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    // swap pricing is now chosen per call; earlier versions left it switched
+    // on in CONFIG after the first swap
+    CONFIG.update(_deps.storage, |mut config| -> StdResult<_> {
+        config.use_swap_pricing = false;
+        Ok(config)
+    })?;
+
+    // earlier versions left the last sale's payout snapshot behind
+    _deps.storage.remove(b"pending-payout");
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("to_version", CONTRACT_VERSION))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     _deps: DepsMut,
-    _api: &dyn Api,
     _env: Env,
     _info: MessageInfo,
     _msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
+    let _api = _deps.api;
     match _msg {
         ExecuteMsg::SetVaultUtils { _utilisAddr } => set_vault_utils(_deps, _info, _utilisAddr),
         ExecuteMsg::SetInManagerMode { Inmanagermode } => {
@@ -135,12 +193,19 @@ pub fn execute(
         ExecuteMsg::SetMaxLeverage { _maxLeverage } => {
             set_max_leverage(_deps, _api, _info, _maxLeverage)
         }
+        ExecuteMsg::SetTokenMaxLeverage {
+            _token,
+            _maxLeverage,
+        } => set_token_max_leverage(_deps, _api, _info, _token, _maxLeverage),
         ExecuteMsg::SetBufferAmount { _token, _amount } => {
             set_buffer_amount(_deps, _api, _info, _token, _amount)
         }
         ExecuteMsg::SetMaxGlobalShortSize { _token, _amount } => {
             set_max_global_shortSize(_deps, _api, _info, _token, _amount)
         }
+        ExecuteMsg::SetMaxGlobalLongSize { _token, _amount } => {
+            set_max_global_long_size(_deps, _api, _info, _token, _amount)
+        }
         ExecuteMsg::SetFess {
             _taxBasisPoints,
             _stableTaxBasisPoints,
@@ -211,10 +276,15 @@ pub fn execute(
             _amount,
         } => upgrade(_deps, _env, _info, _newVault, _token, _amount),
         ExecuteMsg::DirectPoolDeposit { _token } => directPoolDeposit(_deps, _env, _info, _token),
+        ExecuteMsg::Sync { _token } => sync(_deps, _env, _info, _token),
+        ExecuteMsg::Skim { _token, _receiver } => skim(_deps, _env, _info, _token, _receiver),
         ExecuteMsg::BuyUSDG { _token, _receiver } => buyUSDG(_deps, _env, _info, _token, _receiver),
-        ExecuteMsg::SellUSDG { _token, _receiver } => {
-            sellUSDG(_deps, _env, _info, _token, _receiver)
-        }
+        ExecuteMsg::SellUSDG {
+            _token,
+            _usdgAmount,
+            _receiver,
+        } => sellUSDG(_deps, _env, _info, _token, _usdgAmount, _receiver),
+        ExecuteMsg::Receive(cw20_msg) => receive_cw20(_deps, _env, _info, cw20_msg),
         ExecuteMsg::UpdateCumulativeFundingRate {
             _collateralToken,
             _indexToken,
@@ -267,7 +337,7 @@ pub fn execute(
             _collateralDelta,
             _sizeDelta,
             _isLong,
-        } => _reduceCollateral(
+        } => reduceCollateral(
             _deps,
             _env,
             _info,
@@ -294,12 +364,323 @@ pub fn execute(
             _isLong,
             _receiver,
         ),
+        ExecuteMsg::GrantRole { role, account } => grant_role(_deps, _api, _info, role, account),
+        ExecuteMsg::RevokeRole { role, account } => revoke_role(_deps, _api, _info, role, account),
+        ExecuteMsg::Pause { targets, reason } => pause(_deps, _env, _info, targets, reason),
+        ExecuteMsg::Unpause { targets } => unpause(_deps, _env, _info, targets),
+        ExecuteMsg::SetFeeRecipients {
+            recipients,
+            fee_token,
+        } => set_fee_recipients(_deps, _info, recipients, fee_token),
+        ExecuteMsg::DistributeFees { tokens } => distribute_fees(_deps, _env, _info, tokens),
+        ExecuteMsg::SetLiquidityConfig {
+            lp_token,
+            cooldown_duration,
+        } => set_liquidity_config(_deps, _api, _info, lp_token, cooldown_duration),
+        ExecuteMsg::AddLiquidity {
+            token,
+            min_usdg,
+            min_lp,
+        } => add_liquidity(_deps, _env, _info, token, min_usdg, min_lp),
+        ExecuteMsg::RemoveLiquidity {
+            token_out,
+            lp_amount,
+            min_out,
+            receiver,
+        } => remove_liquidity(_deps, _env, _info, token_out, lp_amount, min_out, receiver),
     }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(_deps: Deps, _env: Env, _msg: QueryMsg) -> StdResult<Binary> {
-    unimplemented!()
+    match _msg {
+        QueryMsg::HasRole { role, account } => to_binary(&has_role(_deps, role, account)?),
+        QueryMsg::RoleMembers {
+            role,
+            start_after,
+            limit,
+        } => to_binary(&query_role_members(_deps, role, start_after, limit)?),
+        QueryMsg::PauseStatus {} => to_binary(&query_pause_status(_deps)?),
+        QueryMsg::IsManager { address } => to_binary(&query_manager(_deps, address)?),
+        QueryMsg::Managers { start_after, limit } => {
+            to_binary(&query_managers(_deps, start_after, limit)?)
+        }
+        QueryMsg::Liquidators { start_after, limit } => {
+            to_binary(&query_liquidators(_deps, start_after, limit)?)
+        }
+        QueryMsg::GlobalShortPnl { token } => to_binary(&query_global_short_pnl(_deps, token)?),
+        QueryMsg::Aum { maximise } => to_binary(&query_aum(_deps, maximise)?),
+        QueryMsg::OpenInterest { token } => to_binary(&query_open_interest(_deps, token)?),
+        QueryMsg::MaxLeverage { token } => to_binary(&query_max_leverage(_deps, token)?),
+        QueryMsg::LiquidatorStats { address } => {
+            to_binary(&query_liquidator_stats(_deps, address)?)
+        }
+        QueryMsg::LiquidityInfo { account } => {
+            to_binary(&query_liquidity_info(_deps, _env, account)?)
+        }
+        QueryMsg::FeeRecipients {} => to_binary(&query_fee_recipients(_deps)?),
+        QueryMsg::FeeRecipientInfo { recipient } => {
+            to_binary(&query_fee_recipient_info(_deps, recipient)?)
+        }
+        QueryMsg::PendingFees {} => to_binary(&query_pending_fees(_deps)?),
+        QueryMsg::BalanceDrift { tokens } => to_binary(&query_balance_drift(_deps, _env, tokens)?),
+        QueryMsg::UsdgSupply {} => to_binary(&query_usdg_supply(_deps, _env)?),
+        QueryMsg::CheckInvariants { tokens } => {
+            to_binary(&query_check_invariants(_deps, _env, tokens)?)
+        }
+        QueryMsg::SimulateSwap {
+            token_in,
+            token_out,
+            amount_in,
+        } => to_binary(&query_simulate_swap(_deps, token_in, token_out, amount_in)?),
+        QueryMsg::SimulateBuyUsdg {
+            token,
+            token_amount,
+        } => to_binary(&query_simulate_buy_usdg(_deps, token, token_amount)?),
+        QueryMsg::SimulateSellUsdg { token, usdg_amount } => {
+            to_binary(&query_simulate_sell_usdg(_deps, token, usdg_amount)?)
+        }
+        QueryMsg::SimulateIncreasePosition {
+            account,
+            collateral_token,
+            index_token,
+            collateral_amount,
+            size_delta,
+            is_long,
+        } => to_binary(&query_simulate_increase_position(
+            _deps,
+            _env,
+            account,
+            collateral_token,
+            index_token,
+            collateral_amount,
+            size_delta,
+            is_long,
+        )?),
+        QueryMsg::SimulateDecreasePosition {
+            account,
+            collateral_token,
+            index_token,
+            collateral_delta,
+            size_delta,
+            is_long,
+        } => to_binary(&query_simulate_decrease_position(
+            _deps,
+            _env,
+            account,
+            collateral_token,
+            index_token,
+            collateral_delta,
+            size_delta,
+            is_long,
+        )?),
+        QueryMsg::LiquidationPrice {
+            account,
+            collateral_token,
+            index_token,
+            is_long,
+        } => to_binary(&query_liquidation_price(
+            _deps,
+            _env,
+            account,
+            collateral_token,
+            index_token,
+            is_long,
+        )?),
+        QueryMsg::PositionDelta {
+            account,
+            collateral_token,
+            index_token,
+            is_long,
+        } => to_binary(&query_position_delta(
+            _deps,
+            _env,
+            account,
+            collateral_token,
+            index_token,
+            is_long,
+        )?),
+    }
+}
+
+/// Settles a USDG sale's payout. Other payouts are plain submessages, so a
+/// failure there reverts the whole transaction instead.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(_deps: DepsMut, _env: Env, _msg: Reply) -> Result<Response, ContractError> {
+    let id = _msg.id;
+    let pending = PENDINGPAYOUTS
+        .may_load(_deps.storage, id)?
+        .ok_or(ContractError::UnknownReplyId { id })?;
+    PENDINGPAYOUTS.remove(_deps.storage, id);
+
+    match _msg.result {
+        SubMsgResult::Ok(_) => Ok(Response::new()),
+        SubMsgResult::Err(error) => rollback_payout(_deps, _env, pending, error),
+    }
+}
+
+/// Undoes the accounting a failed payout had already applied and mints back
+/// whatever was burnt for it.
+fn rollback_payout(
+    _deps: DepsMut,
+    _env: Env,
+    pending: PendingPayout,
+    error: String,
+) -> Result<Response, ContractError> {
+    let token = pending.token.clone();
+    POOLAMOUNT.update(_deps.storage, token.clone(), |amount| -> StdResult<_> {
+        Ok(amount
+            .unwrap_or_default()
+            .checked_add(pending.pool_decrease)?)
+    })?;
+    USDGAMOUNT.update(_deps.storage, token.clone(), |amount| -> StdResult<_> {
+        Ok(amount
+            .unwrap_or_default()
+            .checked_add(pending.usdg_decrease)?)
+    })?;
+    FEERESERVED.update(_deps.storage, token.clone(), |amount| -> StdResult<_> {
+        Ok(amount
+            .unwrap_or_default()
+            .checked_sub(pending.fee_reserved_increase)?)
+    })?;
+    TOKENBALANCE.update(_deps.storage, token.clone(), |amount| -> StdResult<_> {
+        Ok(amount
+            .unwrap_or_default()
+            .checked_add(pending.token_balance_decrease)?)
+    })?;
+    if !pending.bank_deposit_decrease.is_zero() {
+        BANKDEPOSITS.update(_deps.storage, token, |amount| -> StdResult<_> {
+            Ok(amount
+                .unwrap_or_default()
+                .checked_add(pending.bank_deposit_decrease)?)
+        })?;
+    }
+    if !pending.uncleared_usdg_debt_increase.is_zero() {
+        let uncleared = UNCLEAREDUSDGDEBT
+            .load(_deps.storage)?
+            .checked_sub(pending.uncleared_usdg_debt_increase)?;
+        UNCLEAREDUSDGDEBT.save(_deps.storage, &uncleared)?;
+    }
+
+    let mut res = Response::new();
+    for burn in pending.burns {
+        let recipient = burn.owner.unwrap_or(_env.contract.address.clone());
+        res = res.add_message(mint_cw20_tokens(burn.token, recipient, burn.amount)?);
+    }
+
+    let event = Event::new("PayoutFailed")
+        .add_attribute("token", pending.token.as_str())
+        .add_attribute("receiver", pending.receiver.as_str())
+        .add_attribute("amount", pending.amount.to_string())
+        .add_attribute("error", error);
+
+    Ok(res.add_event(event))
+}
+
+pub fn grant_role(
+    _deps: DepsMut,
+    _api: &dyn Api,
+    _info: MessageInfo,
+    role: Role,
+    account: String,
+) -> Result<Response, ContractError> {
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
+    let account = _api.addr_validate(&account)?;
+
+    match role {
+        Role::Gov => {
+            return Err(ContractError::RoleNotAssignable {
+                role: role.as_str().to_string(),
+            })
+        }
+        Role::Manager => ISMANAGER.save(_deps.storage, account.clone(), &true)?,
+        Role::Liquidator => ISLIQUIDATOR.save(_deps.storage, account.clone(), &true)?,
+        _ => ROLES.save(
+            _deps.storage,
+            (role.as_str().to_string(), account.clone()),
+            &true,
+        )?,
+    }
+
+    let event = Event::new("GrantRole")
+        .add_attribute("role", role.as_str())
+        .add_attribute("account", account.as_str());
+
+    Ok(Response::new().add_event(event))
+}
+
+pub fn revoke_role(
+    _deps: DepsMut,
+    _api: &dyn Api,
+    _info: MessageInfo,
+    role: Role,
+    account: String,
+) -> Result<Response, ContractError> {
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
+    let account = _api.addr_validate(&account)?;
+
+    match role {
+        Role::Gov => {
+            return Err(ContractError::RoleNotAssignable {
+                role: role.as_str().to_string(),
+            })
+        }
+        Role::Manager => ISMANAGER.remove(_deps.storage, account.clone()),
+        Role::Liquidator => ISLIQUIDATOR.remove(_deps.storage, account.clone()),
+        _ => ROLES.remove(_deps.storage, (role.as_str().to_string(), account.clone())),
+    }
+
+    let event = Event::new("RevokeRole")
+        .add_attribute("role", role.as_str())
+        .add_attribute("account", account.as_str());
+
+    Ok(Response::new().add_event(event))
+}
+
+pub fn pause(
+    _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    targets: Option<Vec<PauseTarget>>,
+    reason: String,
+) -> Result<Response, ContractError> {
+    only_role(_deps.as_ref(), &_info.sender, Role::Pauser)?;
+
+    let targets = targets.unwrap_or_else(PauseTarget::all);
+    let pause_info = PauseInfo {
+        reason: reason.clone(),
+        paused_by: _info.sender.clone(),
+        paused_at: _env.block.time.seconds(),
+    };
+
+    let mut event = Event::new("Pause")
+        .add_attribute("paused_by", _info.sender.as_str())
+        .add_attribute("reason", reason);
+    for target in targets {
+        PAUSED.save(_deps.storage, target.as_str().to_string(), &pause_info)?;
+        event = event.add_attribute("target", target.as_str());
+    }
+
+    Ok(Response::new().add_event(event))
+}
+
+pub fn unpause(
+    _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    targets: Option<Vec<PauseTarget>>,
+) -> Result<Response, ContractError> {
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
+
+    let targets = targets.unwrap_or_else(PauseTarget::all);
+
+    let mut event = Event::new("Unpause").add_attribute("unpaused_by", _info.sender.as_str());
+    for target in targets {
+        PAUSED.remove(_deps.storage, target.as_str().to_string());
+        event = event.add_attribute("target", target.as_str());
+    }
+
+    Ok(Response::new().add_event(event))
 }
 
 pub fn set_vault_utils(
@@ -307,7 +688,7 @@ pub fn set_vault_utils(
     _info: MessageInfo,
     _utilisAddr: Addr,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
 
     let mut config = query_config(_deps.as_ref())?;
     config.vault_utils = _utilisAddr;
@@ -322,10 +703,10 @@ pub fn set_in_managerMode(
     _info: MessageInfo,
     _Inmanagermode: bool,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
 
     let mut config = query_config(_deps.as_ref())?;
-    config.in_manager_mode = true;
+    config.in_manager_mode = _Inmanagermode;
 
     CONFIG.save(_deps.storage, &config)?;
 
@@ -339,7 +720,7 @@ pub fn set_manager(
     ismanager: bool,
     address: Addr,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
 
     ISMANAGER.save(_deps.storage, address, &ismanager)?;
 
@@ -352,10 +733,10 @@ pub fn set_in_private_liqMode(
     _info: MessageInfo,
     _InPrivateLiqMode: bool,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
 
     let mut config = query_config(_deps.as_ref())?;
-    config.in_private_liquidation_mode = true;
+    config.in_private_liquidation_mode = _InPrivateLiqMode;
 
     CONFIG.save(_deps.storage, &config)?;
 
@@ -368,7 +749,7 @@ pub fn set_liquidator(
     is_active: bool,
     liquidator: Addr,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
 
     ISLIQUIDATOR.save(_deps.storage, liquidator, &is_active)?;
 
@@ -381,10 +762,10 @@ pub fn set_is_swap_enabled(
     _info: MessageInfo,
     _is_swap_enable: bool,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::RiskAdmin)?;
 
     let mut config = query_config(_deps.as_ref())?;
-    config.is_swap_enabled = true;
+    config.is_swap_enabled = _is_swap_enable;
 
     CONFIG.save(_deps.storage, &config)?;
 
@@ -397,10 +778,10 @@ pub fn set_is_leverage_enabled(
     _info: MessageInfo,
     _is_Leverage_enable: bool,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::RiskAdmin)?;
 
     let mut config = query_config(_deps.as_ref())?;
-    config.is_leverage_enabled = true;
+    config.is_leverage_enabled = _is_Leverage_enable;
 
     CONFIG.save(_deps.storage, &config)?;
 
@@ -413,7 +794,7 @@ pub fn set_max_gas_price(
     _info: MessageInfo,
     _max_gas_price: Uint128,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::RiskAdmin)?;
 
     let mut config = query_config(_deps.as_ref())?;
     config.max_gas_price = _max_gas_price;
@@ -429,7 +810,7 @@ pub fn set_gov(
     _info: MessageInfo,
     gov: Addr,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(deps.as_ref(), &_info.sender)?;
+    only_role(deps.as_ref(), &_info.sender, Role::Gov)?;
     let _admin_storage = ADMIN.get(deps.as_ref())?;
 
     let mut config = query_config(deps.as_ref())?;
@@ -447,7 +828,7 @@ pub fn set_price_feed(
     _info: MessageInfo,
     _price_feed: Addr,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
 
     let mut config = query_config(_deps.as_ref())?;
     config.price_feed = _price_feed;
@@ -463,16 +844,38 @@ pub fn set_max_leverage(
     _info: MessageInfo,
     _maxLeverage: Uint128,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::RiskAdmin)?;
+
+    validate(_maxLeverage > MIN_LEVERAGE, "ERR_MAX_LEVERAGE_TOO_LOW")?;
 
     let mut config = query_config(_deps.as_ref())?;
-    config.max_gas_price = _maxLeverage;
+    config.max_leverage = _maxLeverage;
 
     CONFIG.save(_deps.storage, &config)?;
 
     Ok(Response::new())
 }
 
+pub fn set_token_max_leverage(
+    _deps: DepsMut,
+    _api: &dyn Api,
+    _info: MessageInfo,
+    _token: Addr,
+    _maxLeverage: Option<Uint128>,
+) -> Result<Response, ContractError> {
+    only_role(_deps.as_ref(), &_info.sender, Role::RiskAdmin)?;
+
+    match _maxLeverage {
+        Some(max_leverage) => {
+            validate(max_leverage > MIN_LEVERAGE, "ERR_MAX_LEVERAGE_TOO_LOW")?;
+            TOKENMAXLEVERAGE.save(_deps.storage, _token, &max_leverage)?;
+        }
+        None => TOKENMAXLEVERAGE.remove(_deps.storage, _token),
+    }
+
+    Ok(Response::new())
+}
+
 pub fn set_buffer_amount(
     _deps: DepsMut,
     _api: &dyn Api,
@@ -480,7 +883,7 @@ pub fn set_buffer_amount(
     _token: Addr,
     _amount: Uint128,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::RiskAdmin)?;
 
     BUFFERAMOUNT.save(_deps.storage, _token, &_amount)?;
 
@@ -492,15 +895,29 @@ pub fn set_max_global_shortSize(
     _api: &dyn Api,
     _info: MessageInfo,
     _token: Addr,
-    _amount: Uint128,
+    _amount: Uint256,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::RiskAdmin)?;
 
     MAXGLOBALSHORTSIZE.save(_deps.storage, _token, &_amount)?;
 
     Ok(Response::new())
 }
 
+pub fn set_max_global_long_size(
+    _deps: DepsMut,
+    _api: &dyn Api,
+    _info: MessageInfo,
+    _token: Addr,
+    _amount: Uint256,
+) -> Result<Response, ContractError> {
+    only_role(_deps.as_ref(), &_info.sender, Role::RiskAdmin)?;
+
+    MAXGLOBALLONGSIZE.save(_deps.storage, _token, &_amount)?;
+
+    Ok(Response::new())
+}
+
 pub fn set_fess(
     _deps: DepsMut,
     _api: &dyn Api,
@@ -515,7 +932,7 @@ pub fn set_fess(
     _minProfitTime: Uint128,
     _hasDynamicFees: bool,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::RiskAdmin)?;
 
     validate(_taxBasisPoints <= MAX_FEE_BASIS_POINTS, "err")?;
     validate(_stableTaxBasisPoints <= MAX_FEE_BASIS_POINTS, "err")?;
@@ -550,7 +967,7 @@ pub fn set_funding_rate(
     _fundingRateFactor: u128,
     _stableFundingRateFactor: u128,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::RiskAdmin)?;
 
     validate(_fundingInterval >= MIN_FUNDING_RATE_INTERVAL, "err")?;
     validate(_fundingRateFactor <= MAX_FUNDING_RATE_FACTOR, "err")?;
@@ -579,19 +996,40 @@ pub fn set_token_config(
     _isStable: bool,
     _isShortable: bool,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
     let mut config = query_config(_deps.as_ref())?;
 
+    // conversions scale by TOKENDECIMAL, so it must match the cw20
+    let decimals = decimals_cw20_tokens(_deps.as_ref(), _token.clone())?;
+    if Uint128::from(decimals) != _tokenDecimals {
+        return Err(ContractError::TokenDecimalsMismatch {
+            token: _token.to_string(),
+            decimals,
+            configured: _tokenDecimals,
+        });
+    }
+
+    let state = STATE.load(_deps.storage)?;
+    if state.custody == CustodyMode::Bank {
+        validate(
+            is_asset_whitelisted(_deps.as_ref(), state.bank_addr, _token.to_string())?,
+            "ERR_ASSET_NOT_WHITELISTED_BY_BANK",
+        )?;
+    }
+
     if !check_whitelisted_token(_deps.as_ref(), _token.clone())? {
-        config.whitelisted_token_count = config.whitelisted_token_count + Uint128::one();
+        config.whitelisted_token_count =
+            config.whitelisted_token_count.checked_add(Uint128::one())?;
 
         config.all_whitelisted_tokens.push(_token.clone());
     }
 
-    let weight = TOKENWEIGHT.load(_deps.storage, _token.clone())?;
+    let weight = TOKENWEIGHT
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
 
     let mut _totalTokenWeights: Uint128 = config.total_token_weights;
-    _totalTokenWeights = _totalTokenWeights - weight;
+    _totalTokenWeights = _totalTokenWeights.checked_sub(weight)?;
 
     WHITELISTEDTOKEN.save(_deps.storage, _token.clone(), &true)?;
     TOKENDECIMAL.save(_deps.storage, _token.clone(), &_tokenDecimals)?;
@@ -601,7 +1039,28 @@ pub fn set_token_config(
     STABLETOKEN.save(_deps.storage, _token.clone(), &_isStable)?;
     SHORTABLETOKEN.save(_deps.storage, _token.clone(), &_isShortable)?;
 
-    config.total_token_weights = _totalTokenWeights + _tokenWeight;
+    // accounting for a token starts at zero, kept as-is if it was listed before
+    for amounts in [
+        POOLAMOUNT,
+        RESERVEDAMOUNTS,
+        USDGAMOUNT,
+        FEERESERVED,
+        TOKENBALANCE,
+        BUFFERAMOUNT,
+        CUMULATIVEFUNDINGRATE,
+    ] {
+        if !amounts.has(_deps.storage, _token.clone()) {
+            amounts.save(_deps.storage, _token.clone(), &Uint128::zero())?;
+        }
+    }
+    if !GUARANTEEUSD.has(_deps.storage, _token.clone()) {
+        GUARANTEEUSD.save(_deps.storage, _token.clone(), &Uint256::zero())?;
+    }
+    if !LASTFUNDINTIME.has(_deps.storage, _token.clone()) {
+        LASTFUNDINTIME.save(_deps.storage, _token.clone(), &0)?;
+    }
+
+    config.total_token_weights = _totalTokenWeights.checked_add(_tokenWeight)?;
 
     CONFIG.save(_deps.storage, &config)?;
 
@@ -614,13 +1073,14 @@ pub fn clearTokenConfig(
     _info: MessageInfo,
     _token: Addr,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
     let mut config = query_config(_deps.as_ref())?;
 
     if check_whitelisted_token(_deps.as_ref(), _token.clone())? {
-        config.whitelisted_token_count = config.whitelisted_token_count - Uint128::one();
+        config.whitelisted_token_count =
+            config.whitelisted_token_count.checked_sub(Uint128::one())?;
         let weight = TOKENWEIGHT.load(_deps.storage, _token.clone())?;
-        config.total_token_weights = config.total_token_weights - weight;
+        config.total_token_weights = config.total_token_weights.checked_sub(weight)?;
 
         CONFIG.save(_deps.storage, &config)?;
     } else {
@@ -645,7 +1105,7 @@ pub fn withdrawFees(
     _token: Addr,
     _receiver: Addr,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::FeeKeeper)?;
 
     let amount = FEERESERVED.load(_deps.storage, _token.clone())?;
     if amount == Uint128::zero() {
@@ -654,20 +1114,234 @@ pub fn withdrawFees(
 
     FEERESERVED.save(_deps.storage, _token.clone(), &Uint128::zero())?;
 
-    let state = STATE.load(_deps.storage)?;
+    let custody = load_custody(_deps.storage)?;
+    let msg = custody.pay_out(_deps.storage, &_env, &_token, &_receiver, amount)?;
 
-    let mut res = Response::new();
+    Ok(Response::new()
+        .add_submessage(msg)
+        .add_attribute("action", "withdraw_fees")
+        .add_attribute("token", _token)
+        .add_attribute("receiver", _receiver)
+        .add_attribute("amount", amount))
+}
+
+pub fn set_fee_recipients(
+    _deps: DepsMut,
+    _info: MessageInfo,
+    recipients: Vec<FeeRecipient>,
+    fee_token: Option<Addr>,
+) -> Result<Response, ContractError> {
+    only_role(_deps.as_ref(), &_info.sender, Role::FeeKeeper)?;
+    validate(!recipients.is_empty(), "ERR_NO_FEE_RECIPIENTS")?;
+
+    let mut total_weight = Uint128::zero();
+    for (i, recipient) in recipients.iter().enumerate() {
+        validate(!recipient.weight.is_zero(), "ERR_INVALID_FEE_WEIGHT")?;
+        validate(
+            !recipients[..i]
+                .iter()
+                .any(|r| r.address == recipient.address),
+            "ERR_DUPLICATE_FEE_RECIPIENT",
+        )?;
+        total_weight += recipient.weight;
+    }
+    validate(
+        total_weight == BASIS_POINTS_DIVISOR,
+        "ERR_FEE_WEIGHTS_MUST_SUM_TO_10000",
+    )?;
+
+    FEERECIPIENTS.save(_deps.storage, &recipients)?;
+    match &fee_token {
+        Some(token) => {
+            validate(
+                check_whitelisted_token(_deps.as_ref(), token.clone())?,
+                "ERR_TOKEN_NOT_WHITELISTED",
+            )?;
+            FEETOKEN.save(_deps.storage, token)?;
+        }
+        None => FEETOKEN.remove(_deps.storage),
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "set_fee_recipients")
+        .add_attribute("recipients", recipients.len().to_string())
+        .add_attribute(
+            "fee_token",
+            fee_token.map_or(String::new(), |token| token.to_string()),
+        ))
+}
+
+pub fn distribute_fees(
+    mut _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    tokens: Option<Vec<Addr>>,
+) -> Result<Response, ContractError> {
+    let recipients = FEERECIPIENTS
+        .may_load(_deps.storage)?
+        .ok_or(ContractError::FeeRecipientsNotSet {})?;
+    let config = CONFIG.load(_deps.storage)?;
+    let tokens = tokens.unwrap_or(config.all_whitelisted_tokens);
+
+    // conversions swap at the feed's price with no minimum out, so only a fee
+    // keeper triggers them, and never while swaps are disabled or paused
+    let converts = config.is_swap_enabled
+        && when_not_paused(_deps.as_ref(), PauseTarget::Swap).is_ok()
+        && only_role(_deps.as_ref(), &_info.sender, Role::FeeKeeper).is_ok();
+    let fee_token = FEETOKEN.may_load(_deps.storage)?.filter(|_| converts);
+
+    let custody = load_custody(_deps.storage)?;
+    let mut res = Response::new().add_attribute("action", "distribute_fees");
+
+    // reserves in the fee token, or every reserve when none is converted, are
+    // split as they are; the others are converted into the fee token first
+    let mut reserves: Vec<(Addr, Uint128)> = vec![];
+    let mut converted = Uint128::zero();
+    for token in tokens {
+        let amount = FEERESERVED
+            .may_load(_deps.storage, token.clone())?
+            .unwrap_or_default();
+        if amount.is_zero() {
+            continue;
+        }
+        FEERESERVED.save(_deps.storage, token.clone(), &Uint128::zero())?;
+
+        match &fee_token {
+            Some(fee_token) if *fee_token != token => {
+                let amount_out =
+                    convert_fees(_deps.branch(), &_env, &_info, &token, fee_token, amount)?;
+                converted += amount_out;
+                res = res.add_event(
+                    Event::new("ConvertFees")
+                        .add_attribute("token", token.as_str())
+                        .add_attribute("fee_token", fee_token.as_str())
+                        .add_attribute("amount", amount.to_string())
+                        .add_attribute("amount_out", amount_out.to_string()),
+                );
+            }
+            _ => reserves.push((token, amount)),
+        }
+    }
+    if let Some(fee_token) = fee_token {
+        if !converted.is_zero() {
+            match reserves.iter_mut().find(|(token, _)| *token == fee_token) {
+                Some((_, amount)) => *amount += converted,
+                None => reserves.push((fee_token, converted)),
+            }
+        }
+    }
+
+    for (token, amount) in reserves {
+        let price = get_token_price(_deps.as_ref(), token.clone(), false)?;
+
+        // the last recipient takes the rounding dust
+        let mut remaining = amount;
+        for (i, recipient) in recipients.iter().enumerate() {
+            let share = if i == recipients.len() - 1 {
+                remaining
+            } else {
+                amount.multiply_ratio(recipient.weight, BASIS_POINTS_DIVISOR)
+            };
+            remaining -= share;
+            if share.is_zero() {
+                continue;
+            }
+
+            let share_usd = Uint128::new(token_to_usd(
+                _deps.as_ref(),
+                &token,
+                share.u128(),
+                price.u128(),
+            )?);
+            FEESDISTRIBUTED.update(
+                _deps.storage,
+                (recipient.address.clone(), token.clone()),
+                |total| -> StdResult<_> { Ok(total.unwrap_or_default() + share) },
+            )?;
+            FEERECIPIENTSTATS.update(
+                _deps.storage,
+                recipient.address.clone(),
+                |stats| -> StdResult<_> {
+                    let mut stats = stats.unwrap_or_default();
+                    stats.usd_received += share_usd;
+                    Ok(stats)
+                },
+            )?;
+
+            res = res
+                .add_submessage(custody.pay_out(
+                    _deps.storage,
+                    &_env,
+                    &token,
+                    &recipient.address,
+                    share,
+                )?)
+                .add_event(
+                    Event::new("DistributeFees")
+                        .add_attribute("token", token.as_str())
+                        .add_attribute("recipient", recipient.address.as_str())
+                        .add_attribute("amount", share.to_string())
+                        .add_attribute("usd_amount", share_usd.to_string()),
+                );
+        }
+    }
+
+    Ok(res)
+}
+
+/// Swaps a fee reserve of `_token` into `_fee_token` through the pool like
+/// `swap`, but without charging a swap fee; returns the fee tokens taken out.
+fn convert_fees(
+    mut _deps: DepsMut,
+    _env: &Env,
+    _info: &MessageInfo,
+    _token: &Addr,
+    _fee_token: &Addr,
+    _amount: Uint128,
+) -> Result<Uint128, ContractError> {
+    for token in [_token, _fee_token] {
+        _updateCumulativeFundingRate(
+            _deps.branch(),
+            _env.clone(),
+            _info.clone(),
+            token.clone(),
+            token.clone(),
+        )?;
+    }
 
-    res = res.add_submessage(pay_out(
+    let quote = quote_swap(_deps.as_ref(), _token.clone(), _fee_token.clone(), _amount)?;
+    let amount_out = quote.amount_out + quote.fee_amount;
+
+    _increaseUsdgAmount(
+        _deps.branch(),
         _env.clone(),
-        state.bank_addr.clone(),
-        0,
-        _info.funds[0].denom.clone(),
-        _info.sender.clone(),
-        amount.u128(),
-    ));
+        _info.clone(),
+        _token.clone(),
+        quote.usdg_amount,
+    )?;
+    _decreaseUsdgAmount(
+        _deps.branch(),
+        _env.clone(),
+        _info.clone(),
+        _fee_token.clone(),
+        quote.usdg_amount,
+    )?;
+    _increasePoolAmount(
+        _deps.branch(),
+        _env.clone(),
+        _info.clone(),
+        _token.clone(),
+        _amount,
+    )?;
+    _decreasePoolAmount(
+        _deps.branch(),
+        _env.clone(),
+        _info.clone(),
+        _fee_token.clone(),
+        amount_out,
+    )?;
 
-    Ok(Response::new())
+    Ok(amount_out)
 }
 
 pub fn setUsdgAmount(
@@ -677,7 +1351,7 @@ pub fn setUsdgAmount(
     _token: Addr,
     _amount: Uint128,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
 
     let usdgAmount = USDGAMOUNT.load(_deps.storage, _token.clone())?;
 
@@ -698,15 +1372,16 @@ pub fn upgrade(
     _token: Addr,
     _amount: Uint128,
 ) -> Result<Response, ContractError> {
-    ADMIN.is_admin(_deps.as_ref(), &_info.sender)?;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
 
-    let msg = transfer_cw20_tokens(_token, _env.contract.address, _newVault, _amount)?;
+    let custody = load_custody(_deps.storage)?;
+    let msg = custody.pay_out(_deps.storage, &_env, &_token, &_newVault, _amount)?;
 
-    Ok(Response::new().add_submessage(SubMsg::new(msg)))
+    Ok(Response::new().add_submessage(msg))
 }
 
 pub fn directPoolDeposit(
-    _deps: DepsMut,
+    mut _deps: DepsMut,
     _env: Env,
     _info: MessageInfo,
     _token: Addr,
@@ -714,135 +1389,263 @@ pub fn directPoolDeposit(
     let whitelistedtoken = WHITELISTEDTOKEN.load(_deps.storage, _token.clone())?;
     validate(whitelistedtoken, "err")?;
 
-    let tokenAmount: Uint128 = balance_cw20_tokens(&_deps, _env.clone(), _token.clone())?;
+    let tokenAmount: Uint128 = transfer_in(_deps.branch(), &_env, _token.clone())?;
 
     validate(tokenAmount > Uint128::zero(), "err")?;
+    let pay_in = load_custody(_deps.storage)?.pay_in(_deps.storage, &_env, &_token, tokenAmount)?;
     _increasePoolAmount(_deps, _env.clone(), _info, _token.clone(), tokenAmount)?;
 
     let event = Event::new("IncreasePoolAmount")
         .add_attribute("token", _token.as_str())
         .add_attribute("amount", tokenAmount.to_string());
 
-    Ok(Response::new().add_event(event))
+    Ok(Response::new().add_submessages(pay_in).add_event(event))
 }
 
-pub fn buyUSDG(
+/// Moves tokens sent to the vault outside of any deposit into the pool and
+/// resets TOKENBALANCE to the actual balance.
+pub fn sync(
     mut _deps: DepsMut,
     _env: Env,
     _info: MessageInfo,
     _token: Addr,
-    _receiver: Addr,
 ) -> Result<Response, ContractError> {
-    let mut config = CONFIG.load(_deps.storage)?;
-    validate(config.in_manager_mode, "err")?;
-
-    let whitelistedtoken = WHITELISTEDTOKEN.load(_deps.storage, _token.clone())?;
-    validate(whitelistedtoken, "err")?;
-
-    config.use_swap_pricing = true;
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
+    validate(
+        check_whitelisted_token(_deps.as_ref(), _token.clone())?,
+        "ERR_TOKEN_NOT_WHITELISTED",
+    )?;
 
-    CONFIG.save(_deps.storage, &config)?;
+    let cached_balance = TOKENBALANCE
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+    let donation = transfer_in(_deps.branch(), &_env, _token.clone())?;
+    let pay_in = load_custody(_deps.storage)?.pay_in(_deps.storage, &_env, &_token, donation)?;
+    if !donation.is_zero() {
+        _increasePoolAmount(
+            _deps.branch(),
+            _env.clone(),
+            _info.clone(),
+            _token.clone(),
+            donation,
+        )?;
+    }
 
-    let tokenAmount: Uint128 = balance_cw20_tokens(&_deps.branch(), _env.clone(), _token.clone())?;
-    validate(tokenAmount > Uint128::zero(), "err")?;
+    let balance = TOKENBALANCE.load(_deps.storage, _token.clone())?;
+    let event = Event::new("Sync")
+        .add_attribute("token", _token.as_str())
+        .add_attribute("cached_balance", cached_balance.to_string())
+        .add_attribute("balance", balance.to_string())
+        .add_attribute("added_to_pool", donation.to_string());
 
-    let _should_update = _updateCumulativeFundingRate(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
-        _token.clone(),
-        _token.clone(),
-    )?;
+    Ok(Response::new().add_submessages(pay_in).add_event(event))
+}
 
-    let price: Uint128 =
-        get_min_price(_deps.branch(), _env.clone(), _info.clone(), _token.clone())?;
+/// Sends tokens sent to the vault outside of any deposit to `_receiver`,
+/// leaving the pool untouched.
+pub fn skim(
+    _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _token: Addr,
+    _receiver: Addr,
+) -> Result<Response, ContractError> {
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
 
-    let mut usdgAmount: Uint128 = (tokenAmount * price) / PRICE_PRECISION;
+    let cached_balance = TOKENBALANCE
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+    let balance = balance_cw20_tokens(&_deps, _env.clone(), _token.clone())?;
+    let surplus = balance.saturating_sub(cached_balance);
+    validate(!surplus.is_zero(), "ERR_NOTHING_TO_SKIM")?;
 
-    usdgAmount = adjust_decimal(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
+    // the surplus sits in the vault whatever the custody mode
+    let msg = transfer_cw20_tokens(
         _token.clone(),
-        config.usdg.clone(),
-        usdgAmount,
+        _env.contract.address,
+        _receiver.clone(),
+        surplus,
     )?;
 
-    validate(usdgAmount > Uint128::zero(), "err")?;
+    let event = Event::new("Skim")
+        .add_attribute("token", _token.as_str())
+        .add_attribute("receiver", _receiver.as_str())
+        .add_attribute("amount", surplus.to_string());
 
-    let feeBasisPoints: Uint128 = getBuyUsdgFeeBasisPoints(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
-        _token.clone(),
-        usdgAmount,
-    )?;
+    Ok(Response::new().add_message(msg).add_event(event))
+}
+
+pub fn buyUSDG(
+    mut _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _token: Addr,
+    _receiver: Addr,
+) -> Result<Response, ContractError> {
+    when_not_paused(_deps.as_ref(), PauseTarget::MintBurn)?;
+    validate_manager(_deps.as_ref(), &_info.sender)?;
+
+    let tokenAmount: Uint128 = transfer_in(_deps.branch(), &_env, _token.clone())?;
+    let pay_in = load_custody(_deps.storage)?.pay_in(_deps.storage, &_env, &_token, tokenAmount)?;
+
+    let (_, res) = _buy_usdg(_deps, _env, _info, _token, tokenAmount, _receiver)?;
+    Ok(res.add_submessages(pay_in))
+}
 
-    let amountAfterFees: Uint128 = collect_fees(
+/// Shared by BuyUSDG and AddLiquidity; returns the USDG amount minted.
+pub fn _buy_usdg(
+    mut _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _token: Addr,
+    token_amount: Uint128,
+    _receiver: Addr,
+) -> Result<(Uint128, Response), ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+
+    let quote = quote_buy_usdg(_deps.as_ref(), _token.clone(), token_amount)?;
+    let _should_update = _updateCumulativeFundingRate(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
         _token.clone(),
-        tokenAmount,
-        feeBasisPoints,
+        _token.clone(),
     )?;
 
-    let mut mintAmount: Uint128 = (amountAfterFees * price) / PRICE_PRECISION;
-    mintAmount = adjust_decimal(
+    let usdg_amount = quote.usdg_amount;
+    let fee_basis_points = quote.fee_basis_points;
+    let amount_after_fees: Uint128 = collect_fees(
         _deps.branch(),
-        _env.clone(),
-        _info.clone(),
         _token.clone(),
-        config.usdg.clone(),
-        mintAmount,
+        token_amount,
+        fee_basis_points,
     )?;
+    let mint_amount = quote.mint_amount;
 
     _increaseUsdgAmount(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
         _token.clone(),
-        mintAmount,
+        mint_amount,
     )?;
     _increasePoolAmount(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
         _token.clone(),
-        amountAfterFees,
+        amount_after_fees,
     )?;
+    let mint_msg = mint_cw20_tokens(config.usdg, _receiver.clone(), mint_amount)?;
+
     let attributes = Event::new("BuyUSDG")
         .add_attribute("action", "BuyUSDG")
         .add_attribute("receiver", _receiver.as_str())
         .add_attribute("token", _token.as_str())
-        .add_attribute("token_amount", usdgAmount.to_string())
-        .add_attribute("mint_amount", mintAmount.to_string())
-        .add_attribute("fee_basis_points", feeBasisPoints.to_string());
-
-    Ok(Response::new().add_event(attributes))
+        .add_attribute("token_amount", usdg_amount.to_string())
+        .add_attribute("mint_amount", mint_amount.to_string())
+        .add_attribute("fee_basis_points", fee_basis_points.to_string());
+
+    Ok((
+        mint_amount,
+        Response::new().add_message(mint_msg).add_event(attributes),
+    ))
 }
 
+/// Sells USDG the sender has approved the vault to burn.
 pub fn sellUSDG(
-    mut _deps: DepsMut,
+    _deps: DepsMut,
     _env: Env,
     _info: MessageInfo,
     _token: Addr,
+    _usdgAmount: Uint128,
     _receiver: Addr,
 ) -> Result<Response, ContractError> {
-    let mut config = CONFIG.load(_deps.storage)?;
-    validate(config.in_manager_mode, "err")?;
+    let owner = _info.sender.clone();
+    sell_usdg_from(
+        _deps,
+        _env,
+        _info,
+        _token,
+        _usdgAmount,
+        _receiver,
+        Some(owner),
+    )
+}
 
-    let whitelistedtoken = WHITELISTEDTOKEN.load(_deps.storage, _token.clone())?;
-    validate(whitelistedtoken, "err")?;
+/// Cw20 receive hook: USDG sent to the vault with a `ReceiveMsg` is sold and
+/// burnt from the vault's balance.
+pub fn receive_cw20(
+    _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _cw20_msg: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+    if _info.sender != config.usdg {
+        return Err(ContractError::Unauthorized {});
+    }
 
-    config.use_swap_pricing = true;
+    let sender = _deps.api.addr_validate(&_cw20_msg.sender)?;
+    let info = MessageInfo {
+        sender,
+        funds: vec![],
+    };
 
-    CONFIG.save(_deps.storage, &config)?;
+    match from_binary(&_cw20_msg.msg)? {
+        ReceiveMsg::SellUSDG { _token, _receiver } => {
+            sell_usdg_from(_deps, _env, info, _token, _cw20_msg.amount, _receiver, None)
+        }
+    }
+}
+
+fn sell_usdg_from(
+    mut _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _token: Addr,
+    _usdg_amount: Uint128,
+    _receiver: Addr,
+    _owner: Option<Addr>,
+) -> Result<Response, ContractError> {
+    when_not_paused(_deps.as_ref(), PauseTarget::MintBurn)?;
+    validate_manager(_deps.as_ref(), &_info.sender)?;
+
+    let config = CONFIG.load(_deps.storage)?;
+    let burn = TokenBurn {
+        token: config.usdg,
+        owner: _owner,
+        amount: _usdg_amount,
+    };
+    let (_amount_out, res) = _sell_usdg(
+        _deps.branch(),
+        _env,
+        _info,
+        _token,
+        _usdg_amount,
+        _usdg_amount,
+        _receiver,
+        vec![burn],
+    )?;
 
-    let usdgAmount: Uint128 =
-        balance_cw20_tokens(&_deps.branch(), _env.clone(), config.usdg.clone())?;
-    validate(usdgAmount > Uint128::zero(), "err")?;
+    Ok(res)
+}
 
+/// Shared by SellUSDG and RemoveLiquidity; returns the token amount paid out.
+/// `_burns` are sent ahead of the payout and minted back if it fails.
+/// `usdg_burnt` is the part of `usdg_amount` they take out of the supply, and
+/// the USDG debt cleared from `_token`.
+pub fn _sell_usdg(
+    mut _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _token: Addr,
+    usdg_amount: Uint128,
+    usdg_burnt: Uint128,
+    _receiver: Addr,
+    _burns: Vec<TokenBurn>,
+) -> Result<(Uint128, Response), ContractError> {
+    let quote = quote_sell_usdg(_deps.as_ref(), _token.clone(), usdg_amount)?;
     let _should_update = _updateCumulativeFundingRate(
         _deps.branch(),
         _env.clone(),
@@ -851,72 +1654,296 @@ pub fn sellUSDG(
         _token.clone(),
     )?;
 
-    let redemptionAmount: Uint128 = getRedemptionAmount(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
-        _token.clone(),
-        usdgAmount,
-    )?;
-    validate(redemptionAmount > Uint128::zero(), "errr")?;
+    let redemption_amount = quote.redemption_amount;
+
+    let prev_pool_amount = POOLAMOUNT.load(_deps.storage, _token.clone())?;
+    let prev_usdg_amount = USDGAMOUNT.load(_deps.storage, _token.clone())?;
+    let prev_fee_reserved = FEERESERVED.load(_deps.storage, _token.clone())?;
+    let prev_token_balance = TOKENBALANCE.load(_deps.storage, _token.clone())?;
+    let prev_uncleared = UNCLEAREDUSDGDEBT
+        .may_load(_deps.storage)?
+        .unwrap_or_default();
+    let prev_bank_deposits = BANKDEPOSITS
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
 
     _decreaseUsdgAmount(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
         _token.clone(),
-        usdgAmount,
+        usdg_burnt,
     )?;
     _decreasePoolAmount(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
         _token.clone(),
-        redemptionAmount,
+        redemption_amount,
     )?;
 
-    update_token_bal(_deps.branch(), _env.clone(), _info.clone(), config.usdg)?;
-
-    let feeBasisPoints: Uint128 = getSellUsdgFeeBasisPoints(
+    let fee_basis_points = quote.fee_basis_points;
+    let amount_after_fees: Uint128 = collect_fees(
         _deps.branch(),
-        _env.clone(),
-        _info.clone(),
         _token.clone(),
-        usdgAmount,
+        redemption_amount,
+        fee_basis_points,
+    )?;
+    let mut res = Response::new();
+    for burn in &_burns {
+        res = res.add_message(burn_cw20_tokens(
+            burn.token.clone(),
+            burn.owner.clone(),
+            burn.amount,
+        )?);
+    }
+
+    let payout = load_custody(_deps.storage)?.pay_out(
+        _deps.storage,
+        &_env,
+        &_token,
+        &_receiver,
+        amount_after_fees,
+    )?;
+
+    // a failed payout is caught in `reply`, which undoes these deltas
+    let payout_id = NEXTPAYOUTID.may_load(_deps.storage)?.unwrap_or(1);
+    NEXTPAYOUTID.save(_deps.storage, &(payout_id + 1))?;
+    let pending = PendingPayout {
+        token: _token.clone(),
+        receiver: _receiver.clone(),
+        amount: amount_after_fees,
+        pool_decrease: prev_pool_amount - POOLAMOUNT.load(_deps.storage, _token.clone())?,
+        usdg_decrease: prev_usdg_amount - USDGAMOUNT.load(_deps.storage, _token.clone())?,
+        fee_reserved_increase: FEERESERVED.load(_deps.storage, _token.clone())? - prev_fee_reserved,
+        token_balance_decrease: prev_token_balance
+            - TOKENBALANCE.load(_deps.storage, _token.clone())?,
+        bank_deposit_decrease: prev_bank_deposits
+            - BANKDEPOSITS
+                .may_load(_deps.storage, _token.clone())?
+                .unwrap_or_default(),
+        uncleared_usdg_debt_increase: UNCLEAREDUSDGDEBT
+            .may_load(_deps.storage)?
+            .unwrap_or_default()
+            - prev_uncleared,
+        burns: _burns,
+    };
+    PENDINGPAYOUTS.save(_deps.storage, payout_id, &pending)?;
+    res = res.add_submessage(SubMsg::reply_always(payout.msg, payout_id));
+
+    let attributes = Event::new("SellUSDG")
+        .add_attribute("action", "SellUSDG")
+        .add_attribute("receiver", _receiver.as_str())
+        .add_attribute("token", _token.as_str())
+        .add_attribute("usdg_amount", usdg_amount.to_string())
+        .add_attribute("token_amount", amount_after_fees.to_string())
+        .add_attribute("fee_basis_points", fee_basis_points.to_string());
+
+    Ok((amount_after_fees, res.add_event(attributes)))
+}
+
+pub fn set_liquidity_config(
+    _deps: DepsMut,
+    _api: &dyn Api,
+    _info: MessageInfo,
+    lp_token: Addr,
+    cooldown_duration: u64,
+) -> Result<Response, ContractError> {
+    only_role(_deps.as_ref(), &_info.sender, Role::Gov)?;
+    validate(
+        cooldown_duration <= MAX_COOLDOWN_DURATION,
+        "ERR_INVALID_COOLDOWN_DURATION",
+    )?;
+
+    LIQUIDITYCONFIG.save(
+        _deps.storage,
+        &LiquidityConfig {
+            lp_token: lp_token.clone(),
+            cooldown_duration,
+        },
     )?;
 
-    let amountAfterFees: Uint128 = collect_fees(
+    Ok(Response::new()
+        .add_attribute("action", "set_liquidity_config")
+        .add_attribute("lp_token", lp_token)
+        .add_attribute("cooldown_duration", cooldown_duration.to_string()))
+}
+
+pub fn add_liquidity(
+    mut _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    token: Addr,
+    min_usdg: Uint128,
+    min_lp: Uint128,
+) -> Result<Response, ContractError> {
+    when_not_paused(_deps.as_ref(), PauseTarget::MintBurn)?;
+    // no validate_manager: like GMX's GlpManager this is the managed path. The
+    // USDG it mints stays in the vault and only RemoveLiquidity burns it
+    let liquidity = LIQUIDITYCONFIG
+        .may_load(_deps.storage)?
+        .ok_or(ContractError::LiquidityNotConfigured {})?;
+
+    let token_amount: Uint128 = transfer_in(_deps.branch(), &_env, token.clone())?;
+    let pay_in = load_custody(_deps.storage)?.pay_in(_deps.storage, &_env, &token, token_amount)?;
+
+    // price shares before the deposit lands in the pool
+    let aum_in_usdg = get_lp_aum_in_usdg(_deps.as_ref(), _env.contract.address.clone(), true)?;
+    let lp_supply = total_supply_cw20_tokens(_deps.as_ref(), liquidity.lp_token.clone())?;
+
+    // the vault keeps the USDG backing the shares
+    let (usdg_amount, res) = _buy_usdg(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
-        _token.clone(),
-        redemptionAmount,
-        feeBasisPoints,
+        token.clone(),
+        token_amount,
+        _env.contract.address.clone(),
     )?;
+    if usdg_amount < min_usdg {
+        return Err(ContractError::InsufficientOutput {
+            amount: usdg_amount,
+            min_amount: min_usdg,
+        });
+    }
 
-    validate(amountAfterFees > Uint128::zero(), "err")?;
-    let mut res = Response::new();
+    // the first shares are minted 1:1. Value already in the pool that no
+    // share owns (trader losses, direct deposits) is seeded as shares held by
+    // the vault, so the first depositor doesn't get it for free
+    let seed_amount = if lp_supply.is_zero() {
+        aum_in_usdg
+    } else {
+        Uint128::zero()
+    };
+    let mint_amount = if aum_in_usdg.is_zero() || lp_supply.is_zero() {
+        usdg_amount
+    } else {
+        mul_div(usdg_amount, lp_supply, aum_in_usdg, Rounding::Down)?
+    };
+    if mint_amount < min_lp {
+        return Err(ContractError::InsufficientOutput {
+            amount: mint_amount,
+            min_amount: min_lp,
+        });
+    }
 
-    let state = STATE.load(_deps.storage)?;
+    LASTADDEDAT.save(
+        _deps.storage,
+        _info.sender.clone(),
+        &_env.block.time.seconds(),
+    )?;
 
-    res = res.add_submessage(pay_out(
+    let mut mint_msgs = vec![mint_cw20_tokens(
+        liquidity.lp_token.clone(),
+        _info.sender.clone(),
+        mint_amount,
+    )?];
+    if !seed_amount.is_zero() {
+        mint_msgs.push(mint_cw20_tokens(
+            liquidity.lp_token,
+            _env.contract.address.clone(),
+            seed_amount,
+        )?);
+    }
+
+    let event = Event::new("AddLiquidity")
+        .add_attribute("account", _info.sender.as_str())
+        .add_attribute("token", token.as_str())
+        .add_attribute("amount", token_amount.to_string())
+        .add_attribute("aum_in_usdg", aum_in_usdg.to_string())
+        .add_attribute("lp_supply", lp_supply.to_string())
+        .add_attribute("usdg_amount", usdg_amount.to_string())
+        .add_attribute("mint_amount", mint_amount.to_string())
+        .add_attribute("seed_amount", seed_amount.to_string());
+
+    Ok(res
+        .add_submessages(pay_in)
+        .add_messages(mint_msgs)
+        .add_event(event))
+}
+
+pub fn remove_liquidity(
+    mut _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    token_out: Addr,
+    lp_amount: Uint128,
+    min_out: Uint128,
+    receiver: Addr,
+) -> Result<Response, ContractError> {
+    when_not_paused(_deps.as_ref(), PauseTarget::MintBurn)?;
+    let liquidity = LIQUIDITYCONFIG
+        .may_load(_deps.storage)?
+        .ok_or(ContractError::LiquidityNotConfigured {})?;
+    validate(lp_amount > Uint128::zero(), "ERR_INVALID_LP_AMOUNT")?;
+
+    let last_added_at = LASTADDEDAT
+        .may_load(_deps.storage, _info.sender.clone())?
+        .unwrap_or_default();
+    let available_at = last_added_at + liquidity.cooldown_duration;
+    if available_at > _env.block.time.seconds() {
+        return Err(ContractError::CooldownNotExpired { available_at });
+    }
+
+    // redeem at min prices so removing never pays more than the pool is worth
+    let aum_in_usdg = get_lp_aum_in_usdg(_deps.as_ref(), _env.contract.address.clone(), false)?;
+    let lp_supply = total_supply_cw20_tokens(_deps.as_ref(), liquidity.lp_token.clone())?;
+    validate(lp_supply >= lp_amount, "ERR_INVALID_LP_AMOUNT")?;
+
+    let usdg_amount = mul_div(lp_amount, aum_in_usdg, lp_supply, Rounding::Down)?;
+
+    let mut burns = vec![TokenBurn {
+        token: liquidity.lp_token,
+        owner: Some(_info.sender.clone()),
+        amount: lp_amount,
+    }];
+
+    // the vault holds the USDG minted for LP deposits, and the shares burn
+    // their part of it whatever the shares are now worth. Only the USDG
+    // actually burnt comes off the token's USDG debt, so the supply stays
+    // reconciled
+    let config = CONFIG.load(_deps.storage)?;
+    let usdg_held = cw20_balance_of(
+        _deps.as_ref(),
+        config.usdg.clone(),
+        _env.contract.address.clone(),
+    )?;
+    let usdg_burnt = mul_div(usdg_held, lp_amount, lp_supply, Rounding::Down)?;
+    if !usdg_burnt.is_zero() {
+        burns.push(TokenBurn {
+            token: config.usdg,
+            owner: None,
+            amount: usdg_burnt,
+        });
+    }
+
+    let (amount_out, res) = _sell_usdg(
+        _deps.branch(),
         _env.clone(),
-        state.bank_addr.clone(),
-        0,
-        _token.clone().into_string(),
-        _receiver.clone(),
-        amountAfterFees.u128(),
-    ));
+        _info.clone(),
+        token_out.clone(),
+        usdg_amount,
+        usdg_burnt,
+        receiver.clone(),
+        burns,
+    )?;
+    if amount_out < min_out {
+        return Err(ContractError::InsufficientOutput {
+            amount: amount_out,
+            min_amount: min_out,
+        });
+    }
 
-    let attributes = Event::new("BuyUSDG")
-        .add_attribute("action", "BuyUSDG")
-        .add_attribute("receiver", _receiver.as_str())
-        .add_attribute("token", _token.as_str())
-        .add_attribute("token_amount", usdgAmount.to_string())
-        .add_attribute("burn_amount", amountAfterFees.to_string())
-        .add_attribute("fee_basis_points", feeBasisPoints.to_string());
+    let event = Event::new("RemoveLiquidity")
+        .add_attribute("account", _info.sender.as_str())
+        .add_attribute("token", token_out.as_str())
+        .add_attribute("lp_amount", lp_amount.to_string())
+        .add_attribute("aum_in_usdg", aum_in_usdg.to_string())
+        .add_attribute("lp_supply", lp_supply.to_string())
+        .add_attribute("usdg_amount", usdg_amount.to_string())
+        .add_attribute("amount_out", amount_out.to_string());
 
-    Ok(Response::new().add_event(attributes))
+    Ok(res.add_event(event))
 }
 
 pub fn _updateCumulativeFundingRate(
@@ -945,17 +1972,12 @@ pub fn _updateCumulativeFundingRate(
     }
 
     if (lastFundingTimes + fundinginterval) > _env.clone().block.time.seconds() as u128 {
-        return Err(ContractError::Unauthorized {});
+        return Ok(Response::new());
     }
 
     CONFIG.save(_deps.branch().storage, &config)?;
 
-    let fundingRate = getNextFundingRate(
-        _deps.branch(),
-        _env.clone(),
-        _info,
-        _collateralToken.clone(),
-    )?;
+    let fundingRate = getNextFundingRate(_deps.as_ref(), &_env, _collateralToken.clone())?;
 
     let mut cumulativeFundingRates =
         CUMULATIVEFUNDINGRATE.load(_deps.storage, _collateralToken.clone())?;
@@ -982,114 +2004,20 @@ pub fn _updateCumulativeFundingRate(
     Ok(Response::new().add_event(event))
 }
 
-pub fn getNextFundingRate(
-    // query
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _collateralToken: Addr,
-) -> Result<Uint128, ContractError> {
-    let lastFundingTimes = LASTFUNDINTIME.load(_deps.storage, _collateralToken.clone())?;
-    let config = CONFIG.load(_deps.storage)?;
-    let fundinginterval = config.funding_interval;
-
-    if lastFundingTimes + fundinginterval > _env.block.time.seconds() as u128 {
-        return Ok(Uint128::zero());
-    }
-
-    let intervals: u128 =
-        ((_env.block.time.seconds() as u128) - lastFundingTimes) / fundinginterval;
-    let poolAmount = POOLAMOUNT.load(_deps.storage, _collateralToken.clone())?;
-    if poolAmount == Uint128::zero() {
-        return Ok(Uint128::zero());
-    }
-
-    let _fundingRateFactor: u128;
-
-    let stableToken = STABLETOKEN.load(_deps.storage, _collateralToken.clone())?;
-
-    if stableToken {
-        _fundingRateFactor = config.stable_funding_rate_factor;
-    } else {
-        _fundingRateFactor = config.funding_rate_factor
-    }
-    let reserve_amount = RESERVEDAMOUNTS.load(_deps.storage, _collateralToken.clone())?;
-
-    let rate = _fundingRateFactor * (reserve_amount.u128()) * intervals;
-
-    Ok(Uint128::new(rate) / poolAmount)
-}
-
-pub fn adjust_decimal(
-    //query
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _tokenDev: Addr,
-    _tokenMul: Addr,
-    _amount: Uint128,
-) -> Result<Uint128, ContractError> {
-    let decimalsDiv: Uint128;
-    let config = CONFIG.load(_deps.storage)?;
-    if _tokenDev == config.usdg {
-        decimalsDiv = USDG_DECIMALS
-    } else {
-        decimalsDiv = TOKENDECIMAL.load(_deps.storage, _tokenDev)?;
-    }
-
-    let decimalsMul: Uint128;
-
-    if _tokenMul == config.usdg {
-        decimalsMul = USDG_DECIMALS
-    } else {
-        decimalsMul = TOKENDECIMAL.load(_deps.storage, _tokenMul)?;
-    }
-
-    let res: Uint128 = (_amount * Uint128::new(10).pow(decimalsMul.u128() as u32))
-        / Uint128::new(10).pow(decimalsDiv.u128() as u32);
-
-    Ok(res)
-}
 pub fn collect_fees(
-    //query
     _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
     _token: Addr,
     _amount: Uint128,
     _fee_basis_points: Uint128,
 ) -> Result<Uint128, ContractError> {
-    let afterFeeAmount: Uint128 =
-        _amount * (BASIS_POINTS_DIVISOR - _fee_basis_points) / BASIS_POINTS_DIVISOR;
-    let _feeAmount: Uint128 = _amount - afterFeeAmount;
-
-    let feeReserves = FEERESERVED.load(_deps.storage, _token)?;
-
-    Ok(feeReserves)
-}
+    let (afterFeeAmount, _feeAmount) = apply_fee(_amount, _fee_basis_points)?;
 
-pub fn getRedemptionAmount(
-    //query
-    mut _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _token: Addr,
-    _usdgAmount: Uint128,
-) -> Result<Uint128, ContractError> {
-    let price: Uint128 =
-        get_max_price(_deps.branch(), _env.clone(), _info.clone(), _token.clone())?;
-    let redemptionAmount: Uint128 = (_usdgAmount * PRICE_PRECISION) / price;
-    let config = CONFIG.load(_deps.storage)?;
+    let feeReserves = FEERESERVED
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+    FEERESERVED.save(_deps.storage, _token, &feeReserves.checked_add(_feeAmount)?)?;
 
-    let res = adjust_decimal(
-        _deps,
-        _env.clone(),
-        _info.clone(),
-        config.usdg,
-        _token.clone(),
-        redemptionAmount,
-    )?;
-    Ok(res)
+    Ok(afterFeeAmount)
 }
 
 pub fn swap(
@@ -1100,16 +2028,9 @@ pub fn swap(
     _tokenOut: Addr,
     _receiver: Addr,
 ) -> Result<Response, ContractError> {
-    let mut config = CONFIG.load(_deps.storage)?;
-    let whitelistedTokensIn = WHITELISTEDTOKEN.load(_deps.storage, _tokenIn.clone())?;
-    let whitelistedTokensOut = WHITELISTEDTOKEN.load(_deps.storage, _tokenOut.clone())?;
-
-    validate(config.is_swap_enabled, "err")?;
-    validate(whitelistedTokensIn, "err")?;
-    validate(whitelistedTokensOut, "err")?;
-    validate(_tokenIn != _tokenOut, "err")?;
+    when_not_paused(_deps.as_ref(), PauseTarget::Swap)?;
+    validate_swap(_deps.as_ref(), _tokenIn.clone(), _tokenOut.clone())?;
 
-    config.use_swap_pricing = true;
     _updateCumulativeFundingRate(
         _deps.branch(),
         _env.clone(),
@@ -1125,54 +2046,86 @@ pub fn swap(
         _tokenOut.clone(),
     )?;
 
-    let amountIn: Uint128 = balance_cw20_tokens(&_deps.branch(), _env.clone(), _tokenIn.clone())?;
-    validate(amountIn > Uint128::zero(), "err")?;
+    let amountIn: Uint128 = transfer_in(_deps.branch(), &_env, _tokenIn.clone())?;
+    let custody = load_custody(_deps.storage)?;
+    let pay_in = custody.pay_in(_deps.storage, &_env, &_tokenIn, amountIn)?;
+    let quote = quote_swap(
+        _deps.as_ref(),
+        _tokenIn.clone(),
+        _tokenOut.clone(),
+        amountIn,
+    )?;
+
+    let amountOut = quote.amount_out + quote.fee_amount;
+    let usdgAmount = quote.usdg_amount;
+    let feeBasisPoints = quote.fee_basis_points;
+    let amountOutAfterFees =
+        collect_fees(_deps.branch(), _tokenOut.clone(), amountOut, feeBasisPoints)?;
 
-    let priceIn = get_min_price(
+    _increaseUsdgAmount(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
         _tokenIn.clone(),
+        usdgAmount,
     )?;
-    let priceOut = get_max_price(
+    _decreaseUsdgAmount(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
         _tokenOut.clone(),
+        usdgAmount,
     )?;
-
-    let mut amountOut: Uint128 = amountIn * priceIn / priceOut;
-
-    amountOut = adjust_decimal(
+    _increasePoolAmount(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
         _tokenIn.clone(),
-        _tokenOut.clone(),
-        amountOut,
+        amountIn,
     )?;
-
-    let mut usdgAmount: Uint128 = amountIn * priceIn / PRICE_PRECISION;
-    usdgAmount = adjust_decimal(
+    _decreasePoolAmount(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
-        _tokenIn.clone(),
-        config.clone().usdg,
-        usdgAmount,
+        _tokenOut.clone(),
+        amountOut,
     )?;
 
-    let _feeBasisPoints: Uint128 = getSwapFeeBasisPoints(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
-        _tokenIn.clone(),
-        _tokenOut.clone(),
-        usdgAmount,
+    let payout = custody.pay_out(
+        _deps.storage,
+        &_env,
+        &_tokenOut,
+        &_receiver,
+        amountOutAfterFees,
     )?;
 
-    CONFIG.save(_deps.storage, &config)?;
-    Ok(Response::new())
+    let event = Event::new("Swap")
+        .add_attribute("account", _receiver.as_str())
+        .add_attribute("token_in", _tokenIn.as_str())
+        .add_attribute("token_out", _tokenOut.as_str())
+        .add_attribute("amount_in", amountIn.to_string())
+        .add_attribute("amount_out", amountOut.to_string())
+        .add_attribute("amount_out_after_fees", amountOutAfterFees.to_string())
+        .add_attribute("fee_basis_points", feeBasisPoints.to_string());
+
+    Ok(Response::new()
+        .add_submessages(pay_in)
+        .add_submessage(payout)
+        .add_event(event))
+}
+
+/// Checks shared by Swap and SimulateSwap that don't depend on the amount.
+pub fn validate_swap(_deps: Deps, _tokenIn: Addr, _tokenOut: Addr) -> Result<(), ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+    let whitelistedTokensIn = WHITELISTEDTOKEN.load(_deps.storage, _tokenIn.clone())?;
+    let whitelistedTokensOut = WHITELISTEDTOKEN.load(_deps.storage, _tokenOut.clone())?;
+
+    validate(config.is_swap_enabled, "err")?;
+    validate(whitelistedTokensIn, "err")?;
+    validate(whitelistedTokensOut, "err")?;
+    validate(_tokenIn != _tokenOut, "err")?;
+
+    Ok(())
 }
 
 pub fn increasePosition(
@@ -1185,13 +2138,24 @@ pub fn increasePosition(
     _sizeDelta: Uint128,
     _isLong: bool,
 ) -> Result<Response, ContractError> {
-    let cofig = CONFIG.load(_deps.storage)?;
-
-    validate(cofig.is_leverage_enabled, "err")?;
-    _validateTokens(
-        &_deps,
+    when_not_paused(_deps.as_ref(), PauseTarget::IncreasePosition)?;
+
+    let collateralDelta = transfer_in(_deps.branch(), &_env, _collateralToken.clone())?;
+    let pay_in = load_custody(_deps.storage)?.pay_in(
+        _deps.storage,
+        &_env,
+        &_collateralToken,
+        collateralDelta,
+    )?;
+    // the quote already counts funding intervals the update below applies
+    let quote = quote_increase_position(
+        _deps.as_ref(),
+        &_env,
+        _account.clone(),
         _collateralToken.clone(),
         _indexToken.clone(),
+        collateralDelta,
+        _sizeDelta,
         _isLong,
     )?;
 
@@ -1209,97 +2173,18 @@ pub fn increasePosition(
         _isLong,
     );
 
-    let price: Uint128;
-
-    if _isLong {
-        price = get_max_price(
-            _deps.branch(),
-            _env.clone(),
-            _info.clone(),
-            _indexToken.clone(),
-        )?;
-    } else {
-        price = get_min_price(
-            _deps.branch(),
-            _env.clone(),
-            _info.clone(),
-            _indexToken.clone(),
-        )?;
-    }
-
-    let mut position = get_position(_deps.branch().as_ref(), key.clone())?;
-
-    if position.size == Uint128::zero() {
-        position.averagePrice = price;
-    }
-    if position.size > Uint128::zero() && _sizeDelta > Uint128::zero() {
-        position.averagePrice = get_next_average_price(
-            _deps.branch(),
-            _env.clone(),
-            _info.clone(),
-            _indexToken.clone(),
-            position.size,
-            position.averagePrice,
-            _isLong,
-            price,
-            _sizeDelta,
-            position.lastIncreasedTime,
-        )?;
-    }
-
-    let fess = &_collect_margin_fees(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
-        _account.clone(),
-        _collateralToken.clone(),
-        _indexToken.clone(),
-        _isLong.clone(),
-        _sizeDelta.u128(),
-        position.size.u128(),
-        position.entryFundingRate.u128(),
-    )?
-    .attributes[0]
-        .value;
-
-    let _fees = Uint128::from_str(fess)?;
-
-    let mut _hasProfit: bool;
-    let mut _adjustedDelta: Uint128;
-
-    let collateralDelta = balance_cw20_tokens(&_deps, _env.clone(), _collateralToken.clone())?;
-    let collateralDeltaUsd = token_to_usd_min(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
-        _collateralToken.clone(),
-        collateralDelta.u128(),
-    )?;
-
-    position.collateral = position.collateral + collateralDeltaUsd;
-    position.collateral = position.collateral - _fees;
+    let price = quote.price;
+    let position = quote.position;
+    let _fees = quote.fee_usd;
+    let collateralDeltaUsd = quote.collateral_delta_usd;
 
-    position.entryFundingRate = get_entry_funding_rate(
+    _collect_margin_fees(_deps.branch(), _collateralToken.clone(), _fees)?;
+    _increaseReservedAmount(
         _deps.branch(),
         _collateralToken.clone(),
-        _indexToken.clone(),
-        _isLong,
+        quote.reserve_delta,
     )?;
 
-    position.size = position.size + _sizeDelta;
-    position.lastIncreasedTime = _env.clone().block.time.seconds();
-    validate(position.size > Uint128::zero(), "err")?;
-
-    let reserveDelta = usdToTokenMax(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
-        _collateralToken.clone(),
-        _sizeDelta,
-    )?;
-    position.reserveAmount = position.reserveAmount + reserveDelta;
-    _increaseReservedAmount(_deps.branch(), _collateralToken.clone(), reserveDelta)?;
-
     if _isLong {
         _increaseGuaranteedUsd(_deps.branch(), _collateralToken.clone(), _sizeDelta + _fees)?;
         _decreaseGuaranteedUsd(
@@ -1315,9 +2200,7 @@ pub fn increasePosition(
             collateralDelta,
         )?;
         let amount = Uint128::new(usd_to_token_min(
-            _deps.branch(),
-            _env.clone(),
-            _info.clone(),
+            _deps.as_ref(),
             _collateralToken.clone(),
             _fees.u128(),
         )?);
@@ -1328,13 +2211,28 @@ pub fn increasePosition(
             _collateralToken.clone(),
             amount,
         )?;
+
+        let globalLongAveragePrices = get_next_global_long_average_price(
+            _deps.as_ref(),
+            _indexToken.clone(),
+            price,
+            _sizeDelta,
+        )?;
+        GLOBALLONGAVERAGEPRICE.save(
+            _deps.storage,
+            _indexToken.clone(),
+            &globalLongAveragePrices,
+        )?;
+        _increaseGlobalLongSize(_deps.branch(), _indexToken.clone(), _sizeDelta)?;
     } else {
-        let globalShortSizes = GLOBALSHORTSIZE.load(_deps.storage, _indexToken.clone())?;
-        if globalShortSizes == Uint128::zero() {
-            GLOBALSHORTSIZE.save(_deps.storage, _indexToken.clone(), &price)?;
+        let globalShortSizes = GLOBALSHORTSIZE
+            .may_load(_deps.storage, _indexToken.clone())?
+            .unwrap_or_default();
+        if globalShortSizes.is_zero() {
+            GLOBALSHORTAVERAGEPRICE.save(_deps.storage, _indexToken.clone(), &price)?;
         } else {
             let globalShortAveragePrices = get_next_global_short_average_price(
-                _deps.branch(),
+                _deps.as_ref(),
                 _indexToken.clone(),
                 price,
                 _sizeDelta,
@@ -1345,14 +2243,47 @@ pub fn increasePosition(
                 &globalShortAveragePrices,
             )?;
         }
+
+        _increaseGlobalShortSize(_deps.branch(), _indexToken.clone(), _sizeDelta)?;
     }
 
     POSITION.save(_deps.storage, key, &position)?;
 
-    Ok(Response::new())
+    Ok(Response::new().add_submessages(pay_in))
 }
 
 pub fn decreasePosition(
+    _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _account: Addr,
+    _collateralToken: Addr,
+    _indexToken: Addr,
+    _collateralDelta: Uint128,
+    _sizeDelta: Uint128,
+    _isLong: bool,
+    _receiver: Addr,
+) -> Result<Response, ContractError> {
+    when_not_paused(_deps.as_ref(), PauseTarget::DecreasePosition)?;
+    if _info.sender != _account {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    _decreasePosition(
+        _deps,
+        _env,
+        _info,
+        _account,
+        _collateralToken,
+        _indexToken,
+        _collateralDelta,
+        _sizeDelta,
+        _isLong,
+        _receiver,
+    )
+}
+
+pub fn _decreasePosition(
     mut _deps: DepsMut,
     _env: Env,
     _info: MessageInfo,
@@ -1364,6 +2295,17 @@ pub fn decreasePosition(
     _isLong: bool,
     _receiver: Addr,
 ) -> Result<Response, ContractError> {
+    // the quote already counts funding intervals the update below applies
+    let quote = quote_decrease_position(
+        _deps.as_ref(),
+        &_env,
+        _account.clone(),
+        _collateralToken.clone(),
+        _indexToken.clone(),
+        _collateralDelta,
+        _sizeDelta,
+        _isLong,
+    )?;
     _updateCumulativeFundingRate(
         _deps.branch(),
         _env.clone(),
@@ -1377,46 +2319,26 @@ pub fn decreasePosition(
         _indexToken.clone(),
         _isLong,
     );
-    let mut position = get_position(_deps.as_ref(), key.clone())?;
-    validate(position.size > Uint128::zero(), "err")?;
-    validate(position.size >= _sizeDelta, "err")?;
-    validate(position.collateral >= _collateralDelta.clone(), "err")?;
-    let config = CONFIG.load(_deps.storage)?;
-
-    let collateral: Uint128 = position.collateral;
-    let reserveDelta: Uint128 = position.reserveAmount * _sizeDelta / position.size;
-    position.reserveAmount = position.reserveAmount - reserveDelta;
-    _decreaseReservedAmount(_deps.branch(), _collateralToken.clone(), reserveDelta)?;
-
-    let usdtout: Uint128;
+    let collateral: Uint128 = get_position(_deps.as_ref(), key.clone())?.collateral;
 
-    let response = _reduceCollateral(
+    _decreaseReservedAmount(
+        _deps.branch(),
+        _collateralToken.clone(),
+        quote.reserve_delta,
+    )?;
+    _apply_reduce_collateral(
         _deps.branch(),
         _env.clone(),
         _info.clone(),
-        _account.clone(),
         _collateralToken.clone(),
-        _indexToken.clone(),
-        _collateralDelta,
-        _sizeDelta,
         _isLong,
+        &quote.reduce,
     )?;
+    let usdOut = quote.reduce.usd_out;
+    let usdOutAfterFee = quote.reduce.usd_out_after_fee;
 
-    usdtout = Uint128::from_str(response.attributes[0].value.as_str())?;
-
-    let price: Uint128;
-
-    if position.size != _sizeDelta {
-        // Update entry funding rate
-        let _entry_funding_rate = get_entry_funding_rate(
-            _deps.branch(),
-            _collateralToken.clone(),
-            _indexToken.clone(),
-            _isLong.clone(),
-        )?;
-
-        // Update position size and validate.clone()
-        position.size = position.size.wrapping_sub(_sizeDelta);
+    if let Some(position) = quote.position {
+        POSITION.save(_deps.storage, key, &position)?;
 
         if _isLong {
             _increaseGuaranteedUsd(
@@ -1424,56 +2346,33 @@ pub fn decreasePosition(
                 _collateralToken.clone(),
                 collateral - position.collateral,
             )?;
+            _decreaseGuaranteedUsd(_deps.branch(), _collateralToken.clone(), _sizeDelta)?;
         }
-
-        if _isLong {
-            price = get_min_price(
-                _deps.branch(),
-                _env.clone(),
-                _info.clone(),
-                _indexToken.clone(),
-            )?;
-        } else {
-            price = get_max_price(
-                _deps.branch(),
-                _env.clone(),
-                _info.clone(),
-                _indexToken.clone(),
-            )?;
-        };
     } else {
         if _isLong {
             _increaseGuaranteedUsd(_deps.branch(), _collateralToken.clone(), collateral)?;
             _decreaseGuaranteedUsd(_deps.branch(), _collateralToken.clone(), _sizeDelta)?;
         }
-        if _isLong {
-            price = get_min_price(
-                _deps.branch(),
-                _env.clone(),
-                _info.clone(),
-                _indexToken.clone(),
-            )?;
-        } else {
-            price = get_max_price(
-                _deps.branch(),
-                _env.clone(),
-                _info.clone(),
-                _indexToken.clone(),
-            )?;
-        }
-    };
+        POSITION.remove(_deps.storage, key);
+    }
 
-    if !_isLong {
-        _decreaseGlobalShortSize(_deps.branch(), _indexToken.clone(), position.size)?;
+    let price = quote.price;
+
+    if _isLong {
+        _decreaseGlobalLongSize(_deps.branch(), _indexToken.clone(), _sizeDelta)?;
+    } else {
+        _decreaseGlobalShortSize(_deps.branch(), _indexToken.clone(), _sizeDelta)?;
     }
-    if usdtout > Uint128::zero() {
+
+    let mut res = Response::new();
+    if usdOut > Uint128::zero() {
+        // long collateral is part of the pool, short collateral is not; the
+        // fee part of usdOut has already moved to the fee reserves
         if _isLong {
             let amount = Uint128::new(usd_to_token_min(
-                _deps.branch(),
-                _env.clone(),
-                _info.clone(),
+                _deps.as_ref(),
                 _collateralToken.clone(),
-                config.liquidation_fee_usd.u128(),
+                usdOut.u128(),
             )?);
             _decreasePoolAmount(
                 _deps.branch(),
@@ -1482,24 +2381,22 @@ pub fn decreasePosition(
                 _collateralToken.clone(),
                 amount,
             )?;
-
-            let amount = Uint128::new(usd_to_token_min(
-                _deps.branch(),
-                _env.clone(),
-                _info.clone(),
-                _collateralToken.clone(),
-                config.liquidation_fee_usd.u128(),
-            )?);
-            transfer_cw20_tokens(
-                _collateralToken.clone(),
-                _env.clone().contract.address,
-                _receiver,
-                amount,
-            )?;
-            return Ok(Response::new().add_attribute("amountafterfees", amount.to_string()));
+        }
+        let amountOut = quote.amount_out;
+
+        if amountOut > Uint128::zero() {
+            let custody = load_custody(_deps.storage)?;
+            res = res
+                .add_submessage(custody.pay_out(
+                    _deps.storage,
+                    &_env,
+                    &_collateralToken,
+                    &_receiver,
+                    amountOut,
+                )?)
+                .add_attribute("amountafterfees", amountOut.to_string());
         }
     }
-    POSITION.save(_deps.storage, key, &position)?;
     let decrease_event = Event::new("decrease_position")
         .add_attribute("account", _account.clone())
         .add_attribute("collateral_token", _collateralToken.clone())
@@ -1508,13 +2405,15 @@ pub fn decreasePosition(
         .add_attribute("size_delta", _sizeDelta.to_string())
         .add_attribute("is_long", _isLong.to_string())
         .add_attribute("price", price.to_string())
-        .add_attribute("usd_out_after_fee", usdtout.to_string());
+        .add_attribute("usd_out_after_fee", usdOutAfterFee.to_string());
 
-    Ok(Response::new().add_event(decrease_event))
+    Ok(res.add_event(decrease_event))
 }
 
-pub fn _reduceCollateral(
-    mut _deps: DepsMut,
+/// Lets a trader take collateral out of their own position. It settles like
+/// any other decrease, so the trader is paid and the leverage is re-checked.
+pub fn reduceCollateral(
+    _deps: DepsMut,
     _env: Env,
     _info: MessageInfo,
     _account: Addr,
@@ -1524,113 +2423,73 @@ pub fn _reduceCollateral(
     _sizeDelta: Uint128,
     _isLong: bool,
 ) -> Result<Response, ContractError> {
-    let key = get_position_key(
-        _account.clone(),
-        _collateralToken.clone(),
-        _indexToken.clone(),
-        _isLong,
-    );
-    let mut position = get_position(_deps.as_ref(), key)?;
-
-    let fess = &_collect_margin_fees(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
-        _account,
-        _collateralToken.clone(),
-        _indexToken.clone(),
-        _isLong,
-        _sizeDelta.u128(),
-        position.size.u128(),
-        position.entryFundingRate.u128(),
-    )?
-    .attributes[0]
-        .value;
-
-    let _fees = Uint128::from_str(fess)?;
-
-    let hasProfit: bool;
-    let adjustedDelta: Uint128;
+    when_not_paused(_deps.as_ref(), PauseTarget::DecreasePosition)?;
+    if _info.sender != _account {
+        return Err(ContractError::Unauthorized {});
+    }
 
-    let (_hasProfit, delta) = get_delta(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
+    _decreasePosition(
+        _deps,
+        _env,
+        _info,
+        _account.clone(),
+        _collateralToken,
         _indexToken,
-        position.size,
-        position.averagePrice,
+        _collateralDelta,
+        _sizeDelta,
         _isLong,
-        position.lastIncreasedTime,
-    )?;
-    hasProfit = _hasProfit;
-
-    adjustedDelta = _sizeDelta * delta / position.size;
-
-    let mut usdtOut = Uint128::zero();
-
-    if hasProfit && adjustedDelta > Uint128::zero() {
-        usdtOut = adjustedDelta;
-        position.realisedPnL =
-            position.realisedPnL + Int128::new(adjustedDelta.clone().u128() as i128);
+        _account,
+    )
+}
 
-        if _isLong {
-            let tokenAmount = usd_to_token_min(
-                _deps.branch(),
-                _env.clone(),
-                _info.clone(),
-                _collateralToken.clone(),
-                adjustedDelta.u128(),
-            )?;
+/// Moves the fees and the pool's side of the realised PnL for a quoted
+/// collateral reduction. Short profits and losses settle against the stable
+/// pool; long ones are already in the pool and settle through the payout.
+fn _apply_reduce_collateral(
+    mut _deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _collateral_token: Addr,
+    _is_long: bool,
+    quote: &ReduceCollateralQuote,
+) -> Result<(), ContractError> {
+    _collect_margin_fees(_deps.branch(), _collateral_token.clone(), quote.fee_usd)?;
+
+    if !_is_long && quote.adjusted_delta > Uint128::zero() {
+        let token_amount = Uint128::new(usd_to_token_min(
+            _deps.as_ref(),
+            _collateral_token.clone(),
+            quote.adjusted_delta.u128(),
+        )?);
+        if quote.has_profit {
             _decreasePoolAmount(
                 _deps.branch(),
                 _env.clone(),
                 _info.clone(),
-                _collateralToken.clone(),
-                Uint128::new(tokenAmount),
-            )?;
-        }
-    }
-
-    if !hasProfit && adjustedDelta > Uint128::zero() {
-        position.collateral = position.collateral - adjustedDelta;
-
-        if _isLong {
-            let tokenAmount = usd_to_token_min(
-                _deps.branch(),
-                _env.clone(),
-                _info.clone(),
-                _collateralToken.clone(),
-                adjustedDelta.u128(),
+                _collateral_token.clone(),
+                token_amount,
             )?;
+        } else {
             _increasePoolAmount(
                 _deps.branch(),
                 _env.clone(),
                 _info.clone(),
-                _collateralToken,
-                Uint128::new(tokenAmount),
+                _collateral_token.clone(),
+                token_amount,
             )?;
         }
-        position.realisedPnL = position.realisedPnL - Int128::new(adjustedDelta.u128() as i128);
-
-        if _collateralDelta > Uint128::zero() {
-            usdtOut = usdtOut + _collateralDelta;
-            position.collateral = position.collateral - _collateralDelta;
-        }
-
-        if position.size == _sizeDelta {
-            usdtOut = usdtOut + position.collateral;
-            position.collateral = Uint128::zero();
-        }
     }
-    let _attributes = vec![
-        attr("action", "update_pnl"),
-        attr("has_profit", hasProfit.to_string()),
-        attr("adjusted_delta", adjustedDelta.to_string()),
-    ];
 
-    let _event = Event::new("update_pnl");
+    if _is_long && quote.fee_from_collateral {
+        let fee_tokens = Uint128::new(usd_to_token_min(
+            _deps.as_ref(),
+            _collateral_token.clone(),
+            quote.fee_usd.u128(),
+        )?);
+        _decreasePoolAmount(_deps, _env, _info, _collateral_token, fee_tokens)?;
+    }
 
-    Ok(Response::new().add_attribute("usdtout", usdtOut.to_string()))
+    Ok(())
 }
 
 pub fn liquidatePosition(
@@ -1643,10 +2502,13 @@ pub fn liquidatePosition(
     _isLong: bool,
     _receiver: Addr,
 ) -> Result<Response, ContractError> {
+    when_not_paused(_deps.as_ref(), PauseTarget::Liquidation)?;
+
     let mut config = CONFIG.load(_deps.storage)?;
-    if config.in_private_liquidation_mode {
-        let isliq = ISLIQUIDATOR.load(_deps.storage, _info.clone().sender)?;
-        validate(isliq, "error_message")?;
+    if config.in_private_liquidation_mode
+        && !has_role(_deps.as_ref(), Role::Liquidator, _info.sender.clone())?
+    {
+        return Err(ContractError::Unauthorized {});
     }
 
     config.include_amm_price = false;
@@ -1671,20 +2533,22 @@ pub fn liquidatePosition(
     let liquidationState: Uint128;
     let marginFees: Uint128;
     (liquidationState, marginFees) = validLiquidation(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
+        _deps.as_ref(),
+        &_env,
         _account.clone(),
         _collateralToken.clone(),
         _indexToken.clone(),
         _isLong,
-        _receiver.clone(),
+        false,
     )?;
 
     validate(liquidationState != Uint128::zero(), "errr")?;
 
     if liquidationState == Uint128::new(2) {
-        decreasePosition(
+        // over max leverage but still solvent: close it and pay the trader.
+        // The keeper earns no liquidation fee, so it is not a liquidation in
+        // LIQUIDATORSTATS either.
+        let res = _decreasePosition(
             _deps.branch(),
             _env.clone(),
             _info.clone(),
@@ -1698,24 +2562,19 @@ pub fn liquidatePosition(
         )?;
 
         config.include_amm_price = true;
-        return Ok(Response::new());
+        return Ok(res);
     }
 
-    let feeTokens = usd_to_token_min(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
-        _collateralToken.clone(),
-        marginFees.u128(),
-    )?;
+    // the position can't pay more fees than the collateral it holds
+    let marginFees = marginFees.min(position.collateral);
+    let feeTokens = usd_to_token_min(_deps.as_ref(), _collateralToken.clone(), marginFees.u128())?;
     let feeReserves = FEERESERVED.load(_deps.storage, _collateralToken.clone())?;
 
-    FEERESERVED.save(_deps.storage, _collateralToken.clone(), &feeReserves)?;
-
-    let _event = Event::new("collect_margin_fees")
-        .add_attribute("collateral_token", _collateralToken.to_string())
-        .add_attribute("margin_fees", marginFees.to_string())
-        .add_attribute("fee_tokens", feeTokens.to_string());
+    FEERESERVED.save(
+        _deps.storage,
+        _collateralToken.clone(),
+        &feeReserves.checked_add(Uint128::new(feeTokens))?,
+    )?;
 
     _decreaseReservedAmount(
         _deps.branch(),
@@ -1730,9 +2589,7 @@ pub fn liquidatePosition(
             position.size - position.collateral,
         )?;
         let amount = Uint128::new(usd_to_token_min(
-            _deps.branch(),
-            _env.clone(),
-            _info.clone(),
+            _deps.as_ref(),
             _collateralToken.clone(),
             marginFees.u128(),
         )?);
@@ -1745,34 +2602,16 @@ pub fn liquidatePosition(
         )?;
     }
 
-    let markPrice: Uint128;
-    if _isLong {
-        markPrice = get_min_price(
-            _deps.branch(),
-            _env.clone(),
-            _info.clone(),
-            _indexToken.clone(),
-        )?;
-    } else {
-        markPrice = get_max_price(
-            _deps.branch(),
-            _env.clone(),
-            _info.clone(),
-            _indexToken.clone(),
-        )?;
-    }
     let event = Event::new("collect_margin_fees")
         .add_attribute("collateral_token", _collateralToken.clone())
         .add_attribute("margin_fees", marginFees.to_string())
         .add_attribute("fee_tokens", feeTokens.to_string());
 
     if !_isLong && marginFees < position.collateral {
-        let remaining_collateral = position.collateral.checked_sub(marginFees).unwrap();
+        let remaining_collateral = position.collateral.checked_sub(marginFees)?;
 
         let usd_to_token_min = usd_to_token_min(
-            _deps.branch(),
-            _env.clone(),
-            _info.clone(),
+            _deps.as_ref(),
             _collateralToken.clone(),
             remaining_collateral.u128(),
         )?;
@@ -1787,13 +2626,13 @@ pub fn liquidatePosition(
     }
 
     if _isLong {
+        _decreaseGlobalLongSize(_deps.branch(), _indexToken.clone(), position.size)?;
+    } else {
         _decreaseGlobalShortSize(_deps.branch(), _indexToken.clone(), position.size)?;
     }
 
     let amount = usd_to_token_min(
-        _deps.branch(),
-        _env.clone(),
-        _info.clone(),
+        _deps.as_ref(),
         _collateralToken.clone(),
         config.liquidation_fee_usd.u128(),
     )?;
@@ -1806,32 +2645,1215 @@ pub fn liquidatePosition(
         Uint128::new(amount),
     )?;
 
-    transfer_cw20_tokens(
-        _collateralToken.clone(),
-        _env.contract.address,
-        _receiver,
-        Uint128::new(amount),
+    let mut res = Response::new();
+    if amount > 0 {
+        let custody = load_custody(_deps.storage)?;
+        res = res.add_submessage(custody.pay_out(
+            _deps.storage,
+            &_env,
+            &_collateralToken,
+            &_receiver,
+            Uint128::new(amount),
+        )?);
+    }
+
+    record_liquidation(
+        _deps.branch(),
+        _info.sender.clone(),
+        config.liquidation_fee_usd,
     )?;
 
     config.include_amm_price = true;
 
     CONFIG.save(_deps.storage, &config)?;
-    POSITION.save(_deps.storage, key, &position)?;
+    POSITION.remove(_deps.storage, key);
 
-    Ok(Response::new().add_event(event))
+    Ok(res.add_event(event))
 }
 
-pub fn get_Utilisationsition_key(_deps: DepsMut, _token: Addr) -> Result<Uint128, ContractError> {
-    let poolAmount = POOLAMOUNT.load(_deps.storage, _token.clone())?;
+fn record_liquidation(
+    _deps: DepsMut,
+    liquidator: Addr,
+    fee_usd: Uint128,
+) -> Result<(), ContractError> {
+    LIQUIDATORSTATS.update(_deps.storage, liquidator, |stats| -> StdResult<_> {
+        let mut stats = stats.unwrap_or_default();
+        stats.liquidation_count += 1;
+        stats.fees_earned_usd += fee_usd;
+        Ok(stats)
+    })?;
+
+    Ok(())
+}
 
-    if poolAmount > Uint128::zero() {
-        return Ok(Uint128::zero());
+/// Leverage in basis points of `_size` over `_collateral`, which must be
+/// within MIN_LEVERAGE and the index token's max leverage.
+pub fn _validateLeverage(
+    _deps: Deps,
+    _indexToken: Addr,
+    _size: Uint128,
+    _collateral: Uint128,
+) -> Result<Uint128, ContractError> {
+    validate(_collateral > Uint128::zero(), "err")?;
+    let max_leverage = query_max_leverage(_deps, _indexToken)?;
+    let leverage = _size.multiply_ratio(BASIS_POINTS_DIVISOR, _collateral);
+
+    if leverage < MIN_LEVERAGE {
+        return Err(ContractError::LeverageTooLow {
+            leverage,
+            min_leverage: MIN_LEVERAGE,
+        });
+    }
+    if leverage > max_leverage {
+        return Err(ContractError::LeverageTooHigh {
+            leverage,
+            max_leverage,
+        });
     }
-    let reservedAmounts = RESERVEDAMOUNTS.load(_deps.storage, _token)?;
-    let res: Uint128 = reservedAmounts * FUNDING_RATE_PRECISION / poolAmount;
 
-    Ok(res)
+    Ok(leverage)
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{
+        from_binary, ContractResult, CosmosMsg, OwnedDeps, QuerierResult, SubMsgResponse,
+        SystemResult, WasmQuery,
+    };
+    use cw20::{Cw20QueryMsg, TokenInfoResponse};
+
+    use crate::msg::{
+        BalanceDriftResponse, CheckInvariantsResponse, FeeRecipientInfoResponse, Invariant,
+        InvariantViolation, ManagersResponse, PauseStatusResponse, RoleMembersResponse,
+    };
+    use crate::state::GLOBALLONGSIZE;
+
+    const GOV: &str = "gov";
+
+    fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        setup_with_custody(CustodyMode::SelfCustody)
+    }
+
+    fn setup_with_custody(custody: CustodyMode) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            _router: Addr::unchecked("router"),
+            _usdg: Addr::unchecked("usdg"),
+            _priceFeed: Addr::unchecked("price-feed"),
+            _liquidationFeeUsd: "5".to_string(),
+            _fundingRateFactor: "100".to_string(),
+            _stableFundingRateFactor: "100".to_string(),
+            _bankAddr: Addr::unchecked("bank"),
+            _custody: custody,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info(GOV, &[]), msg).unwrap();
+        deps
+    }
+
+    fn exec(
+        deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+        sender: &str,
+        msg: ExecuteMsg,
+    ) -> Result<Response, ContractError> {
+        execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), msg)
+    }
+
+    fn is_token_info(msg: &Binary) -> bool {
+        matches!(from_binary(msg), Ok(Cw20QueryMsg::TokenInfo {}))
+    }
+
+    fn token_info(decimals: u8) -> QuerierResult {
+        SystemResult::Ok(ContractResult::Ok(
+            to_binary(&TokenInfoResponse {
+                name: "Token".to_string(),
+                symbol: "TKN".to_string(),
+                decimals,
+                total_supply: Uint128::zero(),
+            })
+            .unwrap(),
+        ))
+    }
+
+    fn buy_usdg() -> ExecuteMsg {
+        ExecuteMsg::BuyUSDG {
+            _token: Addr::unchecked("token"),
+            _receiver: Addr::unchecked("receiver"),
+        }
+    }
+
+    fn sell_usdg() -> ExecuteMsg {
+        ExecuteMsg::SellUSDG {
+            _token: Addr::unchecked("token"),
+            _usdgAmount: Uint128::new(100),
+            _receiver: Addr::unchecked("receiver"),
+        }
+    }
+
+    fn role_members(
+        deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>,
+        role: Role,
+        start_after: Option<&str>,
+        limit: Option<u32>,
+    ) -> Vec<Addr> {
+        let res: RoleMembersResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::RoleMembers {
+                    role,
+                    start_after: start_after.map(str::to_string),
+                    limit,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        res.members
+    }
+
+    #[test]
+    fn gov_grants_and_revokes_roles() {
+        let mut deps = setup();
+        let keeper = Addr::unchecked("keeper");
+
+        let err = exec(
+            &mut deps,
+            "anyone",
+            ExecuteMsg::GrantRole {
+                role: Role::FeeKeeper,
+                account: keeper.to_string(),
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role } if role == "gov"));
+
+        let err = exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::GrantRole {
+                role: Role::FeeKeeper,
+                account: "Keeper".to_string(),
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::Std(_)));
+
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::GrantRole {
+                role: Role::FeeKeeper,
+                account: keeper.to_string(),
+            },
+        )
+        .unwrap();
+        assert!(has_role(deps.as_ref(), Role::FeeKeeper, keeper.clone()).unwrap());
+        assert!(!has_role(deps.as_ref(), Role::Pauser, keeper.clone()).unwrap());
+
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::RevokeRole {
+                role: Role::FeeKeeper,
+                account: keeper.to_string(),
+            },
+        )
+        .unwrap();
+        assert!(!has_role(deps.as_ref(), Role::FeeKeeper, keeper).unwrap());
+    }
+
+    #[test]
+    fn gov_role_is_not_assignable() {
+        let mut deps = setup();
+
+        let err = exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::GrantRole {
+                role: Role::Gov,
+                account: "anyone".to_string(),
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::RoleNotAssignable { .. }));
+    }
+
+    #[test]
+    fn gov_passes_every_role_check() {
+        let deps = setup();
+        let gov = Addr::unchecked(GOV);
+
+        for role in [
+            Role::RiskAdmin,
+            Role::FeeKeeper,
+            Role::Pauser,
+            Role::Liquidator,
+        ] {
+            only_role(deps.as_ref(), &gov, role).unwrap();
+        }
+        let err = only_role(deps.as_ref(), &Addr::unchecked("anyone"), Role::Pauser).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role } if role == "pauser"));
+    }
+
+    #[test]
+    fn only_gov_sets_price_feed() {
+        let mut deps = setup();
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::GrantRole {
+                role: Role::RiskAdmin,
+                account: "keeper".to_string(),
+            },
+        )
+        .unwrap();
+
+        let err = exec(
+            &mut deps,
+            "keeper",
+            ExecuteMsg::SetPriceFeed {
+                _price_feed: Addr::unchecked("other-feed"),
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role } if role == "gov"));
+
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::SetPriceFeed {
+                _price_feed: Addr::unchecked("other-feed"),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            CONFIG.load(&deps.storage).unwrap().price_feed,
+            Addr::unchecked("other-feed")
+        );
+    }
+
+    #[test]
+    fn role_members_are_paginated() {
+        let mut deps = setup();
+        for account in ["aaa", "bbb", "ccc", "ddd"] {
+            exec(
+                &mut deps,
+                GOV,
+                ExecuteMsg::GrantRole {
+                    role: Role::Pauser,
+                    account: account.to_string(),
+                },
+            )
+            .unwrap();
+        }
+
+        let page = role_members(&deps, Role::Pauser, None, Some(2));
+        assert_eq!(page, vec![Addr::unchecked("aaa"), Addr::unchecked("bbb")]);
+        let page = role_members(&deps, Role::Pauser, Some("bbb"), Some(2));
+        assert_eq!(page, vec![Addr::unchecked("ccc"), Addr::unchecked("ddd")]);
+        assert!(role_members(&deps, Role::Pauser, Some("ddd"), None).is_empty());
+        assert_eq!(
+            role_members(&deps, Role::Gov, None, None),
+            vec![Addr::unchecked(GOV)]
+        );
+    }
+
+    fn paused_operation(target: PauseTarget) -> ExecuteMsg {
+        let token = Addr::unchecked("token");
+        let receiver = Addr::unchecked("receiver");
+        match target {
+            PauseTarget::Swap => ExecuteMsg::Swap {
+                _tokenIn: token.clone(),
+                _tokenOut: Addr::unchecked("other"),
+                _receiver: receiver,
+            },
+            PauseTarget::MintBurn => buy_usdg(),
+            PauseTarget::IncreasePosition => ExecuteMsg::IncreasePosition {
+                _account: Addr::unchecked("trader"),
+                _collateralToken: token.clone(),
+                _indexToken: token,
+                _sizeDelta: Uint128::new(100),
+                _isLong: true,
+            },
+            PauseTarget::DecreasePosition => ExecuteMsg::DecreasePosition {
+                _account: Addr::unchecked("trader"),
+                _collateralToken: token.clone(),
+                _indexToken: token,
+                _collateralDelta: Uint128::zero(),
+                _sizeDelta: Uint128::new(100),
+                _isLong: true,
+                _receiver: receiver,
+            },
+            PauseTarget::Liquidation => ExecuteMsg::LiquidatePosition {
+                _account: Addr::unchecked("trader"),
+                _collateralToken: token.clone(),
+                _indexToken: token,
+                _isLong: true,
+                _receiver: receiver,
+            },
+        }
+    }
+
+    #[test]
+    fn pausing_blocks_each_operation() {
+        for target in PauseTarget::all() {
+            let mut deps = setup();
+            exec(
+                &mut deps,
+                GOV,
+                ExecuteMsg::Pause {
+                    targets: Some(vec![target]),
+                    reason: "incident".to_string(),
+                },
+            )
+            .unwrap();
+
+            let err = exec(&mut deps, "trader", paused_operation(target)).unwrap_err();
+            assert!(
+                matches!(
+                    &err,
+                    ContractError::Paused { operation, reason }
+                        if operation == target.as_str() && reason == "incident"
+                ),
+                "{target:?}: {err}"
+            );
+            for other in PauseTarget::all().into_iter().filter(|t| *t != target) {
+                when_not_paused(deps.as_ref(), other).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn pauser_pauses_and_gov_unpauses() {
+        let mut deps = setup();
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::GrantRole {
+                role: Role::Pauser,
+                account: "pauser".to_string(),
+            },
+        )
+        .unwrap();
+
+        let err = exec(
+            &mut deps,
+            "anyone",
+            ExecuteMsg::Pause {
+                targets: None,
+                reason: "incident".to_string(),
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { .. }));
+
+        exec(
+            &mut deps,
+            "pauser",
+            ExecuteMsg::Pause {
+                targets: None,
+                reason: "incident".to_string(),
+            },
+        )
+        .unwrap();
+        let status: PauseStatusResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), QueryMsg::PauseStatus {}).unwrap())
+                .unwrap();
+        assert_eq!(status.paused.len(), PauseTarget::all().len());
+        assert_eq!(status.paused[0].paused_by, Addr::unchecked("pauser"));
+
+        let err = exec(&mut deps, "pauser", ExecuteMsg::Unpause { targets: None }).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { role } if role == "gov"));
+
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::Unpause {
+                targets: Some(vec![PauseTarget::Swap]),
+            },
+        )
+        .unwrap();
+        when_not_paused(deps.as_ref(), PauseTarget::Swap).unwrap();
+        when_not_paused(deps.as_ref(), PauseTarget::MintBurn).unwrap_err();
+
+        exec(&mut deps, GOV, ExecuteMsg::Unpause { targets: None }).unwrap();
+        for target in PauseTarget::all() {
+            when_not_paused(deps.as_ref(), target).unwrap();
+        }
+    }
+
+    #[test]
+    fn manager_mode_off_allows_anyone() {
+        let deps = setup();
+
+        validate_manager(deps.as_ref(), &Addr::unchecked("anyone")).unwrap();
+    }
+
+    #[test]
+    fn manager_mode_on_blocks_non_managers() {
+        let mut deps = setup();
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::SetInManagerMode {
+                Inmanagermode: true,
+            },
+        )
+        .unwrap();
+
+        let err = exec(&mut deps, "anyone", buy_usdg()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+        let err = exec(&mut deps, "anyone", sell_usdg()).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+    }
+
+    #[test]
+    fn manager_mode_on_allows_managers() {
+        let mut deps = setup();
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::SetInManagerMode {
+                Inmanagermode: true,
+            },
+        )
+        .unwrap();
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::SetManager {
+                ismanager: true,
+                address: Addr::unchecked("manager"),
+            },
+        )
+        .unwrap();
+
+        validate_manager(deps.as_ref(), &Addr::unchecked("manager")).unwrap();
+        let err = validate_manager(deps.as_ref(), &Addr::unchecked("anyone")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::SetManager {
+                ismanager: false,
+                address: Addr::unchecked("manager"),
+            },
+        )
+        .unwrap();
+        let err = validate_manager(deps.as_ref(), &Addr::unchecked("manager")).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+    }
+
+    #[test]
+    fn manager_mode_can_be_switched_off() {
+        let mut deps = setup();
+        for mode in [true, false] {
+            exec(
+                &mut deps,
+                GOV,
+                ExecuteMsg::SetInManagerMode {
+                    Inmanagermode: mode,
+                },
+            )
+            .unwrap();
+            assert_eq!(query_config(deps.as_ref()).unwrap().in_manager_mode, mode);
+        }
+
+        validate_manager(deps.as_ref(), &Addr::unchecked("anyone")).unwrap();
+    }
+
+    #[test]
+    fn managers_query_lists_allowlist() {
+        let mut deps = setup();
+        for manager in ["manager-b", "manager-a", "manager-c"] {
+            exec(
+                &mut deps,
+                GOV,
+                ExecuteMsg::SetManager {
+                    ismanager: true,
+                    address: Addr::unchecked(manager),
+                },
+            )
+            .unwrap();
+        }
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::SetManager {
+                ismanager: false,
+                address: Addr::unchecked("manager-c"),
+            },
+        )
+        .unwrap();
+
+        let res: ManagersResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::Managers {
+                    start_after: None,
+                    limit: None,
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(!res.in_manager_mode);
+        assert_eq!(
+            res.managers,
+            vec![Addr::unchecked("manager-a"), Addr::unchecked("manager-b")]
+        );
+
+        let is_manager: bool = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::IsManager {
+                    address: Addr::unchecked("manager-c"),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(!is_manager);
+    }
+
+    #[test]
+    fn only_gov_sets_manager_mode() {
+        let mut deps = setup();
+
+        let err = exec(
+            &mut deps,
+            "anyone",
+            ExecuteMsg::SetInManagerMode {
+                Inmanagermode: true,
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { .. }));
+    }
+
+    fn set_liquidity_config(
+        deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+        sender: &str,
+        cooldown_duration: u64,
+    ) -> Result<Response, ContractError> {
+        exec(
+            deps,
+            sender,
+            ExecuteMsg::SetLiquidityConfig {
+                lp_token: Addr::unchecked("lp-token"),
+                cooldown_duration,
+            },
+        )
+    }
+
+    #[test]
+    fn liquidity_requires_config() {
+        let mut deps = setup();
+
+        let err = exec(
+            &mut deps,
+            "lp",
+            ExecuteMsg::AddLiquidity {
+                token: Addr::unchecked("token"),
+                min_usdg: Uint128::zero(),
+                min_lp: Uint128::zero(),
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::LiquidityNotConfigured {}));
+    }
+
+    #[test]
+    fn liquidity_config_is_gov_only_and_bounded() {
+        let mut deps = setup();
+
+        let err = set_liquidity_config(&mut deps, "anyone", 900).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { .. }));
+        set_liquidity_config(&mut deps, GOV, MAX_COOLDOWN_DURATION + 1).unwrap_err();
+        set_liquidity_config(&mut deps, GOV, MAX_COOLDOWN_DURATION).unwrap();
+    }
+
+    #[test]
+    fn remove_liquidity_waits_for_cooldown() {
+        let mut deps = setup();
+        set_liquidity_config(&mut deps, GOV, 900).unwrap();
+        let added_at = mock_env().block.time.seconds();
+        LASTADDEDAT
+            .save(deps.as_mut().storage, Addr::unchecked("lp"), &added_at)
+            .unwrap();
+
+        let err = exec(
+            &mut deps,
+            "lp",
+            ExecuteMsg::RemoveLiquidity {
+                token_out: Addr::unchecked("token"),
+                lp_amount: Uint128::new(100),
+                min_out: Uint128::zero(),
+                receiver: Addr::unchecked("lp"),
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::CooldownNotExpired { available_at } if available_at == added_at + 900
+        ));
+    }
+
+    #[test]
+    fn receive_only_accepts_usdg() {
+        let mut deps = setup();
+        let msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "seller".to_string(),
+            amount: Uint128::new(100),
+            msg: to_binary(&ReceiveMsg::SellUSDG {
+                _token: Addr::unchecked("token"),
+                _receiver: Addr::unchecked("seller"),
+            })
+            .unwrap(),
+        });
+
+        let err = exec(&mut deps, "not-usdg", msg).unwrap_err();
+        assert!(matches!(err, ContractError::Unauthorized {}));
+    }
+
+    fn fee_recipients(weights: &[(&str, u128)]) -> ExecuteMsg {
+        ExecuteMsg::SetFeeRecipients {
+            recipients: weights
+                .iter()
+                .map(|(address, weight)| FeeRecipient {
+                    address: Addr::unchecked(*address),
+                    weight: Uint128::new(*weight),
+                })
+                .collect(),
+            fee_token: None,
+        }
+    }
+
+    #[test]
+    fn fee_recipient_weights_must_sum_to_divisor() {
+        let mut deps = setup();
+
+        let err = exec(&mut deps, "anyone", fee_recipients(&[("lp", 10000)])).unwrap_err();
+        assert!(matches!(err, ContractError::MissingRole { .. }));
+        exec(
+            &mut deps,
+            GOV,
+            fee_recipients(&[("lp", 6000), ("treasury", 3000)]),
+        )
+        .unwrap_err();
+        exec(
+            &mut deps,
+            GOV,
+            fee_recipients(&[("lp", 5000), ("lp", 5000)]),
+        )
+        .unwrap_err();
+        exec(
+            &mut deps,
+            GOV,
+            fee_recipients(&[("lp", 7000), ("treasury", 3000)]),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn distribute_fees_splits_reserves_by_weight() {
+        let mut deps = setup();
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { contract_addr, .. } if contract_addr == "bank" => {
+                SystemResult::Ok(ContractResult::Ok(to_binary(&true).unwrap()))
+            }
+            WasmQuery::Smart { msg, .. } if is_token_info(msg) => token_info(6),
+            _ => SystemResult::Ok(ContractResult::Ok(
+                to_binary(&Uint128::new(10u128.pow(30))).unwrap(),
+            )),
+        });
+        let token = Addr::unchecked("token");
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::SetTokenConfig {
+                _token: token.clone(),
+                _tokenDecimals: Uint128::new(6),
+                _tokenWeight: Uint128::new(10000),
+                _minProfitBps: Uint128::zero(),
+                _maxUsdgAmount: Uint128::zero(),
+                _isStable: true,
+                _isShortable: false,
+            },
+        )
+        .unwrap();
+        FEERESERVED
+            .save(deps.as_mut().storage, token.clone(), &Uint128::new(1001))
+            .unwrap();
+
+        let err = exec(
+            &mut deps,
+            "anyone",
+            ExecuteMsg::DistributeFees { tokens: None },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::FeeRecipientsNotSet {}));
+
+        exec(
+            &mut deps,
+            GOV,
+            fee_recipients(&[("lp", 7000), ("treasury", 3000)]),
+        )
+        .unwrap();
+        let res = exec(
+            &mut deps,
+            "anyone",
+            ExecuteMsg::DistributeFees { tokens: None },
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 2);
+
+        let lp: FeeRecipientInfoResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::FeeRecipientInfo {
+                    recipient: Addr::unchecked("lp"),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(lp.weight, Uint128::new(7000));
+        assert_eq!(lp.distributed[0].amount, Uint128::new(700));
+        // 700 tokens of 6 decimals at $1
+        assert_eq!(lp.usd_received, Uint128::new(700 * 10u128.pow(24)));
+        let treasury = FEESDISTRIBUTED
+            .load(&deps.storage, (Addr::unchecked("treasury"), token.clone()))
+            .unwrap();
+        assert_eq!(treasury, Uint128::new(301));
+        assert!(FEERESERVED.load(&deps.storage, token).unwrap().is_zero());
+    }
+
+    #[test]
+    fn failed_payout_rolls_back_accounting() {
+        let mut deps = setup();
+        let token = Addr::unchecked("token");
+        save_pending_payout(&mut deps, 3, &token);
+        // another sale settled after this payout was queued
+        POOLAMOUNT
+            .save(deps.as_mut().storage, token.clone(), &Uint128::new(800))
+            .unwrap();
+        USDGAMOUNT
+            .save(deps.as_mut().storage, token.clone(), &Uint128::new(400))
+            .unwrap();
+        FEERESERVED
+            .save(deps.as_mut().storage, token.clone(), &Uint128::new(9))
+            .unwrap();
+        UNCLEAREDUSDGDEBT
+            .save(deps.as_mut().storage, &Uint128::new(60))
+            .unwrap();
+
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: 3,
+                result: SubMsgResult::Err("bank out of funds".to_string()),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            POOLAMOUNT.load(&deps.storage, token.clone()).unwrap(),
+            Uint128::new(900)
+        );
+        assert_eq!(
+            USDGAMOUNT.load(&deps.storage, token.clone()).unwrap(),
+            Uint128::new(450)
+        );
+        assert_eq!(
+            FEERESERVED.load(&deps.storage, token).unwrap(),
+            Uint128::new(7)
+        );
+        assert_eq!(
+            UNCLEAREDUSDGDEBT.load(&deps.storage).unwrap(),
+            Uint128::new(10)
+        );
+        assert!(PENDINGPAYOUTS.may_load(&deps.storage, 3).unwrap().is_none());
+    }
+
+    #[test]
+    fn settled_payout_is_forgotten() {
+        let mut deps = setup();
+        let token = Addr::unchecked("token");
+        save_pending_payout(&mut deps, 3, &token);
+
+        let res = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: 3,
+                result: SubMsgResult::Ok(SubMsgResponse {
+                    events: vec![],
+                    data: None,
+                }),
+            },
+        )
+        .unwrap();
+
+        assert!(res.messages.is_empty());
+        assert!(PENDINGPAYOUTS.may_load(&deps.storage, 3).unwrap().is_none());
+        let err = reply(
+            deps.as_mut(),
+            mock_env(),
+            Reply {
+                id: 3,
+                result: SubMsgResult::Err("replayed".to_string()),
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ContractError::UnknownReplyId { id: 3 }));
+    }
+
+    fn save_pending_payout(
+        deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>,
+        id: u64,
+        token: &Addr,
+    ) {
+        PENDINGPAYOUTS
+            .save(
+                deps.as_mut().storage,
+                id,
+                &PendingPayout {
+                    token: token.clone(),
+                    receiver: Addr::unchecked("seller"),
+                    amount: Uint128::new(99),
+                    pool_decrease: Uint128::new(100),
+                    usdg_decrease: Uint128::new(50),
+                    fee_reserved_increase: Uint128::new(2),
+                    token_balance_decrease: Uint128::new(99),
+                    bank_deposit_decrease: Uint128::new(99),
+                    uncleared_usdg_debt_increase: Uint128::new(50),
+                    burns: vec![TokenBurn {
+                        token: Addr::unchecked("usdg"),
+                        owner: Some(Addr::unchecked("seller")),
+                        amount: Uint128::new(100),
+                    }],
+                },
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn fee_withdrawals_follow_custody_mode() {
+        for (custody, paid_by) in [
+            (CustodyMode::SelfCustody, "token"),
+            (CustodyMode::Bank, "bank"),
+        ] {
+            let mut deps = setup_with_custody(custody);
+            let token = Addr::unchecked("token");
+            for amounts in [FEERESERVED, BANKDEPOSITS] {
+                amounts
+                    .save(deps.as_mut().storage, token.clone(), &Uint128::new(50))
+                    .unwrap();
+            }
+
+            let res = exec(
+                &mut deps,
+                GOV,
+                ExecuteMsg::WithdrawFees {
+                    _token: token.clone(),
+                    _receiver: Addr::unchecked("treasury"),
+                },
+            )
+            .unwrap();
+
+            match &res.messages[0].msg {
+                CosmosMsg::Wasm(cosmwasm_std::WasmMsg::Execute { contract_addr, .. }) => {
+                    assert_eq!(contract_addr, paid_by)
+                }
+                msg => panic!("unexpected payout {:?}", msg),
+            }
+            // only a bank payout draws on what the vault paid into the bank
+            let deposits = BANKDEPOSITS.load(&deps.storage, token).unwrap();
+            assert_eq!(deposits.is_zero(), custody == CustodyMode::Bank);
+        }
+    }
+
+    fn setup_with_donation() -> (OwnedDeps<MockStorage, MockApi, MockQuerier>, Addr) {
+        let mut deps = setup();
+        deps.querier.update_wasm(|query| match query {
+            WasmQuery::Smart { msg, .. } if is_token_info(msg) => token_info(6),
+            _ => SystemResult::Ok(ContractResult::Ok(
+                to_binary(&cw20::BalanceResponse {
+                    balance: Uint128::new(1500),
+                })
+                .unwrap(),
+            )),
+        });
+        let token = Addr::unchecked("token");
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::SetTokenConfig {
+                _token: token.clone(),
+                _tokenDecimals: Uint128::new(6),
+                _tokenWeight: Uint128::new(10000),
+                _minProfitBps: Uint128::zero(),
+                _maxUsdgAmount: Uint128::zero(),
+                _isStable: true,
+                _isShortable: false,
+            },
+        )
+        .unwrap();
+        for amounts in [TOKENBALANCE, POOLAMOUNT] {
+            amounts
+                .save(deps.as_mut().storage, token.clone(), &Uint128::new(1000))
+                .unwrap();
+        }
+        (deps, token)
+    }
+
+    #[test]
+    fn sync_adds_donations_to_pool() {
+        let (mut deps, token) = setup_with_donation();
+
+        exec(
+            &mut deps,
+            "anyone",
+            ExecuteMsg::Sync {
+                _token: token.clone(),
+            },
+        )
+        .unwrap_err();
+        exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::Sync {
+                _token: token.clone(),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            POOLAMOUNT.load(&deps.storage, token.clone()).unwrap(),
+            Uint128::new(1500)
+        );
+        assert_eq!(
+            TOKENBALANCE.load(&deps.storage, token).unwrap(),
+            Uint128::new(1500)
+        );
+    }
+
+    #[test]
+    fn skim_sends_donations_away() {
+        let (mut deps, token) = setup_with_donation();
+
+        let drift: BalanceDriftResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::BalanceDrift { tokens: None },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(drift.drifts[0].surplus, Uint128::new(500));
+
+        let res = exec(
+            &mut deps,
+            GOV,
+            ExecuteMsg::Skim {
+                _token: token.clone(),
+                _receiver: Addr::unchecked("treasury"),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(
+            POOLAMOUNT.load(&deps.storage, token).unwrap(),
+            Uint128::new(1000)
+        );
+    }
+
+    #[test]
+    fn check_invariants_reports_violations() {
+        let (mut deps, token) = setup_with_donation();
+        let check = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            from_binary::<CheckInvariantsResponse>(
+                &query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::CheckInvariants { tokens: None },
+                )
+                .unwrap(),
+            )
+            .unwrap()
+            .violations
+        };
+
+        // a donation on top of the pool is not a violation, and neither is a
+        // buffer the pool hasn't reached yet
+        BUFFERAMOUNT
+            .save(deps.as_mut().storage, token.clone(), &Uint128::new(5000))
+            .unwrap();
+        assert_eq!(check(&deps), vec![]);
+
+        RESERVEDAMOUNTS
+            .save(deps.as_mut().storage, token.clone(), &Uint128::new(1200))
+            .unwrap();
+        FEERESERVED
+            .save(deps.as_mut().storage, token.clone(), &Uint128::new(600))
+            .unwrap();
+
+        assert_eq!(
+            check(&deps),
+            vec![
+                InvariantViolation {
+                    token: token.clone(),
+                    invariant: Invariant::PoolCoversReserved,
+                    actual: Uint128::new(1000),
+                    required: Uint128::new(1200),
+                },
+                InvariantViolation {
+                    token: token.clone(),
+                    invariant: Invariant::BalanceCoversPoolAndFees,
+                    actual: Uint128::new(1500),
+                    required: Uint128::new(1600),
+                },
+            ]
+        );
+
+        // fees that overflow the sum are a violation rather than a failed query
+        FEERESERVED
+            .save(deps.as_mut().storage, token.clone(), &Uint128::MAX)
+            .unwrap();
+        assert!(check(&deps).contains(&InvariantViolation {
+            token,
+            invariant: Invariant::BalanceCoversPoolAndFees,
+            actual: Uint128::new(1500),
+            required: Uint128::MAX,
+        }));
+    }
+
+    #[test]
+    fn bank_custody_covers_only_what_the_vault_paid_in() {
+        let mut deps = setup_with_custody(CustodyMode::Bank);
+        // the bank holds plenty, most of it for other clients
+        deps.querier.update_wasm(|_| {
+            SystemResult::Ok(ContractResult::Ok(
+                to_binary(&cw20::BalanceResponse {
+                    balance: Uint128::new(1_000_000),
+                })
+                .unwrap(),
+            ))
+        });
+        let token = Addr::unchecked("token");
+        POOLAMOUNT
+            .save(deps.as_mut().storage, token.clone(), &Uint128::new(1000))
+            .unwrap();
+        BANKDEPOSITS
+            .save(deps.as_mut().storage, token.clone(), &Uint128::new(400))
+            .unwrap();
+
+        let report: CheckInvariantsResponse = from_binary(
+            &query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::CheckInvariants {
+                    tokens: Some(vec![token.clone()]),
+                },
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(report.violations.contains(&InvariantViolation {
+            token,
+            invariant: Invariant::BalanceCoversPoolAndFees,
+            actual: Uint128::new(400),
+            required: Uint128::new(1000),
+        }));
+    }
+
+    #[test]
+    fn accounting_totals_overflow_as_errors() {
+        let mut deps = setup();
+        let token = Addr::unchecked("token");
+        GLOBALLONGSIZE
+            .save(deps.as_mut().storage, token.clone(), &Uint256::MAX)
+            .unwrap();
+        GLOBALSHORTSIZE
+            .save(deps.as_mut().storage, token.clone(), &Uint256::MAX)
+            .unwrap();
+        FEERESERVED
+            .save(deps.as_mut().storage, token.clone(), &Uint128::MAX)
+            .unwrap();
+
+        let err =
+            _increaseGlobalLongSize(deps.as_mut(), token.clone(), Uint128::one()).unwrap_err();
+        assert!(matches!(err, ContractError::Overflow(_)));
+        let err =
+            _increaseGlobalShortSize(deps.as_mut(), token.clone(), Uint128::one()).unwrap_err();
+        assert!(matches!(err, ContractError::Overflow(_)));
+        let err =
+            collect_fees(deps.as_mut(), token, Uint128::new(100), Uint128::new(30)).unwrap_err();
+        assert!(matches!(err, ContractError::Overflow(_)));
+    }
+
+    #[test]
+    fn migrate_keeps_totals_stored_as_uint128() {
+        let mut deps = setup();
+        let token = Addr::unchecked("token");
+        let legacy: cw_storage_plus::Map<Addr, Uint128> =
+            cw_storage_plus::Map::new("guarantee-usdt");
+        legacy
+            .save(deps.as_mut().storage, token.clone(), &Uint128::MAX)
+            .unwrap();
+
+        migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap();
+
+        assert_eq!(
+            GUARANTEEUSD.load(&deps.storage, token).unwrap(),
+            Uint256::from(Uint128::MAX)
+        );
+        assert_eq!(
+            get_contract_version(&deps.storage).unwrap().version,
+            CONTRACT_VERSION
+        );
+    }
+
+    #[test]
+    fn migrate_rejects_other_contracts() {
+        let mut deps = setup();
+        set_contract_version(deps.as_mut().storage, "crates.io:other", "1.0.0").unwrap();
+
+        let err = migrate(deps.as_mut(), mock_env(), MigrateMsg {}).unwrap_err();
+        assert!(matches!(err, ContractError::CannotMigrate { .. }));
+    }
+
+    #[test]
+    fn accounting_underflows_are_errors() {
+        let (mut deps, token) = setup_with_donation();
+
+        let err = _decreasePoolAmount(
+            deps.as_mut(),
+            mock_env(),
+            mock_info(GOV, &[]),
+            token.clone(),
+            Uint128::new(1001),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ContractError::InsufficientPoolAmount { pool, .. } if pool == Uint128::new(1000)
+        ));
+
+        let err = _decreaseGuaranteedUsd(deps.as_mut(), token, Uint128::one()).unwrap_err();
+        assert!(matches!(err, ContractError::Overflow(_)));
+    }
+}
@@ -0,0 +1,171 @@
+use cosmwasm_std::{Addr, Deps, Env, StdResult, Storage, SubMsg, Uint128};
+use cw20::{BalanceResponse, Cw20QueryMsg};
+
+use crate::bank;
+use crate::helpers::transfer_cw20_tokens;
+use crate::state::{CustodyMode, BANKDEPOSITS, STATE, TOKENBALANCE};
+use crate::ContractError;
+
+/// Holds the pool's tokens, taking them in and paying them out on the vault's
+/// behalf. Implementations keep the vault's TOKENBALANCE cache, and the bank
+/// its BANKDEPOSITS, in step.
+pub trait Custody {
+    /// Tokens held in custody, which must cover the pool.
+    fn balance(&self, deps: Deps, env: &Env, token: &Addr) -> StdResult<Uint128>;
+
+    /// Takes `amount` of `token` the vault has just received into custody.
+    fn pay_in(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        token: &Addr,
+        amount: Uint128,
+    ) -> Result<Vec<SubMsg>, ContractError>;
+
+    fn pay_out(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        token: &Addr,
+        recipient: &Addr,
+        amount: Uint128,
+    ) -> Result<SubMsg, ContractError>;
+}
+
+/// The vault keeps its cw20 balances and transfers them out directly, so
+/// every payout lowers the cached balance.
+pub struct SelfCustody;
+
+impl Custody for SelfCustody {
+    fn balance(&self, deps: Deps, env: &Env, token: &Addr) -> StdResult<Uint128> {
+        cw20_balance(deps, token, &env.contract.address)
+    }
+
+    fn pay_in(
+        &self,
+        _storage: &mut dyn Storage,
+        _env: &Env,
+        _token: &Addr,
+        _amount: Uint128,
+    ) -> Result<Vec<SubMsg>, ContractError> {
+        Ok(vec![])
+    }
+
+    fn pay_out(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        token: &Addr,
+        recipient: &Addr,
+        amount: Uint128,
+    ) -> Result<SubMsg, ContractError> {
+        let balance = TOKENBALANCE
+            .may_load(storage, token.clone())?
+            .unwrap_or_default();
+        TOKENBALANCE.save(storage, token.clone(), &balance.saturating_sub(amount))?;
+
+        let msg = transfer_cw20_tokens(
+            token.clone(),
+            env.contract.address.clone(),
+            recipient.clone(),
+            amount,
+        )?;
+        Ok(SubMsg::new(msg))
+    }
+}
+
+/// An external bank contract holds the funds: deposits are forwarded to it
+/// through `PayIn` and it pays out through `PayOut`.
+pub struct BankCustody {
+    pub bank_addr: Addr,
+}
+
+impl Custody for BankCustody {
+    /// Deposits count from when their `PayIn` is queued, so the vault pays
+    /// them in before growing the pool.
+    fn balance(&self, deps: Deps, _env: &Env, token: &Addr) -> StdResult<Uint128> {
+        Ok(BANKDEPOSITS
+            .may_load(deps.storage, token.clone())?
+            .unwrap_or_default())
+    }
+
+    fn pay_in(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        token: &Addr,
+        amount: Uint128,
+    ) -> Result<Vec<SubMsg>, ContractError> {
+        if amount.is_zero() {
+            return Ok(vec![]);
+        }
+        let balance = TOKENBALANCE
+            .may_load(storage, token.clone())?
+            .unwrap_or_default();
+        TOKENBALANCE.save(storage, token.clone(), &balance.saturating_sub(amount))?;
+        let deposits = BANKDEPOSITS
+            .may_load(storage, token.clone())?
+            .unwrap_or_default()
+            .checked_add(amount)?;
+        BANKDEPOSITS.save(storage, token.clone(), &deposits)?;
+
+        Ok(vec![bank::pay_in_cw20(
+            env.clone(),
+            self.bank_addr.clone(),
+            token.clone(),
+            amount.u128(),
+        )?])
+    }
+
+    fn pay_out(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        token: &Addr,
+        recipient: &Addr,
+        amount: Uint128,
+    ) -> Result<SubMsg, ContractError> {
+        let deposits = BANKDEPOSITS
+            .may_load(storage, token.clone())?
+            .unwrap_or_default();
+        let remaining =
+            deposits
+                .checked_sub(amount)
+                .map_err(|_| ContractError::InsufficientBankDeposits {
+                    token: token.to_string(),
+                    deposits,
+                    amount,
+                })?;
+        BANKDEPOSITS.save(storage, token.clone(), &remaining)?;
+
+        Ok(bank::pay_out(
+            env.clone(),
+            self.bank_addr.clone(),
+            0,
+            token.to_string(),
+            recipient.clone(),
+            amount.u128(),
+        )?)
+    }
+}
+
+pub fn load_custody(storage: &dyn Storage) -> StdResult<Box<dyn Custody>> {
+    let state = STATE.load(storage)?;
+    let custody: Box<dyn Custody> = match state.custody {
+        CustodyMode::SelfCustody => Box::new(SelfCustody),
+        CustodyMode::Bank => Box::new(BankCustody {
+            bank_addr: state.bank_addr,
+        }),
+    };
+    Ok(custody)
+}
+
+fn cw20_balance(deps: Deps, token: &Addr, holder: &Addr) -> StdResult<Uint128> {
+    let res: BalanceResponse = deps.querier.query_wasm_smart(
+        token,
+        &Cw20QueryMsg::Balance {
+            address: holder.to_string(),
+        },
+    )?;
+    Ok(res.balance)
+}
@@ -1,4 +1,6 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{
+    ConversionOverflowError, DivideByZeroError, OverflowError, StdError, Uint128, Uint256,
+};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,8 +8,108 @@ pub enum ContractError {
     #[error("{0}")]
     Std(#[from] StdError),
 
+    #[error("{0}")]
+    ConversionOverflow(#[from] ConversionOverflowError),
+
+    #[error("{0}")]
+    Overflow(#[from] OverflowError),
+
+    #[error("{0}")]
+    DivideByZero(#[from] DivideByZeroError),
+
     #[error("Unauthorized")]
     Unauthorized {},
+
+    #[error("Unauthorized: missing role {role}")]
+    MissingRole { role: String },
+
+    #[error("Role {role} cannot be granted or revoked")]
+    RoleNotAssignable { role: String },
+
+    #[error("Leverage {leverage} exceeds max leverage {max_leverage}")]
+    LeverageTooHigh {
+        leverage: Uint128,
+        max_leverage: Uint128,
+    },
+
+    #[error("Leverage {leverage} is below min leverage {min_leverage}")]
+    LeverageTooLow {
+        leverage: Uint128,
+        min_leverage: Uint128,
+    },
+
+    #[error("Reserved amount {reserved} of {token} exceeds available pool {available}")]
+    InsufficientPoolCapacity {
+        token: String,
+        reserved: Uint128,
+        available: Uint128,
+    },
+
+    #[error("Cannot release {amount} of {token}: only {reserved} reserved")]
+    InsufficientReserve {
+        token: String,
+        reserved: Uint128,
+        amount: Uint128,
+    },
+
+    #[error("Cannot pay out {amount} of {token}: the bank only holds {deposits} for the vault")]
+    InsufficientBankDeposits {
+        token: String,
+        deposits: Uint128,
+        amount: Uint128,
+    },
+
+    #[error("Cannot remove {amount} of {token}: pool only holds {pool}")]
+    InsufficientPoolAmount {
+        token: String,
+        pool: Uint128,
+        amount: Uint128,
+    },
+
+    #[error("Global short size {size} of {token} exceeds max {max_size}")]
+    MaxGlobalShortSizeExceeded {
+        token: String,
+        size: Uint256,
+        max_size: Uint256,
+    },
+
+    #[error("Global long size {size} of {token} exceeds max {max_size}")]
+    MaxGlobalLongSizeExceeded {
+        token: String,
+        size: Uint256,
+        max_size: Uint256,
+    },
+
+    #[error("Operation {operation} is paused: {reason}")]
+    Paused { operation: String, reason: String },
+
+    #[error("Cannot migrate from contract {contract}")]
+    CannotMigrate { contract: String },
+
+    #[error("Unknown reply id {id}")]
+    UnknownReplyId { id: u64 },
+
+    #[error("Fee recipients are not configured")]
+    FeeRecipientsNotSet {},
+
+    #[error("Liquidity is not configured")]
+    LiquidityNotConfigured {},
+
+    #[error("Cooldown not expired: liquidity can be removed at {available_at}")]
+    CooldownNotExpired { available_at: u64 },
+
+    #[error("Insufficient output: got {amount}, expected at least {min_amount}")]
+    InsufficientOutput {
+        amount: Uint128,
+        min_amount: Uint128,
+    },
+
+    #[error("Token {token} has {decimals} decimals, not {configured}")]
+    TokenDecimalsMismatch {
+        token: String,
+        decimals: u8,
+        configured: Uint128,
+    },
     // Add any other custom errors you like here.
     // Look at https://docs.rs/thiserror/1.0.21/thiserror/ for details.
 }
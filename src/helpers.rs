@@ -2,17 +2,21 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use cosmwasm_std::{
-    to_binary, Addr, CosmosMsg, DepsMut, Env, Event, MessageInfo,
-    QueryRequest, Response, StdError, StdResult, Uint128, WasmMsg, WasmQuery,
+    to_binary, Addr, CosmosMsg, Deps, DepsMut, Env, Event, MessageInfo, QueryRequest, Response,
+    StdError, StdResult, Uint128, Uint256, WasmMsg, WasmQuery,
 };
-use cw20::{BalanceResponse, Cw20ExecuteMsg};
+use cw20::{BalanceResponse, Cw20ExecuteMsg, TokenInfoResponse};
 
 use crate::{
+    custody::load_custody,
     msg::ExecuteMsg,
+    pricing::usd_to_token_min,
+    query::has_role,
     state::{
-        FEERESERVED, GLOBALSHORTAVERAGEPRICE, GLOBALSHORTSIZE, GUARANTEEUSD, MAXUSDGAMOUNT,
-        POOLAMOUNT, RESERVEDAMOUNTS, SHORTABLETOKEN, STABLETOKEN, TOKENBALANCE,
-        USDGAMOUNT, WHITELISTEDTOKEN,
+        PauseTarget, Role, BUFFERAMOUNT, CONFIG, FEERESERVED, GLOBALLONGSIZE, GLOBALSHORTSIZE,
+        GUARANTEEUSD, MAXGLOBALLONGSIZE, MAXGLOBALSHORTSIZE, MAXUSDGAMOUNT, PAUSED, POOLAMOUNT,
+        RESERVEDAMOUNTS, SHORTABLETOKEN, STABLETOKEN, TOKENBALANCE, UNCLEAREDUSDGDEBT, USDGAMOUNT,
+        WHITELISTEDTOKEN,
     },
     ContractError,
 };
@@ -48,6 +52,37 @@ pub fn validate(condition: bool, error_message: &str) -> StdResult<()> {
     }
 }
 
+/// Gov passes every role check; anyone else must hold `role` explicitly.
+pub fn only_role(deps: Deps, sender: &Addr, role: Role) -> Result<(), ContractError> {
+    if has_role(deps, Role::Gov, sender.clone())? || has_role(deps, role, sender.clone())? {
+        Ok(())
+    } else {
+        Err(ContractError::MissingRole {
+            role: role.as_str().to_string(),
+        })
+    }
+}
+
+/// In manager mode only addresses flagged in ISMANAGER may mint or burn USDG.
+pub fn validate_manager(deps: Deps, sender: &Addr) -> Result<(), ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if config.in_manager_mode && !has_role(deps, Role::Manager, sender.clone())? {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    Ok(())
+}
+
+pub fn when_not_paused(deps: Deps, target: PauseTarget) -> Result<(), ContractError> {
+    match PAUSED.may_load(deps.storage, target.as_str().to_string())? {
+        Some(pause_info) => Err(ContractError::Paused {
+            operation: target.as_str().to_string(),
+            reason: pause_info.reason,
+        }),
+        None => Ok(()),
+    }
+}
+
 pub fn _increaseUsdgAmount(
     _deps: DepsMut,
     _env: Env,
@@ -55,14 +90,16 @@ pub fn _increaseUsdgAmount(
     _token: Addr,
     _amount: Uint128,
 ) -> Result<Response, ContractError> {
-    let usdgamount = USDGAMOUNT.load(_deps.storage, _token.clone())?;
+    let usdgamount = USDGAMOUNT
+        .load(_deps.storage, _token.clone())?
+        .checked_add(_amount)?;
 
-    USDGAMOUNT.save(_deps.storage, _token.clone(), &(usdgamount + _amount))?;
+    USDGAMOUNT.save(_deps.storage, _token.clone(), &usdgamount)?;
 
     let maxUsdgAmount = MAXUSDGAMOUNT.load(_deps.storage, _token.clone())?;
 
     if maxUsdgAmount != Uint128::zero() {
-        validate(usdgamount <= maxUsdgAmount, "err")?;
+        validate(usdgamount <= maxUsdgAmount, "ERR_MAX_USDG_AMOUNT_EXCEEDED")?;
     };
 
     let event = Event::new("IncreaseUsdgAmount")
@@ -84,9 +121,17 @@ pub fn _decreaseUsdgAmount(
 
     if usdgamount < _amount {
         USDGAMOUNT.save(_deps.storage, _token.clone(), &Uint128::zero())?;
+        // the rest stays on the books so the USDG supply can be reconciled
+        let uncleared = _amount - usdgamount;
+        let total_uncleared = UNCLEAREDUSDGDEBT
+            .may_load(_deps.storage)?
+            .unwrap_or_default()
+            .checked_add(uncleared)?;
+        UNCLEAREDUSDGDEBT.save(_deps.storage, &total_uncleared)?;
         let event = Event::new("DecreaseUsdgAmount")
             .add_attribute("token", _token.as_str())
-            .add_attribute("amount", _amount.to_string());
+            .add_attribute("amount", _amount.to_string())
+            .add_attribute("uncleared", uncleared.to_string());
         return Ok(Response::new().add_event(event));
     } else {
     }
@@ -121,13 +166,79 @@ pub fn transfer_cw20_tokens(
     Ok(exec_msg)
 }
 
+pub fn mint_cw20_tokens(
+    contract_address: Addr,
+    recipient_address: Addr,
+    amount: Uint128,
+) -> Result<CosmosMsg, ContractError> {
+    let mint_msg = Cw20ExecuteMsg::Mint {
+        recipient: recipient_address.into_string(),
+        amount,
+    };
+
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: contract_address.to_string(),
+        msg: to_binary(&mint_msg)?,
+        funds: vec![],
+    }))
+}
+
+/// Burns from `owner` through its allowance, or from the vault's own balance
+/// when there is no owner.
+pub fn burn_cw20_tokens(
+    contract_address: Addr,
+    owner: Option<Addr>,
+    amount: Uint128,
+) -> Result<CosmosMsg, ContractError> {
+    let burn_msg = match owner {
+        Some(owner) => Cw20ExecuteMsg::BurnFrom {
+            owner: owner.into_string(),
+            amount,
+        },
+        None => Cw20ExecuteMsg::Burn { amount },
+    };
+
+    Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: contract_address.to_string(),
+        msg: to_binary(&burn_msg)?,
+        funds: vec![],
+    }))
+}
+
+pub fn cw20_balance_of(
+    _deps: Deps,
+    contract_address: Addr,
+    account: Addr,
+) -> Result<Uint128, ContractError> {
+    let query_msg = QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: contract_address.into_string(),
+        msg: to_binary(&cw20::Cw20QueryMsg::Balance {
+            address: account.into_string(),
+        })?,
+    });
+
+    let query_result: BalanceResponse = _deps.querier.query(&query_msg)?;
+
+    Ok(query_result.balance)
+}
+
+/// Amount of `_token` received since TOKENBALANCE was last updated; the cache
+/// is moved to the current balance.
+pub fn transfer_in(_deps: DepsMut, _env: &Env, _token: Addr) -> Result<Uint128, ContractError> {
+    let prev_balance = TOKENBALANCE
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+    let next_balance = balance_cw20_tokens(&_deps, _env.clone(), _token.clone())?;
+    TOKENBALANCE.save(_deps.storage, _token, &next_balance)?;
+
+    Ok(next_balance.saturating_sub(prev_balance))
+}
+
 pub fn balance_cw20_tokens(
     _deps: &DepsMut,
     _env: Env,
     contract_address: Addr,
 ) -> Result<Uint128, ContractError> {
-    let _prev_balance = TOKENBALANCE.load(_deps.storage, contract_address.clone())?;
-
     let query_msg = QueryRequest::Wasm(WasmQuery::Smart {
         contract_addr: contract_address.clone().into_string(),
         msg: to_binary(&cw20::Cw20QueryMsg::Balance {
@@ -141,6 +252,31 @@ pub fn balance_cw20_tokens(
     Ok(balance)
 }
 
+pub fn total_supply_cw20_tokens(
+    _deps: Deps,
+    contract_address: Addr,
+) -> Result<Uint128, ContractError> {
+    let query_msg = QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: contract_address.into_string(),
+        msg: to_binary(&cw20::Cw20QueryMsg::TokenInfo {})?,
+    });
+
+    let query_result: TokenInfoResponse = _deps.querier.query(&query_msg)?;
+
+    Ok(query_result.total_supply)
+}
+
+pub fn decimals_cw20_tokens(_deps: Deps, contract_address: Addr) -> Result<u8, ContractError> {
+    let query_msg = QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: contract_address.into_string(),
+        msg: to_binary(&cw20::Cw20QueryMsg::TokenInfo {})?,
+    });
+
+    let query_result: TokenInfoResponse = _deps.querier.query(&query_msg)?;
+
+    Ok(query_result.decimals)
+}
+
 pub fn _increasePoolAmount(
     _deps: DepsMut,
     _env: Env,
@@ -148,12 +284,13 @@ pub fn _increasePoolAmount(
     _token: Addr,
     _amount: Uint128,
 ) -> Result<Response, ContractError> {
-    let poolAmount = POOLAMOUNT.load(_deps.storage, _token.clone())?;
-    POOLAMOUNT.save(_deps.storage, _token.clone(), &(poolAmount + _amount))?;
+    let poolAmount = POOLAMOUNT
+        .load(_deps.storage, _token.clone())?
+        .checked_add(_amount)?;
+    POOLAMOUNT.save(_deps.storage, _token.clone(), &poolAmount)?;
 
-    let balance = balance_cw20_tokens(&_deps, _env, _token.clone())?;
-    let _poolAmount_next = POOLAMOUNT.load(_deps.storage, _token.clone())?;
-    validate(poolAmount <= balance, "error_message")?;
+    let balance = load_custody(_deps.storage)?.balance(_deps.as_ref(), &_env, &_token)?;
+    validate(poolAmount <= balance, "ERR_INVALID_INCREASE")?;
 
     let event = Event::new("IncreasePoolAmount")
         .add_attribute("token", _token.as_str())
@@ -170,53 +307,6 @@ pub fn updateCumulativeFundingRate(
     Ok(true)
 }
 
-pub fn get_min_price(
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _token: Addr,
-) -> Result<Uint128, ContractError> {
-    unimplemented!()
-}
-pub fn get_max_price(
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _token: Addr,
-) -> Result<Uint128, ContractError> {
-    unimplemented!()
-}
-
-pub fn getBuyUsdgFeeBasisPoints(
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _token: Addr,
-    _usdgAmount: Uint128,
-) -> Result<Uint128, ContractError> {
-    unimplemented!()
-}
-pub fn getSellUsdgFeeBasisPoints(
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _token: Addr,
-    _usdgAmount: Uint128,
-) -> Result<Uint128, ContractError> {
-    unimplemented!()
-}
-
-pub fn getSwapFeeBasisPoints(
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _tokenin: Addr,
-    _tokenout: Addr,
-    _usdgAmount: Uint128,
-) -> Result<Uint128, ContractError> {
-    unimplemented!()
-}
-
 pub fn _decreasePoolAmount(
     _deps: DepsMut,
     _env: Env,
@@ -224,12 +314,8 @@ pub fn _decreasePoolAmount(
     _token: Addr,
     _amount: Uint128,
 ) -> Result<Response, ContractError> {
-    let poolAmount = POOLAMOUNT.load(_deps.storage, _token.clone())?;
-    POOLAMOUNT.save(_deps.storage, _token.clone(), &(poolAmount - _amount))?;
-
-    let balance = RESERVEDAMOUNTS.load(_deps.storage, _token.clone())?;
-    let poolAmount_next = POOLAMOUNT.load(_deps.storage, _token.clone())?;
-    validate(balance <= poolAmount_next, "error_message")?;
+    let nextAmount = _validatePoolDecrease(_deps.as_ref(), _token.clone(), _amount)?;
+    POOLAMOUNT.save(_deps.storage, _token.clone(), &nextAmount)?;
 
     let event = Event::new("DecreasePoolAmount")
         .add_attribute("token", _token.as_str())
@@ -238,19 +324,32 @@ pub fn _decreasePoolAmount(
     Ok(Response::new().add_event(event))
 }
 
-pub fn update_token_bal(
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _address: Addr,
-) -> Result<Response, ContractError> {
-    let bal: Uint128 = balance_cw20_tokens(&_deps, _env, _address.clone())?;
-    TOKENBALANCE.save(_deps.storage, _address, &bal);
-    Ok(Response::new())
+/// Pool amount left after taking `_amount` out, which must still back the
+/// token's reserves.
+pub fn _validatePoolDecrease(
+    _deps: Deps,
+    _token: Addr,
+    _amount: Uint128,
+) -> Result<Uint128, ContractError> {
+    let poolAmount = POOLAMOUNT.load(_deps.storage, _token.clone())?;
+    let nextAmount =
+        poolAmount
+            .checked_sub(_amount)
+            .map_err(|_| ContractError::InsufficientPoolAmount {
+                token: _token.to_string(),
+                pool: poolAmount,
+                amount: _amount,
+            })?;
+    let reserved = RESERVEDAMOUNTS
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+    _validateReserveCapacityWith(_deps, _token, reserved, nextAmount)?;
+
+    Ok(nextAmount)
 }
 
 pub fn _validateTokens(
-    _deps: &DepsMut,
+    _deps: Deps,
     collateral_token: Addr,
     index_token: Addr,
     is_long: bool,
@@ -267,209 +366,34 @@ pub fn _validateTokens(
         validate(!stable_tokens, "ERR_COLLATERAL_STABLE")?;
         return Ok(());
     } else {
+        // shorts are backed by a stable collateral and pay profits out of its pool
         let whitelisted_tokens = WHITELISTEDTOKEN.load(_deps.storage, collateral_token.clone())?;
         validate(whitelisted_tokens, "ERR_COLLATERAL_NOT_WHITELISTED")?;
         let stable_tokens = STABLETOKEN.load(_deps.storage, collateral_token.clone())?;
 
-        validate(!stable_tokens, "ERR_COLLATERAL_STABLE")?;
+        validate(stable_tokens, "ERR_COLLATERAL_NOT_STABLE")?;
 
-        let shortable_token = SHORTABLETOKEN.load(_deps.storage, collateral_token.clone())?;
-        validate(shortable_token, "ERR_COLLATERAL_STABLE")?;
+        let stable_tokens = STABLETOKEN.load(_deps.storage, index_token.clone())?;
 
-        let stable_tokens = STABLETOKEN.load(_deps.storage, index_token)?;
+        validate(!stable_tokens, "ERR_INDEX_STABLE")?;
 
-        validate(!stable_tokens, "ERR_COLLATERAL_STABLE")?;
+        let shortable_token = SHORTABLETOKEN.load(_deps.storage, index_token)?;
+        validate(shortable_token, "ERR_INDEX_NOT_SHORTABLE")?;
 
         Ok(())
     }
 }
 
-pub fn get_next_average_price(
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _index_token: Addr,
-    _size: Uint128,
-    _average_price: Uint128,
-    _is_long: bool,
-    _next_price: Uint128,
-    _size_delta: Uint128,
-    _last_increased_time: u64,
-) -> Result<Uint128, ContractError> {
-    let (has_profit, delta) = get_delta(
-        _deps,
-        _env,
-        _info,
-        _index_token,
-        _size,
-        _average_price,
-        _is_long,
-        _last_increased_time,
-    )?;
-
-    let next_size = _size + _size_delta;
-    let divisor = if _is_long {
-        if has_profit {
-            next_size + delta
-        } else {
-            next_size - delta
-        }
-    } else {
-        if has_profit {
-            next_size - delta
-        } else {
-            next_size + delta
-        }
-    };
-
-    let next_average_price: Uint128 = _next_price * next_size / divisor;
-
-    Ok(next_average_price)
-}
-
-pub fn get_delta(
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _index_token: Addr,
-    _size: Uint128,
-    _average_price: Uint128,
-    _is_long: bool,
-    _last_increased_time: u64,
-) -> Result<(bool, Uint128), ContractError> {
-    validate(_average_price > Uint128::zero(), "ERR_AVERAGE_PRICE_ZERO")?;
-
-    let price = if _is_long {
-        get_min_price(_deps, _env.clone(), _info.clone(), _index_token.clone())?
-    } else {
-        get_max_price(_deps, _env.clone(), _info.clone(), _index_token.clone())?
-    };
-
-    let price_delta = if _average_price > price {
-        _average_price - price
-    } else {
-        price - _average_price
-    };
-
-    let delta: Uint128 = _size * price_delta / _average_price;
-
-    let has_profit = if _is_long {
-        price > _average_price
-    } else {
-        _average_price > price
-    };
-
-    // Define constants for BASIS_POINTS_DIVISOR and minProfitTime
-    const BASIS_POINTS_DIVISOR: Uint128 = Uint128::new(10000);
-    const MIN_PROFIT_TIME: u64 = 3600; // Placeholder value in seconds
-
-    // Placeholder for minProfitBasisPoints
-    let min_profit_basis_points: Uint128 = Uint128::new(500); // Placeholder value
-
-    let min_bps: Uint128 =
-        if _env.clone().block.time.seconds() as u64 > _last_increased_time + MIN_PROFIT_TIME {
-            Uint128::zero()
-        } else {
-            min_profit_basis_points
-        };
-
-    if has_profit && delta * BASIS_POINTS_DIVISOR <= min_bps {
-        return Ok((has_profit, Uint128::zero()));
-    }
-
-    Ok((has_profit, delta))
-}
-
-pub fn get_position_fee(
-    _account: Addr,
-    _collateral_token: Addr,
-    _index_token: Addr,
-    _is_long: bool,
-    _size_delta: u128,
-) -> Uint128 {
-    // Your implementation here
-    unimplemented!()
-}
-
-// Placeholder for getFundingFee implementation
-pub fn get_funding_fee(
-    _account: Addr,
-    _collateral_token: Addr,
-    _index_token: Addr,
-    _is_long: bool,
-    _size: u128,
-    _entry_funding_rate: u128,
-) -> Uint128 {
-    // Your implementation here
-    unimplemented!()
-}
-
-pub fn usd_to_token_min(
-    mut deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _token: Addr,
-    _usd_amount: u128,
-) -> StdResult<u128> {
-    if _usd_amount == 0 {
-        return Ok(0);
-    }
-    let max_price =
-        get_max_price(deps.branch(), _env.clone(), _info.clone(), _token.clone()).unwrap();
-    let result = usd_to_token(deps, &_token, _usd_amount, max_price.u128())?;
-    Ok(result)
-}
-
-pub fn usd_to_token(
-    _deps: DepsMut,
-    _token: &Addr,
-    _usd_amount: u128,
-    _price: u128,
-) -> StdResult<u128> {
-    if _usd_amount == 0 {
-        return Ok(0);
-    }
-    let decimals: u32 = 6;
-    let result: u128 = _usd_amount * 10u128.pow(decimals) / _price;
-    Ok(result)
-}
-
+/// Moves `_fee_usd` worth of collateral into the fee reserves.
 pub fn _collect_margin_fees(
-    mut deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _account: Addr,
+    deps: DepsMut,
     _collateral_token: Addr,
-    _index_token: Addr,
-    _is_long: bool,
-    _size_delta: u128,
-    _size: u128,
-    _entry_funding_rate: u128,
-) -> Result<Response, ContractError> {
-    let mut feeUsd = get_position_fee(
-        _account.clone().clone(),
-        _collateral_token.clone(),
-        _index_token.clone(),
-        _is_long,
-        _size_delta,
-    );
-    let fundingFee = get_funding_fee(
-        _account,
-        _collateral_token.clone(),
-        _index_token.clone(),
-        _is_long,
-        _size,
-        _entry_funding_rate,
-    );
-
-    feeUsd = feeUsd + fundingFee;
-
+    _fee_usd: Uint128,
+) -> Result<Uint128, ContractError> {
     let feeTokens: Uint128 = Uint128::new(usd_to_token_min(
-        deps.branch(),
-        _env,
-        _info,
+        deps.as_ref(),
         _collateral_token.clone(),
-        feeUsd.u128(),
+        _fee_usd.u128(),
     )?);
 
     let feeReserves = FEERESERVED.load(deps.storage, _collateral_token.clone())?;
@@ -477,66 +401,68 @@ pub fn _collect_margin_fees(
     FEERESERVED.save(
         deps.storage,
         _collateral_token.clone(),
-        &(feeReserves + feeTokens),
-    );
-
-    let event = Event::new("collect_margin_fees")
-        .add_attribute("collateral_token", _collateral_token.as_str())
-        .add_attribute("fee_usd", feeUsd.to_string())
-        .add_attribute("fee_tokens", feeTokens.to_string());
+        &feeReserves.checked_add(feeTokens)?,
+    )?;
 
-    Ok(Response::new()
-        .add_attribute("feeReserves", feeReserves.to_string())
-        .add_event(event))
+    Ok(feeTokens)
 }
 
-pub fn token_to_usd_min(
-    mut deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _token: Addr,
-    _token_amount: u128,
-) -> Result<Uint128, ContractError> {
-    if _token_amount == 0 {
-        return Ok(Uint128::zero());
-    }
-    let price: Uint128 = get_min_price(deps.branch(), _env.clone(), _info.clone(), _token.clone())?;
-    let decimals = get_max_price(deps, _env.clone(), _info.clone(), _token.clone())?;
-    let result: u128 = _token_amount * price.u128() / 10u128.pow(decimals.u128() as u32);
-    Ok(Uint128::new(result))
+pub fn _increaseReservedAmount(
+    _deps: DepsMut,
+    _collateral_token: Addr,
+    _reserveDelta: Uint128,
+) -> Result<Response, ContractError> {
+    let reservedAmounts = RESERVEDAMOUNTS
+        .may_load(_deps.storage, _collateral_token.clone())?
+        .unwrap_or_default();
+    RESERVEDAMOUNTS.save(
+        _deps.storage,
+        _collateral_token.clone(),
+        &reservedAmounts.checked_add(_reserveDelta)?,
+    )?;
+
+    _validateReserveCapacity(_deps.as_ref(), _collateral_token.clone())?;
+
+    let event = Event::new("IncreaseReservedAmount")
+        .add_attribute("token", _collateral_token.as_str())
+        .add_attribute("amount", _reserveDelta.to_string());
+
+    Ok(Response::new().add_event(event))
 }
 
-pub fn get_entry_funding_rate(
-    mut _deps: DepsMut,
-    _collateral_token: Addr,
-    _index_token: Addr,
-    _is_long: bool,
-) -> StdResult<Uint128> {
-    unimplemented!()
+/// Reserved amounts may only be backed by the part of the pool above its buffer.
+pub fn _validateReserveCapacity(_deps: Deps, _token: Addr) -> Result<(), ContractError> {
+    let reserved = RESERVEDAMOUNTS
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+    let pool = POOLAMOUNT
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+
+    _validateReserveCapacityWith(_deps, _token, reserved, pool)
 }
 
-pub fn usdToTokenMax(
-    mut _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
+/// _validateReserveCapacity for reserve and pool amounts not yet stored.
+pub fn _validateReserveCapacityWith(
+    _deps: Deps,
     _token: Addr,
-    _usdAmount: Uint128,
-) -> Result<Uint128, ContractError> {
-    if _usdAmount == Uint128::zero() {
-        return Ok(Uint128::zero());
-    } else {
-        let price = get_min_price(_deps.branch(), _env.clone(), _info.clone(), _token.clone())?;
-        let res = usd_to_token(_deps, &_token, _usdAmount.u128(), price.u128())?;
-        Ok(Uint128::new(res))
+    reserved: Uint128,
+    pool: Uint128,
+) -> Result<(), ContractError> {
+    let buffer = BUFFERAMOUNT
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+
+    let available = pool.saturating_sub(buffer);
+    if reserved > available {
+        return Err(ContractError::InsufficientPoolCapacity {
+            token: _token.to_string(),
+            reserved,
+            available,
+        });
     }
-}
 
-pub fn _increaseReservedAmount(
-    _deps: DepsMut,
-    _collateral_token: Addr,
-    _reserveDelta: Uint128,
-) -> Result<Response, ContractError> {
-    unimplemented!()
+    Ok(())
 }
 
 pub fn _increaseGuaranteedUsd(
@@ -544,10 +470,11 @@ pub fn _increaseGuaranteedUsd(
     _collateral_token: Addr,
     _usdamount: Uint128,
 ) -> Result<Response, ContractError> {
-    let mut guaranteedUsd = GUARANTEEUSD.load(deps.storage, _collateral_token.clone())?;
-    guaranteedUsd = guaranteedUsd + _usdamount;
+    let guaranteedUsd = GUARANTEEUSD
+        .load(deps.storage, _collateral_token.clone())?
+        .checked_add(Uint256::from(_usdamount))?;
 
-    GUARANTEEUSD.save(deps.storage, _collateral_token.clone(), &guaranteedUsd);
+    GUARANTEEUSD.save(deps.storage, _collateral_token.clone(), &guaranteedUsd)?;
     let response = Response::new();
     let event =
         Event::new("_increaseGuaranteedUsd").add_attribute("token", _collateral_token.to_string());
@@ -560,10 +487,11 @@ pub fn _decreaseGuaranteedUsd(
     _collateral_token: Addr,
     _usdamount: Uint128,
 ) -> Result<Response, ContractError> {
-    let mut guaranteedUsd = GUARANTEEUSD.load(deps.storage, _collateral_token.clone())?;
-    guaranteedUsd = guaranteedUsd - _usdamount;
+    let guaranteedUsd = GUARANTEEUSD
+        .load(deps.storage, _collateral_token.clone())?
+        .checked_sub(Uint256::from(_usdamount))?;
 
-    GUARANTEEUSD.save(deps.storage, _collateral_token.clone(), &guaranteedUsd);
+    GUARANTEEUSD.save(deps.storage, _collateral_token.clone(), &guaranteedUsd)?;
     let response = Response::new();
     let event =
         Event::new("_decreaseGuaranteedUsd").add_attribute("token", _collateral_token.to_string());
@@ -571,33 +499,34 @@ pub fn _decreaseGuaranteedUsd(
     Ok(response.add_event(event))
 }
 
-pub fn get_next_global_short_average_price(
-    deps: DepsMut,
-    _index_token: Addr,
-    _next_price: Uint128,
-    _size_delta: Uint128,
-) -> Result<Uint128, ContractError> {
-    let size = GLOBALSHORTSIZE.load(deps.storage, _index_token.clone())?;
+pub fn _increaseGlobalShortSize(
+    _deps: DepsMut,
+    _token: Addr,
+    _amount: Uint128,
+) -> Result<Response, ContractError> {
+    let size = GLOBALSHORTSIZE
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default()
+        .checked_add(Uint256::from(_amount))?;
+
+    let max_size = MAXGLOBALSHORTSIZE
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+    if !max_size.is_zero() && size > max_size {
+        return Err(ContractError::MaxGlobalShortSizeExceeded {
+            token: _token.to_string(),
+            size,
+            max_size,
+        });
+    }
 
-    let average_price = GLOBALSHORTAVERAGEPRICE.load(deps.storage, _index_token)?;
+    GLOBALSHORTSIZE.save(_deps.storage, _token.clone(), &size)?;
 
-    let price_delta = if average_price > _next_price {
-        average_price - _next_price
-    } else {
-        _next_price - average_price
-    };
-    let delta = size * price_delta / average_price;
-    let has_profit = average_price > _next_price;
-
-    let next_size = size + _size_delta;
-    let divisor = if has_profit {
-        next_size - delta
-    } else {
-        next_size + delta
-    };
+    let event = Event::new("IncreaseGlobalShortSize")
+        .add_attribute("token", _token.as_str())
+        .add_attribute("amount", _amount.to_string());
 
-    let result = _next_price * next_size / divisor;
-    Ok(result)
+    Ok(Response::new().add_event(event))
 }
 
 pub fn _decreaseReservedAmount(
@@ -605,114 +534,87 @@ pub fn _decreaseReservedAmount(
     _token: Addr,
     _amount: Uint128,
 ) -> Result<Response, ContractError> {
-    let mut guaranteedUsd = RESERVEDAMOUNTS.load(deps.storage, _token.clone())?;
-    guaranteedUsd = guaranteedUsd - _amount;
+    let reservedAmounts = RESERVEDAMOUNTS
+        .may_load(deps.storage, _token.clone())?
+        .unwrap_or_default();
+    if reservedAmounts < _amount {
+        return Err(ContractError::InsufficientReserve {
+            token: _token.to_string(),
+            reserved: reservedAmounts,
+            amount: _amount,
+        });
+    }
 
-    RESERVEDAMOUNTS.save(deps.storage, _token.clone(), &guaranteedUsd);
+    RESERVEDAMOUNTS.save(deps.storage, _token.clone(), &(reservedAmounts - _amount))?;
     let response = Response::new();
-    let event = Event::new("_decreaseReservedAmount").add_attribute("token", _token.to_string());
+    let event = Event::new("_decreaseReservedAmount")
+        .add_attribute("token", _token.to_string())
+        .add_attribute("amount", _amount.to_string());
 
     Ok(response.add_event(event))
 }
 
-pub fn _decreaseGlobalShortSize(
+pub fn _increaseGlobalLongSize(
     _deps: DepsMut,
     _token: Addr,
     _amount: Uint128,
 ) -> Result<Response, ContractError> {
-    let size = GLOBALSHORTSIZE.load(_deps.storage, _token.clone())?;
-    if _amount > size {
-        GLOBALSHORTSIZE.save(_deps.storage, _token, &Uint128::zero());
-        return Ok(Response::new());
-    } else {
-        GLOBALSHORTSIZE.save(_deps.storage, _token, &(size - _amount))?;
-        Ok(Response::new())
-    }
-}
-
-pub fn validLiquidation(
-    mut _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _account: Addr,
-    _collateralToken: Addr,
-    _indexToken: Addr,
-    _isLong: bool,
-    _receiver: Addr,
-) -> Result<(Uint128, Uint128), ContractError> {
-    unimplemented!()
-}
-
-pub fn getRedemptionCollateral(
-    mut _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _token: Addr,
-) -> Result<Uint128, ContractError> {
-    let stableTokens = STABLETOKEN.load(_deps.storage, _token.clone())?;
-    let amount = POOLAMOUNT.load(_deps.storage, _token.clone())?;
-    if stableTokens {
-        return Ok(amount);
+    let size = GLOBALLONGSIZE
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default()
+        .checked_add(Uint256::from(_amount))?;
+
+    let max_size = MAXGLOBALLONGSIZE
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+    if !max_size.is_zero() && size > max_size {
+        return Err(ContractError::MaxGlobalLongSizeExceeded {
+            token: _token.to_string(),
+            size,
+            max_size,
+        });
     }
-    let grantedusd = GUARANTEEUSD.load(_deps.storage, _token.clone())?;
-
-    let collateral = usd_to_token_min(
-        _deps.branch(),
-        _env,
-        _info.clone(),
-        _token.clone(),
-        grantedusd.u128(),
-    )?;
 
-    let _collateral = Uint128::new(collateral);
+    GLOBALLONGSIZE.save(_deps.storage, _token.clone(), &size)?;
 
-    let reservedAmounts = RESERVEDAMOUNTS.load(_deps.storage, _token.clone())?;
-    let res: Uint128 = _collateral + amount - reservedAmounts;
+    let event = Event::new("IncreaseGlobalLongSize")
+        .add_attribute("token", _token.as_str())
+        .add_attribute("amount", _amount.to_string());
 
-    Ok(res)
+    Ok(Response::new().add_event(event))
 }
 
-pub fn getRedemptionCollateralUsd(
-    mut _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
+pub fn _decreaseGlobalLongSize(
+    _deps: DepsMut,
     _token: Addr,
-) -> Result<Uint128, ContractError> {
-    let RedemptionCollateral =
-        getRedemptionCollateral(_deps.branch(), _env.clone(), _info.clone(), _token.clone())?;
-
-    let res = token_to_usd_min(
-        _deps.branch(),
-        _env.clone(),
-        _info,
+    _amount: Uint128,
+) -> Result<Response, ContractError> {
+    let size = GLOBALLONGSIZE
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+    GLOBALLONGSIZE.save(
+        _deps.storage,
         _token,
-        RedemptionCollateral.u128(),
+        &size.saturating_sub(Uint256::from(_amount)),
     )?;
-    Ok(res)
-}
 
-pub fn getPositionFee(
-    _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
-    _account: Addr,
-    _collateralToken: Addr,
-    _indexToken: Addr,
-    _isLong: bool,
-    _sizeDelta: Uint128,
-) -> Result<Uint128, ContractError> {
-    unimplemented!()
+    Ok(Response::new())
 }
 
-pub fn getFeeBasisPoints(
+pub fn _decreaseGlobalShortSize(
     _deps: DepsMut,
-    _env: Env,
-    _info: MessageInfo,
     _token: Addr,
-    _usdgDelta: Uint128,
-    _feeBasisPoints: Uint128,
-    _taxBasisPoints: Uint128,
-    _increment: bool,
-) -> Result<Uint128, ContractError> {
-    unimplemented!()
+    _amount: Uint128,
+) -> Result<Response, ContractError> {
+    let size = GLOBALSHORTSIZE
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+    let _amount = Uint256::from(_amount);
+    if _amount > size {
+        GLOBALSHORTSIZE.save(_deps.storage, _token, &Uint256::zero())?;
+        return Ok(Response::new());
+    } else {
+        GLOBALSHORTSIZE.save(_deps.storage, _token, &(size - _amount))?;
+        Ok(Response::new())
+    }
 }
@@ -1,10 +1,17 @@
 pub mod bank;
 pub mod contract;
+pub mod custody;
 mod error;
 
 pub mod helpers;
+pub mod math;
 pub mod msg;
+#[cfg(test)]
+mod multitest;
+pub mod price_feed;
+pub mod pricing;
 pub mod query;
+pub mod simulate;
 pub mod state;
 
 pub use crate::error::ContractError;
@@ -0,0 +1,118 @@
+//! Checked arithmetic for vault accounting.
+//!
+//! Divisions take an explicit [`Rounding`] so truncation never works against
+//! the pool: amounts owed to the vault (fees, funding) round up, amounts paid
+//! out of it round down. Overflow and division by zero come back as
+//! `ContractError`s rather than aborting the transaction.
+
+use cosmwasm_std::{DivideByZeroError, Uint128, Uint256};
+
+use crate::error::ContractError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// Towards zero, for amounts leaving the vault.
+    Down,
+    /// Away from zero, for amounts owed to the vault.
+    Up,
+}
+
+/// `a * b / denominator` computed over a 256-bit intermediate.
+pub fn mul_div(
+    a: Uint128,
+    b: Uint128,
+    denominator: Uint128,
+    rounding: Rounding,
+) -> Result<Uint128, ContractError> {
+    let result = div_256(a.full_mul(b), Uint256::from(denominator), rounding)?;
+    Ok(Uint128::try_from(result)?)
+}
+
+/// `a * b / denominator` for values already held as Uint256.
+pub fn mul_div_256(
+    a: Uint256,
+    b: Uint256,
+    denominator: Uint256,
+    rounding: Rounding,
+) -> Result<Uint256, ContractError> {
+    div_256(a.checked_mul(b)?, denominator, rounding)
+}
+
+pub fn div(a: Uint128, b: Uint128, rounding: Rounding) -> Result<Uint128, ContractError> {
+    let result = div_256(Uint256::from(a), Uint256::from(b), rounding)?;
+    Ok(Uint128::try_from(result)?)
+}
+
+fn div_256(
+    numerator: Uint256,
+    denominator: Uint256,
+    rounding: Rounding,
+) -> Result<Uint256, ContractError> {
+    if denominator.is_zero() {
+        return Err(DivideByZeroError::new(numerator).into());
+    }
+
+    let quotient = numerator / denominator;
+    if rounding == Rounding::Up && !(numerator % denominator).is_zero() {
+        return Ok(quotient.checked_add(Uint256::one())?);
+    }
+    Ok(quotient)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_in_the_requested_direction() {
+        let (a, b, c) = (Uint128::new(10), Uint128::new(10), Uint128::new(3));
+
+        assert_eq!(mul_div(a, b, c, Rounding::Down).unwrap(), Uint128::new(33));
+        assert_eq!(mul_div(a, b, c, Rounding::Up).unwrap(), Uint128::new(34));
+        // exact results are not bumped
+        assert_eq!(mul_div(a, c, c, Rounding::Up).unwrap(), Uint128::new(10));
+        assert_eq!(div(a, c, Rounding::Up).unwrap(), Uint128::new(4));
+    }
+
+    #[test]
+    fn intermediate_products_may_exceed_uint128() {
+        let price = Uint128::new(3_000 * 10u128.pow(30));
+        let amount = Uint128::new(10u128.pow(21));
+
+        assert_eq!(
+            mul_div(amount, price, Uint128::new(10u128.pow(30)), Rounding::Down).unwrap(),
+            Uint128::new(3 * 10u128.pow(24))
+        );
+    }
+
+    #[test]
+    fn failures_are_contract_errors() {
+        assert!(matches!(
+            mul_div(
+                Uint128::one(),
+                Uint128::one(),
+                Uint128::zero(),
+                Rounding::Down
+            ),
+            Err(ContractError::DivideByZero(_))
+        ));
+        assert!(matches!(
+            mul_div(
+                Uint128::MAX,
+                Uint128::new(2),
+                Uint128::one(),
+                Rounding::Down
+            ),
+            Err(ContractError::ConversionOverflow(_))
+        ));
+        assert!(matches!(
+            mul_div_256(
+                Uint256::MAX,
+                Uint256::from(2u8),
+                Uint256::one(),
+                Rounding::Down
+            ),
+            Err(ContractError::Overflow(_))
+        ));
+    }
+}
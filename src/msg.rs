@@ -1,5 +1,8 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, Uint128, Uint256};
+
+use crate::state::{CustodyMode, FeeRecipient, LiquidatorStats, PauseTarget, Role};
+use cw20::Cw20ReceiveMsg;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -12,8 +15,14 @@ pub struct InstantiateMsg {
     pub _fundingRateFactor: String,
     pub _stableFundingRateFactor: String,
     pub _bankAddr: Addr,
+    /// Defaults to self-custody; `bank` keeps deposits with `_bankAddr`, which pays them out.
+    #[serde(default)]
+    pub _custody: CustodyMode,
 }
 
+#[cw_serde]
+pub struct MigrateMsg {}
+
 #[cw_serde]
 pub enum ExecuteMsg {
     SetVaultUtils {
@@ -62,6 +71,12 @@ pub enum ExecuteMsg {
         _maxLeverage: Uint128,
     },
 
+    /// Overrides Config.max_leverage for positions on `_token`; `None` clears it.
+    SetTokenMaxLeverage {
+        _token: Addr,
+        _maxLeverage: Option<Uint128>,
+    },
+
     SetBufferAmount {
         _token: Addr,
         _amount: Uint128,
@@ -69,7 +84,12 @@ pub enum ExecuteMsg {
 
     SetMaxGlobalShortSize {
         _token: Addr,
-        _amount: Uint128,
+        _amount: Uint256,
+    },
+
+    SetMaxGlobalLongSize {
+        _token: Addr,
+        _amount: Uint256,
     },
 
     SetFess {
@@ -124,16 +144,32 @@ pub enum ExecuteMsg {
         _token: Addr,
     },
 
+    /// Adds tokens donated to the vault to the pool and resyncs its cached balance.
+    Sync {
+        _token: Addr,
+    },
+
+    /// Sends tokens donated to the vault to `_receiver`, e.g. a treasury.
+    Skim {
+        _token: Addr,
+        _receiver: Addr,
+    },
+
     BuyUSDG {
         _token: Addr,
         _receiver: Addr,
     },
 
+    /// Burns `_usdgAmount` of the sender's USDG through an allowance.
     SellUSDG {
         _token: Addr,
+        _usdgAmount: Uint128,
         _receiver: Addr,
     },
 
+    /// USDG sent with a `ReceiveMsg` is sold without an allowance.
+    Receive(Cw20ReceiveMsg),
+
     UpdateCumulativeFundingRate {
         _collateralToken: Addr,
         _indexToken: Addr,
@@ -153,6 +189,7 @@ pub enum ExecuteMsg {
         _isLong: bool,
     },
 
+    /// Decreases the sender's own position, paying out to `_receiver`.
     DecreasePosition {
         _account: Addr,
         _collateralToken: Addr,
@@ -163,6 +200,7 @@ pub enum ExecuteMsg {
         _receiver: Addr,
     },
 
+    /// Takes collateral out of the sender's own position and pays it to them.
     ReduceCollateral {
         _account: Addr,
         _collateralToken: Addr,
@@ -179,8 +217,455 @@ pub enum ExecuteMsg {
         _isLong: bool,
         _receiver: Addr,
     },
+
+    GrantRole {
+        role: Role,
+        account: String,
+    },
+
+    RevokeRole {
+        role: Role,
+        account: String,
+    },
+
+    /// Pauses the given operations, or all of them when `targets` is omitted.
+    Pause {
+        targets: Option<Vec<PauseTarget>>,
+        reason: String,
+    },
+
+    Unpause {
+        targets: Option<Vec<PauseTarget>>,
+    },
+
+    SetLiquidityConfig {
+        lp_token: Addr,
+        cooldown_duration: u64,
+    },
+
+    /// Mints LP shares for `token` already transferred to the vault. Open to
+    /// everyone in manager mode: the USDG minted here stays in the vault, so
+    /// this is the managed mint path rather than a way around it.
+    AddLiquidity {
+        token: Addr,
+        min_usdg: Uint128,
+        min_lp: Uint128,
+    },
+
+    /// `fee_token`, when set, is the whitelisted token fee reserves are
+    /// converted into before they are split.
+    SetFeeRecipients {
+        recipients: Vec<FeeRecipient>,
+        #[serde(default)]
+        fee_token: Option<Addr>,
+    },
+
+    /// Splits the fee reserves of `tokens` (all whitelisted tokens when omitted)
+    /// between the fee recipients. Callable by anyone. With a fee token set and
+    /// a fee keeper calling while swaps are enabled and unpaused, each reserve
+    /// is first swapped into it through the pool, fee-free; otherwise the
+    /// reserves are split as they are.
+    DistributeFees {
+        tokens: Option<Vec<Addr>>,
+    },
+
+    /// Burns `lp_amount` shares (needs an allowance) and pays out `token_out`.
+    RemoveLiquidity {
+        token_out: Addr,
+        lp_amount: Uint128,
+        min_out: Uint128,
+        receiver: Addr,
+    },
+}
+
+#[cw_serde]
+pub enum ReceiveMsg {
+    SellUSDG { _token: Addr, _receiver: Addr },
 }
 
 #[cw_serde]
 #[derive(QueryResponses)]
-pub enum QueryMsg {}
+pub enum QueryMsg {
+    #[returns(bool)]
+    HasRole { role: Role, account: Addr },
+
+    #[returns(RoleMembersResponse)]
+    RoleMembers {
+        role: Role,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    #[returns(PauseStatusResponse)]
+    PauseStatus {},
+
+    #[returns(bool)]
+    IsManager { address: Addr },
+
+    #[returns(ManagersResponse)]
+    Managers {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    #[returns(GlobalShortPnlResponse)]
+    GlobalShortPnl { token: Addr },
+
+    /// Pool value in USD scaled by PRICE_PRECISION, priced at max or min prices.
+    #[returns(Uint256)]
+    Aum { maximise: bool },
+
+    #[returns(OpenInterestResponse)]
+    OpenInterest { token: Addr },
+
+    /// Max leverage in basis points for positions indexed on `token`.
+    #[returns(Uint128)]
+    MaxLeverage { token: Addr },
+
+    #[returns(LiquidatorsResponse)]
+    Liquidators {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    #[returns(LiquidatorStats)]
+    LiquidatorStats { address: Addr },
+
+    #[returns(LiquidityInfoResponse)]
+    LiquidityInfo { account: Option<Addr> },
+
+    #[returns(FeeRecipientsResponse)]
+    FeeRecipients {},
+
+    #[returns(FeeRecipientInfoResponse)]
+    FeeRecipientInfo { recipient: Addr },
+
+    /// Fee reserves waiting to be distributed, per whitelisted token.
+    #[returns(PendingFeesResponse)]
+    PendingFees {},
+
+    /// Actual vs cached balances of `tokens` (all whitelisted tokens when omitted).
+    #[returns(BalanceDriftResponse)]
+    BalanceDrift { tokens: Option<Vec<Addr>> },
+
+    /// Compares the USDG cw20 supply with the vault's per-token USDG debt.
+    #[returns(UsdgSupplyResponse)]
+    UsdgSupply {},
+
+    /// Checks the pool, reserve, fee reserve and balance accounting of
+    /// `tokens` (all whitelisted tokens when omitted) and lists every violation.
+    #[returns(CheckInvariantsResponse)]
+    CheckInvariants { tokens: Option<Vec<Addr>> },
+
+    /// Quotes a Swap of `amount_in` without executing it.
+    #[returns(SimulateSwapResponse)]
+    SimulateSwap {
+        token_in: Addr,
+        token_out: Addr,
+        amount_in: Uint128,
+    },
+
+    /// Quotes a BuyUSDG depositing `token_amount` of `token`.
+    #[returns(SimulateBuyUsdgResponse)]
+    SimulateBuyUsdg { token: Addr, token_amount: Uint128 },
+
+    /// Quotes a SellUSDG of `usdg_amount` for `token`.
+    #[returns(SimulateSellUsdgResponse)]
+    SimulateSellUsdg { token: Addr, usdg_amount: Uint128 },
+
+    /// Quotes an IncreasePosition depositing `collateral_amount` collateral
+    /// tokens and adding `size_delta` USD of size.
+    #[returns(SimulateIncreasePositionResponse)]
+    SimulateIncreasePosition {
+        account: Addr,
+        collateral_token: Addr,
+        index_token: Addr,
+        collateral_amount: Uint128,
+        size_delta: Uint128,
+        is_long: bool,
+    },
+
+    /// Quotes a DecreasePosition; `collateral_delta` and `size_delta` are in USD.
+    #[returns(SimulateDecreasePositionResponse)]
+    SimulateDecreasePosition {
+        account: Addr,
+        collateral_token: Addr,
+        index_token: Addr,
+        collateral_delta: Uint128,
+        size_delta: Uint128,
+        is_long: bool,
+    },
+
+    /// Mark price at which the position becomes liquidatable, given its
+    /// current collateral, accrued margin and funding fees.
+    #[returns(LiquidationPriceResponse)]
+    LiquidationPrice {
+        account: Addr,
+        collateral_token: Addr,
+        index_token: Addr,
+        is_long: bool,
+    },
+
+    /// Profit or loss of the position at the current mark price.
+    #[returns(PositionDeltaResponse)]
+    PositionDelta {
+        account: Addr,
+        collateral_token: Addr,
+        index_token: Addr,
+        is_long: bool,
+    },
+}
+
+#[cw_serde]
+pub struct TokenAmount {
+    pub token: Addr,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct FeeRecipientsResponse {
+    pub recipients: Vec<FeeRecipient>,
+    pub fee_token: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct FeeRecipientInfoResponse {
+    pub recipient: Addr,
+    /// Current weight, zero once the recipient has been removed from the split.
+    pub weight: Uint128,
+    pub usd_received: Uint128,
+    pub distributed: Vec<TokenAmount>,
+}
+
+#[cw_serde]
+pub struct PendingFeesResponse {
+    pub fees: Vec<TokenAmount>,
+}
+
+#[cw_serde]
+pub struct TokenDrift {
+    pub token: Addr,
+    pub cached_balance: Uint128,
+    pub actual_balance: Uint128,
+    /// Tokens held beyond the cached balance, recoverable with Sync or Skim.
+    pub surplus: Uint128,
+    /// Tokens missing from the cached balance.
+    pub deficit: Uint128,
+}
+
+#[cw_serde]
+pub struct BalanceDriftResponse {
+    pub drifts: Vec<TokenDrift>,
+}
+
+#[cw_serde]
+pub enum Invariant {
+    /// POOLAMOUNT >= RESERVEDAMOUNTS
+    PoolCoversReserved,
+    /// Balance held in custody (the vault's cw20 balance, or what it has paid
+    /// into the bank) >= POOLAMOUNT + FEERESERVED; an overflowing sum is
+    /// reported with `required` at `Uint128::MAX`
+    BalanceCoversPoolAndFees,
+    /// cw20 balance >= TOKENBALANCE
+    BalanceCoversCached,
+}
+
+#[cw_serde]
+pub struct InvariantViolation {
+    pub token: Addr,
+    pub invariant: Invariant,
+    /// Left-hand side of the invariant.
+    pub actual: Uint128,
+    /// Right-hand side of the invariant, which `actual` fell short of.
+    pub required: Uint128,
+}
+
+#[cw_serde]
+pub struct CheckInvariantsResponse {
+    pub violations: Vec<InvariantViolation>,
+}
+
+#[cw_serde]
+pub struct UsdgSupplyResponse {
+    pub total_supply: Uint128,
+    /// Sum of USDGAMOUNT over every token the vault has accounted for.
+    pub total_usdg_amount: Uint128,
+    /// USDG backing LP shares, held by the vault itself.
+    pub held_by_vault: Uint128,
+    pub minter: Option<Addr>,
+    pub vault_is_minter: bool,
+    /// USDG debt decreases that found less debt on their token, and so left
+    /// `total_usdg_amount` above `total_supply`.
+    pub uncleared_usdg_debt: Uint128,
+    /// Whether `total_supply + uncleared_usdg_debt == total_usdg_amount`.
+    pub is_reconciled: bool,
+}
+
+#[cw_serde]
+pub struct LiquidityInfoResponse {
+    pub lp_token: Addr,
+    pub cooldown_duration: u64,
+    pub lp_supply: Uint128,
+    /// AUM owned by LP shares, used to price shares minted by AddLiquidity.
+    /// Excludes the backing of USDG held outside the vault.
+    pub aum_in_usdg_max: Uint128,
+    /// AUM owned by LP shares, used to price shares redeemed by
+    /// RemoveLiquidity.
+    pub aum_in_usdg_min: Uint128,
+    /// Present when the query names an account that has added liquidity.
+    pub last_added_at: Option<u64>,
+}
+
+#[cw_serde]
+pub struct RoleMembersResponse {
+    pub role: Role,
+    pub members: Vec<Addr>,
+}
+
+#[cw_serde]
+pub struct PausedOperation {
+    pub target: PauseTarget,
+    pub reason: String,
+    pub paused_by: Addr,
+    pub paused_at: u64,
+}
+
+#[cw_serde]
+pub struct PauseStatusResponse {
+    pub paused: Vec<PausedOperation>,
+}
+
+#[cw_serde]
+pub struct ManagersResponse {
+    pub in_manager_mode: bool,
+    pub managers: Vec<Addr>,
+}
+
+#[cw_serde]
+pub struct LiquidatorInfo {
+    pub address: Addr,
+    pub is_active: bool,
+    pub stats: LiquidatorStats,
+}
+
+#[cw_serde]
+pub struct LiquidatorsResponse {
+    pub in_private_liquidation_mode: bool,
+    pub liquidators: Vec<LiquidatorInfo>,
+}
+
+#[cw_serde]
+pub struct GlobalShortPnlResponse {
+    pub token: Addr,
+    pub size: Uint256,
+    pub average_price: Uint128,
+    /// True when shorts are in profit, i.e. the pool is at a loss.
+    pub has_profit: bool,
+    pub delta: Uint256,
+}
+
+#[cw_serde]
+pub struct OpenInterestResponse {
+    pub token: Addr,
+    pub long_size: Uint256,
+    pub long_average_price: Uint128,
+    pub max_long_size: Uint256,
+    pub short_size: Uint256,
+    pub short_average_price: Uint128,
+    pub max_short_size: Uint256,
+}
+
+#[cw_serde]
+pub struct SimulateSwapResponse {
+    pub price_in: Uint128,
+    pub price_out: Uint128,
+    /// USDG value of `amount_in`, which sets the dynamic fee.
+    pub usdg_amount: Uint128,
+    pub fee_basis_points: Uint128,
+    /// Taken from the output, in `token_out`.
+    pub fee_amount: Uint128,
+    /// Paid to the receiver after fees.
+    pub amount_out: Uint128,
+}
+
+#[cw_serde]
+pub struct SimulateBuyUsdgResponse {
+    pub price: Uint128,
+    /// USDG value of the deposit before fees, which sets the dynamic fee.
+    pub usdg_amount: Uint128,
+    pub fee_basis_points: Uint128,
+    /// Kept by the vault, in the deposited token.
+    pub fee_amount: Uint128,
+    pub mint_amount: Uint128,
+}
+
+#[cw_serde]
+pub struct SimulateSellUsdgResponse {
+    pub price: Uint128,
+    /// Tokens the USDG redeems before fees.
+    pub redemption_amount: Uint128,
+    pub fee_basis_points: Uint128,
+    pub fee_amount: Uint128,
+    /// Paid to the receiver after fees.
+    pub amount_out: Uint128,
+}
+
+#[cw_serde]
+pub struct SimulateIncreasePositionResponse {
+    /// Mark price the size is added at.
+    pub price: Uint128,
+    pub size: Uint128,
+    pub collateral: Uint128,
+    pub average_price: Uint128,
+    /// Margin and funding fees in USD, taken from the collateral.
+    pub fee_usd: Uint128,
+    /// In basis points.
+    pub leverage: Uint128,
+    pub liquidation_price: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct SimulateDecreasePositionResponse {
+    /// Mark price the size is closed at.
+    pub price: Uint128,
+    pub has_profit: bool,
+    /// Profit or loss realised on the closed size, in USD.
+    pub realised_delta: Uint128,
+    /// Margin and funding fees in USD.
+    pub fee_usd: Uint128,
+    /// Paid to the receiver in USD, after fees.
+    pub usd_out: Uint128,
+    /// `usd_out` in collateral tokens.
+    pub amount_out: Uint128,
+    /// Zero once the position is closed.
+    pub size: Uint128,
+    pub collateral: Uint128,
+    /// In basis points, zero once the position is closed.
+    pub leverage: Uint128,
+    pub liquidation_price: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct LiquidationPriceResponse {
+    /// Price liquidations are checked against: the min price for longs and
+    /// the max price for shorts.
+    pub mark_price: Uint128,
+    /// Margin and funding fees in USD owed if the position closed now.
+    pub margin_fees: Uint128,
+    /// Longs are liquidatable at or below it, shorts at or above it. `None`
+    /// when the position is already liquidatable at any price.
+    pub liquidation_price: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct PositionDeltaResponse {
+    pub has_profit: bool,
+    /// Profit or loss in USD at `mark_price`.
+    pub delta: Uint128,
+    /// `delta`, with profits inside the token's min-profit threshold zeroed
+    /// until `min_profit_time` has passed since the last increase. This is
+    /// what a decrease would realise.
+    pub adjusted_delta: Uint128,
+    /// The min price for longs and the max price for shorts.
+    pub mark_price: Uint128,
+}
@@ -0,0 +1,153 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_binary, Addr, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError, StdResult,
+    Uint128, WasmMsg,
+};
+use cw20::Cw20ReceiveMsg;
+use cw_multi_test::{Contract, ContractWrapper};
+use cw_storage_plus::{Item, Map};
+
+use crate::price_feed::QueryPriceFeedMsg;
+
+const PRICES: Map<String, (Uint128, Uint128)> = Map::new("prices");
+const FAILING: Item<bool> = Item::new("failing");
+const MAX_WAGER: Item<Uint128> = Item::new("max_wager");
+
+#[cw_serde]
+pub enum PriceFeedExecuteMsg {
+    SetPrice {
+        token: String,
+        min_price: Uint128,
+        max_price: Uint128,
+    },
+}
+
+/// Answers `GetPrice` with whatever min/max price was last set for the token.
+pub fn price_feed_contract() -> Box<dyn Contract<Empty>> {
+    fn instantiate(_: DepsMut, _: Env, _: MessageInfo, _: Empty) -> StdResult<Response> {
+        Ok(Response::new())
+    }
+
+    fn execute(
+        deps: DepsMut,
+        _: Env,
+        _: MessageInfo,
+        msg: PriceFeedExecuteMsg,
+    ) -> StdResult<Response> {
+        match msg {
+            PriceFeedExecuteMsg::SetPrice {
+                token,
+                min_price,
+                max_price,
+            } => PRICES.save(deps.storage, token, &(min_price, max_price))?,
+        }
+        Ok(Response::new())
+    }
+
+    fn query(deps: Deps, _: Env, msg: QueryPriceFeedMsg) -> StdResult<Binary> {
+        match msg {
+            QueryPriceFeedMsg::GetPrice {
+                token, maximise, ..
+            } => {
+                let (min_price, max_price) = PRICES
+                    .may_load(deps.storage, token.clone())?
+                    .ok_or_else(|| StdError::generic_err(format!("no price for {}", token)))?;
+                to_binary(&if maximise { max_price } else { min_price })
+            }
+        }
+    }
+
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+}
+
+#[cw_serde]
+pub enum BankExecuteMsg {
+    PayIn {
+        sender: Addr,
+        escrowed_amount: u128,
+        escrowed_asset: String,
+    },
+    PayOut {
+        sender: Addr,
+        escrowed_amount: u128,
+        escrowed_asset: String,
+        recipient: Addr,
+        total_amount: u128,
+    },
+    /// cw20 deposits, carrying `PayIn` as the hook.
+    Receive(Cw20ReceiveMsg),
+    /// Makes every following payout fail until switched back off.
+    SetFailing { failing: bool },
+}
+
+#[cw_serde]
+pub enum BankQueryMsg {
+    GetMaxWager { asset: String },
+    IsAssetWhitelisted { asset: String },
+}
+
+/// Whitelists every asset, keeps the cw20 tokens paid in and pays out from
+/// its own balance.
+pub fn bank_contract() -> Box<dyn Contract<Empty>> {
+    fn instantiate(deps: DepsMut, _: Env, _: MessageInfo, _: Empty) -> StdResult<Response> {
+        FAILING.save(deps.storage, &false)?;
+        MAX_WAGER.save(deps.storage, &Uint128::MAX)?;
+        Ok(Response::new())
+    }
+
+    fn execute(deps: DepsMut, _: Env, _: MessageInfo, msg: BankExecuteMsg) -> StdResult<Response> {
+        match msg {
+            BankExecuteMsg::PayIn { .. } | BankExecuteMsg::Receive(_) => Ok(Response::new()),
+            BankExecuteMsg::PayOut {
+                escrowed_asset,
+                recipient,
+                total_amount,
+                ..
+            } => {
+                if FAILING.load(deps.storage)? {
+                    return Err(StdError::generic_err("bank payout failed"));
+                }
+                Ok(Response::new().add_message(WasmMsg::Execute {
+                    contract_addr: escrowed_asset,
+                    msg: to_binary(&cw20::Cw20ExecuteMsg::Transfer {
+                        recipient: recipient.to_string(),
+                        amount: Uint128::new(total_amount),
+                    })?,
+                    funds: vec![],
+                }))
+            }
+            BankExecuteMsg::SetFailing { failing } => {
+                FAILING.save(deps.storage, &failing)?;
+                Ok(Response::new())
+            }
+        }
+    }
+
+    fn query(deps: Deps, _: Env, msg: BankQueryMsg) -> StdResult<Binary> {
+        match msg {
+            BankQueryMsg::GetMaxWager { .. } => to_binary(&MAX_WAGER.load(deps.storage)?),
+            BankQueryMsg::IsAssetWhitelisted { .. } => to_binary(&true),
+        }
+    }
+
+    Box::new(ContractWrapper::new(execute, instantiate, query))
+}
+
+pub fn cw20_contract() -> Box<dyn Contract<Empty>> {
+    Box::new(ContractWrapper::new(
+        cw20_base::contract::execute,
+        cw20_base::contract::instantiate,
+        cw20_base::contract::query,
+    ))
+}
+
+pub fn vault_contract() -> Box<dyn Contract<Empty>> {
+    Box::new(
+        ContractWrapper::new(
+            crate::contract::execute,
+            crate::contract::instantiate,
+            crate::contract::query,
+        )
+        .with_reply(crate::contract::reply),
+    )
+}
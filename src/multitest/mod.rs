@@ -0,0 +1,1069 @@
+//! cw-multi-test harness: the vault wired to real cw20-base tokens (USDG and
+//! the LP token included), a settable price feed and a bank, with one helper
+//! per `ExecuteMsg`.
+
+// helpers exist for every message, not every one has a scenario yet
+#![allow(dead_code)]
+
+mod mocks;
+mod properties;
+mod scenarios;
+
+use anyhow::Result as AnyResult;
+use cosmwasm_std::{from_slice, to_binary, Addr, CosmosMsg, Empty, Uint128, Uint256, WasmMsg};
+use cw20::{BalanceResponse, Cw20Coin, Cw20ExecuteMsg, Cw20QueryMsg, MinterResponse};
+use cw_multi_test::{App, AppResponse, Executor};
+use cw_storage_plus::Map;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::contract::PRICE_PRECISION;
+use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, ReceiveMsg};
+use crate::query::get_position_key;
+use crate::state::{CustodyMode, FeeRecipient, PauseTarget, Position, Role, POSITION};
+
+use mocks::{BankExecuteMsg, PriceFeedExecuteMsg};
+
+pub const GOV: &str = "gov";
+pub const USER: &str = "user";
+
+/// Whole tokens every user and the bank start with, per token.
+const INITIAL_TOKENS: u128 = 1_000_000_000;
+
+pub struct SuiteBuilder {
+    custody: CustodyMode,
+    cooldown_duration: u64,
+}
+
+impl SuiteBuilder {
+    pub fn new() -> Self {
+        SuiteBuilder {
+            custody: CustodyMode::SelfCustody,
+            cooldown_duration: 15 * 60,
+        }
+    }
+
+    pub fn with_custody(mut self, custody: CustodyMode) -> Self {
+        self.custody = custody;
+        self
+    }
+
+    pub fn with_cooldown_duration(mut self, cooldown_duration: u64) -> Self {
+        self.cooldown_duration = cooldown_duration;
+        self
+    }
+
+    pub fn build(self) -> Suite {
+        let mut app = App::default();
+        let gov = Addr::unchecked(GOV);
+        let user = Addr::unchecked(USER);
+
+        let vault_code = app.store_code(mocks::vault_contract());
+        let cw20_code = app.store_code(mocks::cw20_contract());
+        let price_feed_code = app.store_code(mocks::price_feed_contract());
+        let bank_code = app.store_code(mocks::bank_contract());
+
+        let price_feed = app
+            .instantiate_contract(
+                price_feed_code,
+                gov.clone(),
+                &Empty {},
+                &[],
+                "price-feed",
+                None,
+            )
+            .unwrap();
+        let bank = app
+            .instantiate_contract(bank_code, gov.clone(), &Empty {}, &[], "bank", None)
+            .unwrap();
+
+        // gov mints until the vault exists, then hands the minter role over
+        let usdg = instantiate_cw20(&mut app, cw20_code, "USDG", 6, vec![], Some(&gov));
+        let lp_token = instantiate_cw20(&mut app, cw20_code, "GLP", 6, vec![], Some(&gov));
+
+        let vault = app
+            .instantiate_contract(
+                vault_code,
+                gov.clone(),
+                &InstantiateMsg {
+                    _router: Addr::unchecked("router"),
+                    _usdg: usdg.clone(),
+                    _priceFeed: price_feed.clone(),
+                    _liquidationFeeUsd: "5".to_string(),
+                    _fundingRateFactor: "100".to_string(),
+                    _stableFundingRateFactor: "100".to_string(),
+                    _bankAddr: bank.clone(),
+                    _custody: self.custody,
+                },
+                &[],
+                "vault",
+                None,
+            )
+            .unwrap();
+
+        for token in [&usdg, &lp_token] {
+            app.execute_contract(
+                gov.clone(),
+                token.clone(),
+                &Cw20ExecuteMsg::UpdateMinter {
+                    new_minter: Some(vault.to_string()),
+                },
+                &[],
+            )
+            .unwrap();
+        }
+
+        let balances = |decimals: u32| {
+            let amount = Uint128::new(INITIAL_TOKENS * 10u128.pow(decimals));
+            vec![
+                Cw20Coin {
+                    address: USER.to_string(),
+                    amount,
+                },
+                Cw20Coin {
+                    address: bank.to_string(),
+                    amount,
+                },
+            ]
+        };
+        let usdc = instantiate_cw20(&mut app, cw20_code, "USDC", 6, balances(6), None);
+        let btc = instantiate_cw20(&mut app, cw20_code, "BTC", 8, balances(8), None);
+        let eth = instantiate_cw20(&mut app, cw20_code, "ETH", 18, balances(18), None);
+
+        let mut suite = Suite {
+            app,
+            gov,
+            user,
+            vault,
+            usdg,
+            lp_token,
+            price_feed,
+            bank,
+            usdc,
+            btc,
+            eth,
+        };
+
+        let (usdc, btc, eth) = (suite.usdc.clone(), suite.btc.clone(), suite.eth.clone());
+        suite.set_price(&usdc, 1).unwrap();
+        suite.set_price(&btc, 60_000).unwrap();
+        suite.set_price(&eth, 3_000).unwrap();
+
+        suite
+            .set_token_config(GOV, &usdc, 6, 4000, 0, 0, true, false)
+            .unwrap();
+        suite
+            .set_token_config(GOV, &btc, 8, 3000, 75, 0, false, true)
+            .unwrap();
+        suite
+            .set_token_config(GOV, &eth, 18, 3000, 75, 0, false, true)
+            .unwrap();
+
+        let lp_token = suite.lp_token.clone();
+        suite
+            .set_liquidity_config(GOV, &lp_token, self.cooldown_duration)
+            .unwrap();
+
+        suite
+    }
+}
+
+fn instantiate_cw20(
+    app: &mut App,
+    code_id: u64,
+    symbol: &str,
+    decimals: u8,
+    initial_balances: Vec<Cw20Coin>,
+    minter: Option<&Addr>,
+) -> Addr {
+    app.instantiate_contract(
+        code_id,
+        Addr::unchecked(GOV),
+        &cw20_base::msg::InstantiateMsg {
+            name: format!("{} token", symbol),
+            symbol: symbol.to_string(),
+            decimals,
+            initial_balances,
+            mint: minter.map(|minter| MinterResponse {
+                minter: minter.to_string(),
+                cap: None,
+            }),
+            marketing: None,
+        },
+        &[],
+        symbol,
+        None,
+    )
+    .unwrap()
+}
+
+pub struct Suite {
+    pub app: App,
+    pub gov: Addr,
+    pub user: Addr,
+    pub vault: Addr,
+    pub usdg: Addr,
+    pub lp_token: Addr,
+    pub price_feed: Addr,
+    pub bank: Addr,
+    pub usdc: Addr,
+    pub btc: Addr,
+    pub eth: Addr,
+}
+
+impl Suite {
+    pub fn new() -> Self {
+        SuiteBuilder::new().build()
+    }
+
+    pub fn execute(&mut self, sender: &str, msg: ExecuteMsg) -> AnyResult<AppResponse> {
+        self.app
+            .execute_contract(Addr::unchecked(sender), self.vault.clone(), &msg, &[])
+    }
+
+    /// Transfers `amount` of `token` into the vault and executes `msg` in the
+    /// same transaction, the way the router does.
+    pub fn deposit_and_execute(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        amount: u128,
+        msg: ExecuteMsg,
+    ) -> AnyResult<AppResponse> {
+        let mut msgs = vec![];
+        if amount > 0 {
+            msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: token.to_string(),
+                msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                    recipient: self.vault.to_string(),
+                    amount: Uint128::new(amount),
+                })?,
+                funds: vec![],
+            }));
+        }
+        msgs.push(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: self.vault.to_string(),
+            msg: to_binary(&msg)?,
+            funds: vec![],
+        }));
+
+        let mut responses = self.app.execute_multi(Addr::unchecked(sender), msgs)?;
+        Ok(responses.pop().unwrap())
+    }
+
+    pub fn query<T: DeserializeOwned>(&self, msg: QueryMsg) -> AnyResult<T> {
+        Ok(self.app.wrap().query_wasm_smart(&self.vault, &msg)?)
+    }
+
+    pub fn position(
+        &self,
+        account: &str,
+        collateral_token: &Addr,
+        index_token: &Addr,
+        is_long: bool,
+    ) -> Option<Position> {
+        let key = get_position_key(
+            Addr::unchecked(account),
+            collateral_token.clone(),
+            index_token.clone(),
+            is_long,
+        );
+        self.app
+            .wrap()
+            .query_wasm_raw(&self.vault, POSITION.key(key).to_vec())
+            .unwrap()
+            .map(|raw| from_slice(&raw).unwrap())
+    }
+
+    /// Reads a per-token accounting entry straight out of vault storage.
+    pub fn amount(&self, map: Map<Addr, Uint128>, token: &Addr) -> Uint128 {
+        self.app
+            .wrap()
+            .query_wasm_raw(&self.vault, map.key(token.clone()).to_vec())
+            .unwrap()
+            .map(|raw| from_slice(&raw).unwrap())
+            .unwrap_or_default()
+    }
+
+    pub fn advance_time(&mut self, seconds: u64) {
+        self.app.update_block(|block| {
+            block.time = block.time.plus_seconds(seconds);
+            block.height += 1;
+        });
+    }
+
+    // ---- mocks -------------------------------------------------------------
+
+    /// Sets both the min and max price to a whole-dollar amount.
+    pub fn set_price(&mut self, token: &Addr, usd: u128) -> AnyResult<AppResponse> {
+        let price = Uint128::new(usd) * PRICE_PRECISION;
+        self.set_prices(token, price, price)
+    }
+
+    pub fn set_prices(
+        &mut self,
+        token: &Addr,
+        min_price: Uint128,
+        max_price: Uint128,
+    ) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            self.gov.clone(),
+            self.price_feed.clone(),
+            &PriceFeedExecuteMsg::SetPrice {
+                token: token.to_string(),
+                min_price,
+                max_price,
+            },
+            &[],
+        )
+    }
+
+    pub fn set_bank_failing(&mut self, failing: bool) -> AnyResult<AppResponse> {
+        self.app.execute_contract(
+            self.gov.clone(),
+            self.bank.clone(),
+            &BankExecuteMsg::SetFailing { failing },
+            &[],
+        )
+    }
+
+    // ---- cw20 --------------------------------------------------------------
+
+    pub fn balance(&self, token: &Addr, account: &Addr) -> Uint128 {
+        let res: BalanceResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(
+                token,
+                &Cw20QueryMsg::Balance {
+                    address: account.to_string(),
+                },
+            )
+            .unwrap();
+        res.balance
+    }
+
+    pub fn supply(&self, token: &Addr) -> Uint128 {
+        let res: cw20::TokenInfoResponse = self
+            .app
+            .wrap()
+            .query_wasm_smart(token, &Cw20QueryMsg::TokenInfo {})
+            .unwrap();
+        res.total_supply
+    }
+
+    pub fn transfer(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        recipient: &Addr,
+        amount: u128,
+    ) -> AnyResult<AppResponse> {
+        self.cw20_execute(
+            sender,
+            token,
+            &Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount: Uint128::new(amount),
+            },
+        )
+    }
+
+    /// Lets the vault burn `amount` of the sender's tokens.
+    pub fn approve(&mut self, sender: &str, token: &Addr, amount: u128) -> AnyResult<AppResponse> {
+        let vault = self.vault.to_string();
+        self.cw20_execute(
+            sender,
+            token,
+            &Cw20ExecuteMsg::IncreaseAllowance {
+                spender: vault,
+                amount: Uint128::new(amount),
+                expires: None,
+            },
+        )
+    }
+
+    fn cw20_execute<T: Serialize + std::fmt::Debug>(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        msg: &T,
+    ) -> AnyResult<AppResponse> {
+        self.app
+            .execute_contract(Addr::unchecked(sender), token.clone(), msg, &[])
+    }
+
+    // ---- vault: configuration ----------------------------------------------
+
+    pub fn set_vault_utils(&mut self, sender: &str, utils: &str) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetVaultUtils {
+                _utilisAddr: Addr::unchecked(utils),
+            },
+        )
+    }
+
+    pub fn set_in_manager_mode(&mut self, sender: &str, enabled: bool) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetInManagerMode {
+                Inmanagermode: enabled,
+            },
+        )
+    }
+
+    pub fn set_manager(
+        &mut self,
+        sender: &str,
+        address: &str,
+        is_manager: bool,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetManager {
+                ismanager: is_manager,
+                address: Addr::unchecked(address),
+            },
+        )
+    }
+
+    pub fn set_in_private_liq_mode(
+        &mut self,
+        sender: &str,
+        enabled: bool,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetInPrivateLiqMode {
+                InPrivateLiqMode: enabled,
+            },
+        )
+    }
+
+    pub fn set_liquidator(
+        &mut self,
+        sender: &str,
+        liquidator: &str,
+        is_active: bool,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::Setliquidator {
+                is_active,
+                liquidator: Addr::unchecked(liquidator),
+            },
+        )
+    }
+
+    pub fn set_is_swap_enabled(&mut self, sender: &str, enabled: bool) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetIsSwapEnabled {
+                is_swap_enable: enabled,
+            },
+        )
+    }
+
+    pub fn set_is_leverage_enabled(
+        &mut self,
+        sender: &str,
+        enabled: bool,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetIsLeverageEnabled {
+                is_Leverage_enable: enabled,
+            },
+        )
+    }
+
+    pub fn set_max_gas_price(&mut self, sender: &str, price: u128) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetMaxGasPrice {
+                _max_gas_price: Uint128::new(price),
+            },
+        )
+    }
+
+    pub fn set_gov(&mut self, sender: &str, gov: &str) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetGov {
+                gov: Addr::unchecked(gov),
+            },
+        )
+    }
+
+    pub fn set_price_feed(&mut self, sender: &str, price_feed: &Addr) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetPriceFeed {
+                _price_feed: price_feed.clone(),
+            },
+        )
+    }
+
+    pub fn set_max_leverage(&mut self, sender: &str, leverage: u128) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetMaxLeverage {
+                _maxLeverage: Uint128::new(leverage),
+            },
+        )
+    }
+
+    pub fn set_token_max_leverage(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        leverage: Option<u128>,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetTokenMaxLeverage {
+                _token: token.clone(),
+                _maxLeverage: leverage.map(Uint128::new),
+            },
+        )
+    }
+
+    pub fn set_buffer_amount(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        amount: u128,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetBufferAmount {
+                _token: token.clone(),
+                _amount: Uint128::new(amount),
+            },
+        )
+    }
+
+    pub fn set_max_global_short_size(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        amount: u128,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetMaxGlobalShortSize {
+                _token: token.clone(),
+                _amount: Uint256::from(amount),
+            },
+        )
+    }
+
+    pub fn set_max_global_long_size(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        amount: u128,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetMaxGlobalLongSize {
+                _token: token.clone(),
+                _amount: Uint256::from(amount),
+            },
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_fees(
+        &mut self,
+        sender: &str,
+        tax_bps: u128,
+        stable_tax_bps: u128,
+        mint_burn_fee_bps: u128,
+        swap_fee_bps: u128,
+        stable_swap_fee_bps: u128,
+        margin_fee_bps: u128,
+        liquidation_fee_usd: Uint128,
+        min_profit_time: u128,
+        has_dynamic_fees: bool,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetFess {
+                _taxBasisPoints: Uint128::new(tax_bps),
+                _stableTaxBasisPoints: Uint128::new(stable_tax_bps),
+                _mintBurnFeeBasisPoints: Uint128::new(mint_burn_fee_bps),
+                _swapFeeBasisPoints: Uint128::new(swap_fee_bps),
+                _stableSwapFeeBasisPoints: Uint128::new(stable_swap_fee_bps),
+                _marginFeeBasisPoints: Uint128::new(margin_fee_bps),
+                _liquidationFeeUsd: liquidation_fee_usd,
+                _minProfitTime: Uint128::new(min_profit_time),
+                _hasDynamicFees: has_dynamic_fees,
+            },
+        )
+    }
+
+    pub fn set_funding_rate(
+        &mut self,
+        sender: &str,
+        interval: u128,
+        rate_factor: u128,
+        stable_rate_factor: u128,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetFundingRate {
+                _fundingInterval: interval,
+                _fundingRateFactor: rate_factor,
+                _stableFundingRateFactor: stable_rate_factor,
+            },
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn set_token_config(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        decimals: u128,
+        weight: u128,
+        min_profit_bps: u128,
+        max_usdg_amount: u128,
+        is_stable: bool,
+        is_shortable: bool,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetTokenConfig {
+                _token: token.clone(),
+                _tokenDecimals: Uint128::new(decimals),
+                _tokenWeight: Uint128::new(weight),
+                _minProfitBps: Uint128::new(min_profit_bps),
+                _maxUsdgAmount: Uint128::new(max_usdg_amount),
+                _isStable: is_stable,
+                _isShortable: is_shortable,
+            },
+        )
+    }
+
+    pub fn clear_token_config(&mut self, sender: &str, token: &Addr) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::ClearTokenConfig {
+                _token: token.clone(),
+            },
+        )
+    }
+
+    pub fn set_usdg_amount(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        amount: u128,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetUsdgAmount {
+                _token: token.clone(),
+                _amount: Uint128::new(amount),
+            },
+        )
+    }
+
+    pub fn set_liquidity_config(
+        &mut self,
+        sender: &str,
+        lp_token: &Addr,
+        cooldown_duration: u64,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetLiquidityConfig {
+                lp_token: lp_token.clone(),
+                cooldown_duration,
+            },
+        )
+    }
+
+    pub fn set_fee_recipients(
+        &mut self,
+        sender: &str,
+        recipients: Vec<FeeRecipient>,
+        fee_token: Option<&Addr>,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::SetFeeRecipients {
+                recipients,
+                fee_token: fee_token.cloned(),
+            },
+        )
+    }
+
+    pub fn grant_role(
+        &mut self,
+        sender: &str,
+        role: Role,
+        account: &str,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::GrantRole {
+                role,
+                account: account.to_string(),
+            },
+        )
+    }
+
+    pub fn revoke_role(
+        &mut self,
+        sender: &str,
+        role: Role,
+        account: &str,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::RevokeRole {
+                role,
+                account: account.to_string(),
+            },
+        )
+    }
+
+    pub fn pause(
+        &mut self,
+        sender: &str,
+        targets: Option<Vec<PauseTarget>>,
+        reason: &str,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::Pause {
+                targets,
+                reason: reason.to_string(),
+            },
+        )
+    }
+
+    pub fn unpause(
+        &mut self,
+        sender: &str,
+        targets: Option<Vec<PauseTarget>>,
+    ) -> AnyResult<AppResponse> {
+        self.execute(sender, ExecuteMsg::Unpause { targets })
+    }
+
+    // ---- vault: fees and balances ------------------------------------------
+
+    pub fn withdraw_fees(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        receiver: &str,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::WithdrawFees {
+                _token: token.clone(),
+                _receiver: Addr::unchecked(receiver),
+            },
+        )
+    }
+
+    pub fn distribute_fees(
+        &mut self,
+        sender: &str,
+        tokens: Option<Vec<Addr>>,
+    ) -> AnyResult<AppResponse> {
+        self.execute(sender, ExecuteMsg::DistributeFees { tokens })
+    }
+
+    pub fn upgrade(
+        &mut self,
+        sender: &str,
+        new_vault: &str,
+        token: &Addr,
+        amount: u128,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::Upgrade {
+                _newVault: Addr::unchecked(new_vault),
+                _token: token.clone(),
+                _amount: Uint128::new(amount),
+            },
+        )
+    }
+
+    /// Transfers `amount` into the vault and credits it straight to the pool.
+    pub fn direct_pool_deposit(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        amount: u128,
+    ) -> AnyResult<AppResponse> {
+        self.deposit_and_execute(
+            sender,
+            token,
+            amount,
+            ExecuteMsg::DirectPoolDeposit {
+                _token: token.clone(),
+            },
+        )
+    }
+
+    pub fn sync(&mut self, sender: &str, token: &Addr) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::Sync {
+                _token: token.clone(),
+            },
+        )
+    }
+
+    pub fn skim(&mut self, sender: &str, token: &Addr, receiver: &str) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::Skim {
+                _token: token.clone(),
+                _receiver: Addr::unchecked(receiver),
+            },
+        )
+    }
+
+    // ---- vault: USDG and liquidity -----------------------------------------
+
+    /// Transfers `amount` into the vault and buys USDG with it, as the router would.
+    pub fn buy_usdg(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        amount: u128,
+        receiver: &str,
+    ) -> AnyResult<AppResponse> {
+        self.deposit_and_execute(
+            sender,
+            token,
+            amount,
+            ExecuteMsg::BuyUSDG {
+                _token: token.clone(),
+                _receiver: Addr::unchecked(receiver),
+            },
+        )
+    }
+
+    /// Approves the vault for `usdg_amount` and sells it through the allowance.
+    pub fn sell_usdg(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        usdg_amount: u128,
+        receiver: &str,
+    ) -> AnyResult<AppResponse> {
+        let usdg = self.usdg.clone();
+        self.approve(sender, &usdg, usdg_amount)?;
+        self.execute(
+            sender,
+            ExecuteMsg::SellUSDG {
+                _token: token.clone(),
+                _usdgAmount: Uint128::new(usdg_amount),
+                _receiver: Addr::unchecked(receiver),
+            },
+        )
+    }
+
+    /// Sells USDG by sending it to the vault with a cw20 `Send`.
+    pub fn send_usdg(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        usdg_amount: u128,
+        receiver: &str,
+    ) -> AnyResult<AppResponse> {
+        let (usdg, vault) = (self.usdg.clone(), self.vault.to_string());
+        self.cw20_execute(
+            sender,
+            &usdg,
+            &Cw20ExecuteMsg::Send {
+                contract: vault,
+                amount: Uint128::new(usdg_amount),
+                msg: to_binary(&ReceiveMsg::SellUSDG {
+                    _token: token.clone(),
+                    _receiver: Addr::unchecked(receiver),
+                })?,
+            },
+        )
+    }
+
+    pub fn add_liquidity(
+        &mut self,
+        sender: &str,
+        token: &Addr,
+        amount: u128,
+        min_usdg: u128,
+        min_lp: u128,
+    ) -> AnyResult<AppResponse> {
+        self.deposit_and_execute(
+            sender,
+            token,
+            amount,
+            ExecuteMsg::AddLiquidity {
+                token: token.clone(),
+                min_usdg: Uint128::new(min_usdg),
+                min_lp: Uint128::new(min_lp),
+            },
+        )
+    }
+
+    /// Approves the vault for `lp_amount` LP tokens and redeems them.
+    pub fn remove_liquidity(
+        &mut self,
+        sender: &str,
+        token_out: &Addr,
+        lp_amount: u128,
+        min_out: u128,
+        receiver: &str,
+    ) -> AnyResult<AppResponse> {
+        let lp_token = self.lp_token.clone();
+        self.approve(sender, &lp_token, lp_amount)?;
+        self.execute(
+            sender,
+            ExecuteMsg::RemoveLiquidity {
+                token_out: token_out.clone(),
+                lp_amount: Uint128::new(lp_amount),
+                min_out: Uint128::new(min_out),
+                receiver: Addr::unchecked(receiver),
+            },
+        )
+    }
+
+    // ---- vault: trading ----------------------------------------------------
+
+    pub fn swap(
+        &mut self,
+        sender: &str,
+        token_in: &Addr,
+        token_out: &Addr,
+        amount_in: u128,
+        receiver: &str,
+    ) -> AnyResult<AppResponse> {
+        self.deposit_and_execute(
+            sender,
+            token_in,
+            amount_in,
+            ExecuteMsg::Swap {
+                _tokenIn: token_in.clone(),
+                _tokenOut: token_out.clone(),
+                _receiver: Addr::unchecked(receiver),
+            },
+        )
+    }
+
+    pub fn update_cumulative_funding_rate(
+        &mut self,
+        sender: &str,
+        collateral_token: &Addr,
+        index_token: &Addr,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::UpdateCumulativeFundingRate {
+                _collateralToken: collateral_token.clone(),
+                _indexToken: index_token.clone(),
+            },
+        )
+    }
+
+    /// Transfers `collateral` into the vault and opens or grows the position.
+    pub fn increase_position(
+        &mut self,
+        sender: &str,
+        collateral_token: &Addr,
+        index_token: &Addr,
+        collateral: u128,
+        size_delta: Uint128,
+        is_long: bool,
+    ) -> AnyResult<AppResponse> {
+        self.deposit_and_execute(
+            sender,
+            collateral_token,
+            collateral,
+            ExecuteMsg::IncreasePosition {
+                _account: Addr::unchecked(sender),
+                _collateralToken: collateral_token.clone(),
+                _indexToken: index_token.clone(),
+                _sizeDelta: size_delta,
+                _isLong: is_long,
+            },
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn decrease_position(
+        &mut self,
+        sender: &str,
+        collateral_token: &Addr,
+        index_token: &Addr,
+        collateral_delta: Uint128,
+        size_delta: Uint128,
+        is_long: bool,
+        receiver: &str,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::DecreasePosition {
+                _account: Addr::unchecked(sender),
+                _collateralToken: collateral_token.clone(),
+                _indexToken: index_token.clone(),
+                _collateralDelta: collateral_delta,
+                _sizeDelta: size_delta,
+                _isLong: is_long,
+                _receiver: Addr::unchecked(receiver),
+            },
+        )
+    }
+
+    pub fn reduce_collateral(
+        &mut self,
+        sender: &str,
+        collateral_token: &Addr,
+        index_token: &Addr,
+        collateral_delta: Uint128,
+        size_delta: Uint128,
+        is_long: bool,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::ReduceCollateral {
+                _account: Addr::unchecked(sender),
+                _collateralToken: collateral_token.clone(),
+                _indexToken: index_token.clone(),
+                _collateralDelta: collateral_delta,
+                _sizeDelta: size_delta,
+                _isLong: is_long,
+            },
+        )
+    }
+
+    pub fn liquidate_position(
+        &mut self,
+        sender: &str,
+        account: &str,
+        collateral_token: &Addr,
+        index_token: &Addr,
+        is_long: bool,
+        receiver: &str,
+    ) -> AnyResult<AppResponse> {
+        self.execute(
+            sender,
+            ExecuteMsg::LiquidatePosition {
+                _account: Addr::unchecked(account),
+                _collateralToken: collateral_token.clone(),
+                _indexToken: index_token.clone(),
+                _isLong: is_long,
+                _receiver: Addr::unchecked(receiver),
+            },
+        )
+    }
+}
@@ -0,0 +1,409 @@
+//! Randomised sequences of trading operations against the harness, checking
+//! the vault's accounting invariants after every step.
+
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{Addr, Uint128};
+
+use super::{Suite, USER};
+use crate::contract::PRICE_PRECISION;
+use crate::msg::{CheckInvariantsResponse, QueryMsg, SimulateSwapResponse, UsdgSupplyResponse};
+use crate::state::{FEERESERVED, POOLAMOUNT, RESERVEDAMOUNTS, USDGAMOUNT};
+
+const TRADERS: [&str; 3] = ["alice", "bob", "carol"];
+const KEEPER: &str = "keeper";
+
+const SEEDS: u64 = 24;
+const STEPS: usize = 120;
+
+// Per-call amounts, each around $1M at starting prices.
+const MAX_USDC: u128 = 1_000_000_000_000;
+const MAX_BTC: u128 = 2_000_000_000;
+const MAX_ETH: u128 = 400_000_000_000_000_000_000;
+
+/// A non-stable token that can be longed, shorted against USDC, and moved in price.
+struct Market {
+    token: Addr,
+    decimals: u32,
+    max_amount: u128,
+    /// Whole USD, kept within `min_price..=max_price`.
+    price: u128,
+    min_price: u128,
+    max_price: u128,
+}
+
+/// xorshift64*, so every failure can be replayed from its seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in `lo..=hi`.
+    fn range(&mut self, lo: u128, hi: u128) -> u128 {
+        lo + (self.next() as u128) % (hi - lo + 1)
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.range(0, items.len() as u128 - 1) as usize]
+    }
+}
+
+struct Fuzz {
+    suite: Suite,
+    rng: Rng,
+    markets: Vec<Market>,
+    /// USDG debt that sales and swaps were expected to leave uncleared, by
+    /// redeeming more than the token they redeem had recorded.
+    uncleared_usdg_debt: Uint128,
+    successes: BTreeMap<&'static str, u32>,
+}
+
+impl Fuzz {
+    fn new(seed: u64) -> Self {
+        let mut suite = Suite::new();
+        let markets = vec![
+            Market {
+                token: suite.btc.clone(),
+                decimals: 8,
+                max_amount: MAX_BTC,
+                price: 60_000,
+                min_price: 20_000,
+                max_price: 120_000,
+            },
+            Market {
+                token: suite.eth.clone(),
+                decimals: 18,
+                max_amount: MAX_ETH,
+                price: 3_000,
+                min_price: 1_000,
+                max_price: 6_000,
+            },
+        ];
+
+        let mut funding = vec![(suite.usdc.clone(), MAX_USDC)];
+        funding.extend(markets.iter().map(|m| (m.token.clone(), m.max_amount)));
+        for (token, max_amount) in funding {
+            for trader in TRADERS {
+                let trader = Addr::unchecked(trader);
+                suite
+                    .transfer(USER, &token, &trader, 20 * max_amount)
+                    .unwrap();
+            }
+            // enough liquidity that positions have something to reserve
+            // against. On even seeds ETH is deposited directly, so its pool
+            // carries no USDG debt for sales and swaps into ETH to clear
+            let direct = seed % 2 == 0 && token == suite.eth;
+            for _ in 0..40 {
+                if direct {
+                    suite.direct_pool_deposit(USER, &token, max_amount).unwrap();
+                } else {
+                    suite.buy_usdg(USER, &token, max_amount, USER).unwrap();
+                }
+            }
+        }
+
+        Fuzz {
+            suite,
+            rng: Rng::new(seed),
+            markets,
+            uncleared_usdg_debt: Uint128::zero(),
+            successes: BTreeMap::new(),
+        }
+    }
+
+    fn step(&mut self) -> String {
+        let trader = *self.rng.pick(&TRADERS);
+        let (usdc, usdg) = (self.suite.usdc.clone(), self.suite.usdg.clone());
+        let market = self.rng.range(0, self.markets.len() as u128 - 1) as usize;
+        let index = self.markets[market].token.clone();
+
+        let (op, desc, result) = match self.rng.range(0, 9) {
+            0 => {
+                let (token, amount) = self.token_amount();
+                let desc = format!("{} buys USDG with {} {}", trader, amount, token);
+                let result = self.suite.buy_usdg(trader, &token, amount, trader);
+                ("buy_usdg", desc, result)
+            }
+            1 => {
+                let token = self.rng.pick(&[usdc, index]).clone();
+                let held = self.suite.balance(&usdg, &Addr::unchecked(trader)).u128();
+                let amount = self.rng.range(0, held);
+                let desc = format!("{} sells {} USDG for {}", trader, amount, token);
+                let debt = self.suite.amount(USDGAMOUNT, &token);
+                let result = self.suite.sell_usdg(trader, &token, amount, trader);
+                if result.is_ok() {
+                    self.uncleared_usdg_debt += Uint128::new(amount).saturating_sub(debt);
+                }
+                ("sell_usdg", desc, result)
+            }
+            2 => {
+                let (token_in, amount) = self.token_amount();
+                let token_out = if token_in == usdc { index } else { usdc };
+                let desc = format!("{} swaps {} {} to {}", trader, amount, token_in, token_out);
+                let debt = self.suite.amount(USDGAMOUNT, &token_out);
+                let quote: Option<SimulateSwapResponse> = self
+                    .suite
+                    .query(QueryMsg::SimulateSwap {
+                        token_in: token_in.clone(),
+                        token_out: token_out.clone(),
+                        amount_in: Uint128::new(amount),
+                    })
+                    .ok();
+                let result = self
+                    .suite
+                    .swap(trader, &token_in, &token_out, amount, trader);
+                if result.is_ok() {
+                    let usdg_amount = quote.expect("an executed swap has a quote").usdg_amount;
+                    self.uncleared_usdg_debt += usdg_amount.saturating_sub(debt);
+                }
+                ("swap", desc, result)
+            }
+            3 | 4 => {
+                let is_long = self.rng.range(0, 1) == 1;
+                let leverage = self.rng.range(11_000, 200_000);
+                let (collateral_token, collateral, collateral_usd) = if is_long {
+                    let Market {
+                        decimals,
+                        max_amount,
+                        price,
+                        ..
+                    } = self.markets[market];
+                    let amount = self.rng.range(0, max_amount);
+                    let usd = Uint128::new(amount)
+                        .multiply_ratio(price * PRICE_PRECISION.u128(), 10u128.pow(decimals));
+                    (index.clone(), amount, usd)
+                } else {
+                    let amount = self.rng.range(0, MAX_USDC / 5);
+                    (
+                        usdc,
+                        amount,
+                        Uint128::new(amount) * Uint128::new(10u128.pow(24)),
+                    )
+                };
+                // adding collateral only is a valid increase too
+                let size = if self.rng.range(0, 4) == 0 {
+                    Uint128::zero()
+                } else {
+                    collateral_usd.multiply_ratio(leverage, 10_000u128)
+                };
+                let desc = format!(
+                    "{} increases {} {} by {} with {} {}",
+                    trader,
+                    side(is_long),
+                    index,
+                    size,
+                    collateral,
+                    collateral_token
+                );
+                let result = self.suite.increase_position(
+                    trader,
+                    &collateral_token,
+                    &index,
+                    collateral,
+                    size,
+                    is_long,
+                );
+                ("increase_position", desc, result)
+            }
+            5 => {
+                let is_long = self.rng.range(0, 1) == 1;
+                let collateral_token = if is_long { index.clone() } else { usdc };
+                let (size, collateral) = self
+                    .suite
+                    .position(trader, &collateral_token, &index, is_long)
+                    .map(|position| (position.size, position.collateral))
+                    .unwrap_or_default();
+                let size_delta = size.multiply_ratio(self.rng.range(0, 100), 100u128);
+                let collateral_delta = collateral.multiply_ratio(self.rng.range(0, 50), 100u128);
+                let desc = format!(
+                    "{} decreases {} {} by {} and {} collateral",
+                    trader,
+                    side(is_long),
+                    index,
+                    size_delta,
+                    collateral_delta
+                );
+                let result = self.suite.decrease_position(
+                    trader,
+                    &collateral_token,
+                    &index,
+                    collateral_delta,
+                    size_delta,
+                    is_long,
+                    trader,
+                );
+                ("decrease_position", desc, result)
+            }
+            6 => {
+                let is_long = self.rng.range(0, 1) == 1;
+                let collateral_token = if is_long { index.clone() } else { usdc };
+                let desc = format!(
+                    "{} liquidates {} {} {}",
+                    KEEPER,
+                    trader,
+                    side(is_long),
+                    index
+                );
+                let result = self.suite.liquidate_position(
+                    KEEPER,
+                    trader,
+                    &collateral_token,
+                    &index,
+                    is_long,
+                    KEEPER,
+                );
+                ("liquidate_position", desc, result)
+            }
+            7 | 8 => {
+                let move_bps = self.rng.range(0, 3_000) as i128 - 1_500;
+                let spread_bps = self.rng.range(0, 20);
+                let market = &mut self.markets[market];
+                let price = (market.price as i128 * (10_000 + move_bps) / 10_000) as u128;
+                market.price = price.clamp(market.min_price, market.max_price);
+                let max_price = Uint128::new(market.price) * PRICE_PRECISION;
+                let min_price = max_price.multiply_ratio(10_000 - spread_bps, 10_000u128);
+                let desc = format!("{} moves to {}", market.token, market.price);
+                let result = self.suite.set_prices(&index, min_price, max_price);
+                ("move_price", desc, result)
+            }
+            _ => {
+                let seconds = self.rng.range(0, 4 * 60 * 60) as u64;
+                self.suite.advance_time(seconds);
+                (
+                    "advance_time",
+                    format!("{}s pass", seconds),
+                    Ok(Default::default()),
+                )
+            }
+        };
+
+        if result.is_ok() {
+            *self.successes.entry(op).or_default() += 1;
+        }
+        desc
+    }
+
+    fn token_amount(&mut self) -> (Addr, u128) {
+        let pick = self.rng.range(0, self.markets.len() as u128) as usize;
+        match self.markets.get(pick) {
+            Some(market) => (market.token.clone(), self.rng.range(0, market.max_amount)),
+            None => (self.suite.usdc.clone(), self.rng.range(0, MAX_USDC)),
+        }
+    }
+
+    fn check_invariants(&self, context: &str) {
+        let suite = &self.suite;
+        let mut total_usdg_amount = Uint128::zero();
+
+        for token in [&suite.usdc, &suite.btc, &suite.eth] {
+            let pool = suite.amount(POOLAMOUNT, token);
+            let reserved = suite.amount(RESERVEDAMOUNTS, token);
+            let fees = suite.amount(FEERESERVED, token);
+            let balance = suite.balance(token, &suite.vault);
+            let usdg_amount = suite.amount(USDGAMOUNT, token);
+
+            assert!(
+                pool >= reserved,
+                "{}: {} pool {} below reserved {}",
+                context,
+                token,
+                pool,
+                reserved
+            );
+            assert!(
+                balance >= pool + fees,
+                "{}: {} balance {} below pool {} + fees {}",
+                context,
+                token,
+                balance,
+                pool,
+                fees
+            );
+
+            total_usdg_amount += usdg_amount;
+        }
+
+        // USDG debt only exceeds the supply by what sales and swaps redeemed
+        // beyond a token's recorded debt
+        let supply = suite.supply(&suite.usdg);
+        assert_eq!(
+            supply + self.uncleared_usdg_debt,
+            total_usdg_amount,
+            "{}: USDG supply {} plus uncleared debt {} differs from summed USDG amounts",
+            context,
+            supply,
+            self.uncleared_usdg_debt
+        );
+        let usdg: UsdgSupplyResponse = suite.query(QueryMsg::UsdgSupply {}).unwrap();
+        assert_eq!(
+            usdg.uncleared_usdg_debt, self.uncleared_usdg_debt,
+            "{}",
+            context
+        );
+        assert!(usdg.is_reconciled, "{}", context);
+
+        // the on-chain checker must agree with the assertions above
+        let report: CheckInvariantsResponse = suite
+            .query(QueryMsg::CheckInvariants { tokens: None })
+            .unwrap();
+        assert_eq!(report.violations, vec![], "{}", context);
+    }
+}
+
+fn side(is_long: bool) -> &'static str {
+    if is_long {
+        "long"
+    } else {
+        "short"
+    }
+}
+
+#[test]
+fn random_operations_keep_accounting_consistent() {
+    let mut successes = BTreeMap::new();
+    let mut seeds_leaving_debt_uncleared = 0;
+
+    for seed in 0..SEEDS {
+        let mut fuzz = Fuzz::new(seed);
+        fuzz.check_invariants(&format!("seed {} setup", seed));
+
+        for step in 0..STEPS {
+            let desc = fuzz.step();
+            fuzz.check_invariants(&format!("seed {} step {} ({})", seed, step, desc));
+        }
+
+        if !fuzz.uncleared_usdg_debt.is_zero() {
+            seeds_leaving_debt_uncleared += 1;
+        }
+        for (op, count) in fuzz.successes {
+            *successes.entry(op).or_insert(0) += count;
+        }
+    }
+
+    // make sure the run exercised every path rather than only rejections
+    for op in [
+        "buy_usdg",
+        "sell_usdg",
+        "swap",
+        "increase_position",
+        "decrease_position",
+        "liquidate_position",
+    ] {
+        assert!(
+            successes.get(op).copied().unwrap_or_default() > 0,
+            "no successful {} in {:?}",
+            op,
+            successes
+        );
+    }
+    assert!(seeds_leaving_debt_uncleared > 0);
+}
@@ -0,0 +1,1338 @@
+use cosmwasm_std::{Addr, Uint128, Uint256};
+
+use super::{Suite, SuiteBuilder, GOV, USER};
+use crate::contract::PRICE_PRECISION;
+use crate::error::ContractError;
+use crate::msg::{
+    CheckInvariantsResponse, ExecuteMsg, GlobalShortPnlResponse, LiquidationPriceResponse,
+    LiquidatorInfo, LiquidatorsResponse, LiquidityInfoResponse, OpenInterestResponse,
+    PositionDeltaResponse, QueryMsg, SimulateBuyUsdgResponse, SimulateDecreasePositionResponse,
+    SimulateIncreasePositionResponse, SimulateSellUsdgResponse, SimulateSwapResponse,
+    UsdgSupplyResponse,
+};
+use crate::state::{
+    CustodyMode, FeeRecipient, LiquidatorStats, PauseTarget, Role, FEERESERVED, POOLAMOUNT,
+    RESERVEDAMOUNTS, USDGAMOUNT,
+};
+
+const HUNDRED_USDC: u128 = 100_000_000;
+
+#[test]
+fn buy_and_sell_usdg_round_trip() {
+    let mut suite = Suite::new();
+    let (usdc, usdg, user) = (suite.usdc.clone(), suite.usdg.clone(), suite.user.clone());
+    let start = suite.balance(&usdc, &user);
+
+    suite.buy_usdg(USER, &usdc, HUNDRED_USDC, USER).unwrap();
+
+    // 30 bps mint fee stays in the vault as fee reserves
+    let minted = suite.balance(&usdg, &user);
+    assert_eq!(minted, Uint128::new(99_700_000));
+    assert_eq!(suite.amount(POOLAMOUNT, &usdc), Uint128::new(99_700_000));
+    assert_eq!(suite.amount(USDGAMOUNT, &usdc), minted);
+    assert_eq!(suite.amount(FEERESERVED, &usdc), Uint128::new(300_000));
+
+    suite.sell_usdg(USER, &usdc, minted.u128(), USER).unwrap();
+
+    assert_eq!(suite.supply(&usdg), Uint128::zero());
+    assert_eq!(suite.amount(USDGAMOUNT, &usdc), Uint128::zero());
+    let received = suite.balance(&usdc, &user) - (start - Uint128::new(HUNDRED_USDC));
+    assert_eq!(received, Uint128::new(99_400_900));
+    assert_eq!(
+        suite.balance(&usdc, &suite.vault),
+        suite.amount(POOLAMOUNT, &usdc) + suite.amount(FEERESERVED, &usdc)
+    );
+}
+
+#[test]
+fn sending_usdg_sells_without_allowance() {
+    let mut suite = Suite::new();
+    let (usdc, usdg, user) = (suite.usdc.clone(), suite.usdg.clone(), suite.user.clone());
+
+    suite.buy_usdg(USER, &usdc, HUNDRED_USDC, USER).unwrap();
+    let minted = suite.balance(&usdg, &user).u128();
+    suite.send_usdg(USER, &usdc, minted, "receiver").unwrap();
+
+    assert_eq!(suite.supply(&usdg), Uint128::zero());
+    assert_eq!(
+        suite.balance(&usdc, &cosmwasm_std::Addr::unchecked("receiver")),
+        Uint128::new(99_400_900)
+    );
+}
+
+#[test]
+fn large_eth_trades_do_not_overflow() {
+    let mut suite = Suite::new();
+    let (eth, usdc, usdg, user) = (
+        suite.eth.clone(),
+        suite.usdc.clone(),
+        suite.usdg.clone(),
+        suite.user.clone(),
+    );
+    let thousand_eth = 1_000 * 10u128.pow(18);
+
+    // 1e21 wei at a 3000e30 price is far beyond Uint128 before dividing back down
+    suite.buy_usdg(USER, &eth, thousand_eth, USER).unwrap();
+    let minted = suite.balance(&usdg, &user);
+    assert_eq!(minted, Uint128::new(2_991_000 * 1_000_000));
+
+    suite
+        .buy_usdg(USER, &usdc, 3_000_000 * 1_000_000, USER)
+        .unwrap();
+    suite
+        .swap(USER, &usdc, &eth, 300_000 * 1_000_000, "receiver")
+        .unwrap();
+    assert_eq!(
+        suite.balance(&eth, &cosmwasm_std::Addr::unchecked("receiver")),
+        Uint128::new(99_700 * 10u128.pow(15))
+    );
+
+    // the swap took 100 ETH out of the pool, so only part of the USDG redeems
+    let eth_before = suite.balance(&eth, &user);
+    suite
+        .sell_usdg(USER, &eth, 1_500_000 * 1_000_000, USER)
+        .unwrap();
+    assert_eq!(
+        suite.balance(&eth, &user) - eth_before,
+        Uint128::new(498_500 * 10u128.pow(15))
+    );
+}
+
+#[test]
+fn swap_pays_out_the_other_token() {
+    let mut suite = Suite::new();
+    let (usdc, btc) = (suite.usdc.clone(), suite.btc.clone());
+
+    // seed the pool with 0.00005 BTC ($3)
+    suite.buy_usdg(USER, &btc, 5_000, USER).unwrap();
+    let btc_pool = suite.amount(POOLAMOUNT, &btc);
+
+    suite
+        .swap(USER, &usdc, &btc, 1_000_000, "receiver")
+        .unwrap();
+
+    // the price ratio is applied before rescaling to 8 decimals, so $1 comes
+    // out as 1600 sats rather than 1666, less the 30 bps swap fee
+    let out = suite.balance(&btc, &cosmwasm_std::Addr::unchecked("receiver"));
+    assert_eq!(out, Uint128::new(1_595));
+    assert_eq!(
+        suite.amount(POOLAMOUNT, &btc),
+        btc_pool - Uint128::new(1_600)
+    );
+    assert_eq!(suite.amount(POOLAMOUNT, &usdc), Uint128::new(1_000_000));
+}
+
+#[test]
+fn liquidity_round_trip_after_cooldown() {
+    let mut suite = SuiteBuilder::new().with_cooldown_duration(60).build();
+    let (usdc, lp_token, user) = (
+        suite.usdc.clone(),
+        suite.lp_token.clone(),
+        suite.user.clone(),
+    );
+
+    suite
+        .add_liquidity(USER, &usdc, HUNDRED_USDC, 0, 0)
+        .unwrap();
+    let shares = suite.balance(&lp_token, &user);
+    assert_eq!(shares, Uint128::new(99_700_000));
+    assert_eq!(suite.balance(&suite.usdg, &suite.vault), shares);
+
+    suite
+        .remove_liquidity(USER, &usdc, shares.u128(), 0, USER)
+        .unwrap_err();
+    suite.advance_time(60);
+    suite
+        .remove_liquidity(USER, &usdc, shares.u128(), 0, USER)
+        .unwrap();
+
+    assert_eq!(suite.supply(&lp_token), Uint128::zero());
+    assert_eq!(suite.supply(&suite.usdg), Uint128::zero());
+    assert_eq!(suite.amount(USDGAMOUNT, &usdc), Uint128::zero());
+}
+
+#[test]
+fn removing_liquidity_after_price_moves_keeps_usdg_reconciled() {
+    for price in [66_000, 54_000] {
+        let mut suite = SuiteBuilder::new().with_cooldown_duration(0).build();
+        let (btc, lp_token, user) = (
+            suite.btc.clone(),
+            suite.lp_token.clone(),
+            suite.user.clone(),
+        );
+        suite
+            .set_fees(GOV, 0, 0, 0, 0, 0, 0, Uint128::new(5), 0, false)
+            .unwrap();
+
+        // one BTC backs USDG held by the user, one backs the LP shares
+        suite.buy_usdg(USER, &btc, 100_000_000, USER).unwrap();
+        suite.add_liquidity(USER, &btc, 100_000_000, 0, 0).unwrap();
+        let shares = suite.balance(&lp_token, &user);
+
+        // the shares are no longer worth the USDG the vault holds for them
+        suite.set_price(&btc, price).unwrap();
+        suite
+            .remove_liquidity(USER, &btc, shares.u128(), 0, USER)
+            .unwrap();
+
+        // only the LP's USDG is burnt; the user's is still owed by the pool
+        let supply: UsdgSupplyResponse = suite.query(QueryMsg::UsdgSupply {}).unwrap();
+        assert!(supply.is_reconciled, "{price}: {supply:?}");
+        assert_eq!(supply.total_usdg_amount, Uint128::new(60_000_000_000));
+    }
+}
+
+#[test]
+fn swapping_out_more_than_a_tokens_usdg_debt_stays_reconciled() {
+    let mut suite = Suite::new();
+    let (usdc, btc) = (suite.usdc.clone(), suite.btc.clone());
+    suite
+        .set_fees(GOV, 0, 0, 0, 0, 0, 0, Uint128::new(5), 0, false)
+        .unwrap();
+
+    // the BTC in the pool carries no USDG debt, so the swap can't clear it
+    suite.direct_pool_deposit(USER, &btc, 100_000_000).unwrap();
+    suite.swap(USER, &usdc, &btc, HUNDRED_USDC, USER).unwrap();
+    assert_eq!(suite.amount(USDGAMOUNT, &btc), Uint128::zero());
+
+    let supply: UsdgSupplyResponse = suite.query(QueryMsg::UsdgSupply {}).unwrap();
+    assert_eq!(supply.total_supply, Uint128::zero());
+    assert_eq!(supply.uncleared_usdg_debt, supply.total_usdg_amount);
+    assert!(!supply.uncleared_usdg_debt.is_zero());
+    assert!(supply.is_reconciled, "{supply:?}");
+}
+
+#[test]
+fn lp_shares_are_priced_without_outside_usdg_backing() {
+    let mut suite = Suite::new();
+    let (usdc, lp_token, user) = (
+        suite.usdc.clone(),
+        suite.lp_token.clone(),
+        suite.user.clone(),
+    );
+    suite
+        .set_fees(GOV, 0, 0, 0, 0, 0, 0, Uint128::new(5), 0, false)
+        .unwrap();
+
+    // the pool backs USDG held by the user, which no share owns
+    suite.buy_usdg(USER, &usdc, HUNDRED_USDC, USER).unwrap();
+    suite
+        .add_liquidity(USER, &usdc, HUNDRED_USDC, 0, 1)
+        .unwrap();
+    assert_eq!(suite.balance(&lp_token, &user), Uint128::new(HUNDRED_USDC));
+
+    // later deposits pay the same $1 per share
+    suite
+        .add_liquidity(USER, &usdc, HUNDRED_USDC, 0, 0)
+        .unwrap();
+    assert_eq!(
+        suite.balance(&lp_token, &user),
+        Uint128::new(2 * HUNDRED_USDC)
+    );
+    assert_eq!(suite.balance(&lp_token, &suite.vault), Uint128::zero());
+
+    let info: LiquidityInfoResponse = suite
+        .query(QueryMsg::LiquidityInfo { account: None })
+        .unwrap();
+    assert_eq!(info.aum_in_usdg_min, Uint128::new(2 * HUNDRED_USDC));
+}
+
+#[test]
+fn first_liquidity_seeds_value_no_share_owns() {
+    let mut suite = Suite::new();
+    let (usdc, lp_token, user) = (
+        suite.usdc.clone(),
+        suite.lp_token.clone(),
+        suite.user.clone(),
+    );
+    suite
+        .set_fees(GOV, 0, 0, 0, 0, 0, 0, Uint128::new(5), 0, false)
+        .unwrap();
+
+    // a direct deposit gives the pool value before any shares exist
+    suite
+        .direct_pool_deposit(USER, &usdc, HUNDRED_USDC)
+        .unwrap();
+    suite
+        .add_liquidity(USER, &usdc, HUNDRED_USDC, 0, 1)
+        .unwrap();
+
+    // the first depositor pays $1 per share and the vault holds the rest
+    assert_eq!(suite.balance(&lp_token, &user), Uint128::new(HUNDRED_USDC));
+    assert_eq!(
+        suite.balance(&lp_token, &suite.vault),
+        Uint128::new(HUNDRED_USDC)
+    );
+    assert_eq!(suite.supply(&lp_token), Uint128::new(2 * HUNDRED_USDC));
+}
+
+#[test]
+fn fee_reserves_are_converted_into_the_fee_token() {
+    let mut suite = Suite::new();
+    let (usdc, btc) = (suite.usdc.clone(), suite.btc.clone());
+    let (treasury, stakers) = (Addr::unchecked("treasury"), Addr::unchecked("stakers"));
+
+    suite.buy_usdg(USER, &usdc, HUNDRED_USDC, USER).unwrap();
+    suite.buy_usdg(USER, &btc, 10_000_000, USER).unwrap();
+    let usdc_fees = suite.amount(FEERESERVED, &usdc);
+    let btc_fees = suite.amount(FEERESERVED, &btc);
+    assert!(!usdc_fees.is_zero() && !btc_fees.is_zero());
+    let usdc_pool = suite.amount(POOLAMOUNT, &usdc);
+    let btc_pool = suite.amount(POOLAMOUNT, &btc);
+
+    suite.grant_role(GOV, Role::FeeKeeper, "keeper").unwrap();
+    let recipients = vec![
+        FeeRecipient {
+            address: treasury.clone(),
+            weight: Uint128::new(7000),
+        },
+        FeeRecipient {
+            address: stakers.clone(),
+            weight: Uint128::new(3000),
+        },
+    ];
+    let usdg = suite.usdg.clone();
+    suite
+        .set_fee_recipients(GOV, recipients.clone(), Some(&usdg))
+        .unwrap_err();
+    suite
+        .set_fee_recipients(GOV, recipients, Some(&usdc))
+        .unwrap();
+    suite.distribute_fees("keeper", None).unwrap();
+
+    // BTC fees are swapped into USDC at $60,000 without a swap fee
+    let converted = btc_fees.multiply_ratio(60_000u128, 100u128);
+    assert_eq!(
+        suite.balance(&usdc, &treasury) + suite.balance(&usdc, &stakers),
+        usdc_fees + converted
+    );
+    assert!(suite.balance(&btc, &treasury).is_zero());
+    assert!(suite.balance(&btc, &stakers).is_zero());
+    assert!(suite.amount(FEERESERVED, &usdc).is_zero());
+    assert!(suite.amount(FEERESERVED, &btc).is_zero());
+    assert_eq!(suite.amount(POOLAMOUNT, &btc), btc_pool + btc_fees);
+    assert_eq!(suite.amount(POOLAMOUNT, &usdc), usdc_pool - converted);
+
+    let report: CheckInvariantsResponse = suite
+        .query(QueryMsg::CheckInvariants { tokens: None })
+        .unwrap();
+    assert_eq!(report.violations, vec![]);
+}
+
+#[test]
+fn fee_reserves_are_split_in_kind_when_they_cannot_be_converted() {
+    for case in ["no fee keeper", "swaps disabled", "swaps paused"] {
+        let mut suite = Suite::new();
+        let (usdc, btc) = (suite.usdc.clone(), suite.btc.clone());
+        let treasury = Addr::unchecked("treasury");
+
+        suite.buy_usdg(USER, &btc, 10_000_000, USER).unwrap();
+        let btc_fees = suite.amount(FEERESERVED, &btc);
+        let recipients = vec![FeeRecipient {
+            address: treasury.clone(),
+            weight: Uint128::new(10000),
+        }];
+        suite
+            .set_fee_recipients(GOV, recipients, Some(&usdc))
+            .unwrap();
+        suite.grant_role(GOV, Role::FeeKeeper, "keeper").unwrap();
+
+        let caller = match case {
+            "no fee keeper" => USER,
+            "swaps disabled" => {
+                suite.set_is_swap_enabled(GOV, false).unwrap();
+                "keeper"
+            }
+            _ => {
+                suite
+                    .pause(GOV, Some(vec![PauseTarget::Swap]), "maintenance")
+                    .unwrap();
+                "keeper"
+            }
+        };
+        suite.distribute_fees(caller, None).unwrap();
+
+        assert_eq!(suite.balance(&btc, &treasury), btc_fees, "{case}");
+        assert!(suite.balance(&usdc, &treasury).is_zero(), "{case}");
+        assert!(suite.amount(FEERESERVED, &btc).is_zero(), "{case}");
+    }
+}
+
+#[test]
+fn bank_holds_deposits_and_pays_withdrawals() {
+    let mut suite = SuiteBuilder::new()
+        .with_custody(CustodyMode::Bank)
+        .with_cooldown_duration(0)
+        .build();
+    let (usdc, lp_token, user, vault, bank) = (
+        suite.usdc.clone(),
+        suite.lp_token.clone(),
+        suite.user.clone(),
+        suite.vault.clone(),
+        suite.bank.clone(),
+    );
+    let bank_before = suite.balance(&usdc, &bank);
+    let user_before = suite.balance(&usdc, &user);
+
+    // the second deposit is checked against what the bank already holds
+    for _ in 0..2 {
+        suite
+            .add_liquidity(USER, &usdc, HUNDRED_USDC / 2, 0, 0)
+            .unwrap();
+    }
+    assert_eq!(suite.balance(&usdc, &vault), Uint128::zero());
+    assert_eq!(
+        suite.balance(&usdc, &bank) - bank_before,
+        Uint128::new(HUNDRED_USDC)
+    );
+
+    let shares = suite.balance(&lp_token, &user);
+    suite
+        .remove_liquidity(USER, &usdc, shares.u128(), 0, USER)
+        .unwrap();
+    let paid = suite.balance(&usdc, &user) - (user_before - Uint128::new(HUNDRED_USDC));
+    assert!(!paid.is_zero());
+    assert_eq!(suite.balance(&usdc, &vault), Uint128::zero());
+    assert_eq!(
+        suite.balance(&usdc, &bank) - bank_before,
+        Uint128::new(HUNDRED_USDC) - paid
+    );
+    // what stays in the bank is the fees the vault still accounts for
+    assert_eq!(
+        suite.amount(POOLAMOUNT, &usdc) + suite.amount(FEERESERVED, &usdc),
+        Uint128::new(HUNDRED_USDC) - paid
+    );
+    let report: CheckInvariantsResponse = suite
+        .query(QueryMsg::CheckInvariants { tokens: None })
+        .unwrap();
+    assert_eq!(report.violations, vec![]);
+}
+
+#[test]
+fn failed_bank_payout_restores_usdg_and_pool() {
+    let mut suite = SuiteBuilder::new().with_custody(CustodyMode::Bank).build();
+    let (usdc, usdg, user) = (suite.usdc.clone(), suite.usdg.clone(), suite.user.clone());
+
+    suite.buy_usdg(USER, &usdc, HUNDRED_USDC, USER).unwrap();
+    let minted = suite.balance(&usdg, &user);
+    let pool = suite.amount(POOLAMOUNT, &usdc);
+    let usdc_before = suite.balance(&usdc, &user);
+
+    suite.set_bank_failing(true).unwrap();
+    suite.sell_usdg(USER, &usdc, minted.u128(), USER).unwrap();
+
+    assert_eq!(suite.balance(&usdg, &user), minted);
+    assert_eq!(suite.amount(POOLAMOUNT, &usdc), pool);
+    assert_eq!(suite.amount(USDGAMOUNT, &usdc), minted);
+    assert_eq!(suite.balance(&usdc, &user), usdc_before);
+    let report: CheckInvariantsResponse = suite
+        .query(QueryMsg::CheckInvariants { tokens: None })
+        .unwrap();
+    assert_eq!(report.violations, vec![]);
+
+    // once the bank recovers the same sale goes through, paid from the bank
+    suite.set_bank_failing(false).unwrap();
+    let bank_before = suite.balance(&usdc, &suite.bank);
+    suite.sell_usdg(USER, &usdc, minted.u128(), USER).unwrap();
+
+    assert_eq!(suite.supply(&usdg), Uint128::zero());
+    assert_eq!(
+        bank_before - suite.balance(&usdc, &suite.bank),
+        suite.balance(&usdc, &user) - usdc_before
+    );
+}
+
+#[test]
+fn only_gov_configures_tokens() {
+    let mut suite = Suite::new();
+    let usdc = suite.usdc.clone();
+
+    suite
+        .set_token_config(USER, &usdc, 6, 1, 0, 0, true, false)
+        .unwrap_err();
+    suite
+        .set_token_config(GOV, &usdc, 6, 1, 0, 0, true, false)
+        .unwrap();
+}
+
+#[test]
+fn token_decimals_must_match_cw20() {
+    let mut suite = Suite::new();
+    let btc = suite.btc.clone();
+
+    let err = suite
+        .set_token_config(GOV, &btc, 18, 3000, 75, 0, false, true)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::TokenDecimalsMismatch { decimals: 8, .. }
+    ));
+}
+
+#[test]
+fn usdg_round_trip_across_decimals() {
+    let mut suite = Suite::new();
+    let (usdc, btc, eth) = (suite.usdc.clone(), suite.btc.clone(), suite.eth.clone());
+    let (usdg, user) = (suite.usdg.clone(), suite.user.clone());
+    suite
+        .set_fees(GOV, 0, 0, 0, 0, 0, 10, Uint128::new(5), 0, false)
+        .unwrap();
+
+    for (token, decimals, usd) in [(&usdc, 6, 1), (&btc, 8, 60_000), (&eth, 18, 3_000)] {
+        let amount = 3 * 10u128.pow(decimals);
+        let start = suite.balance(token, &user);
+
+        // three whole tokens are worth three times the price in 6-decimal USDG
+        suite.buy_usdg(USER, token, amount, USER).unwrap();
+        let minted = suite.balance(&usdg, &user);
+        assert_eq!(minted, Uint128::new(3 * usd * 1_000_000));
+
+        suite.sell_usdg(USER, token, minted.u128(), USER).unwrap();
+        assert_eq!(suite.balance(token, &user), start);
+        assert_eq!(suite.amount(POOLAMOUNT, token), Uint128::zero());
+    }
+}
+
+#[test]
+fn simulations_match_usdg_and_swap_execution() {
+    let mut suite = Suite::new();
+    let (usdc, btc, usdg, user) = (
+        suite.usdc.clone(),
+        suite.btc.clone(),
+        suite.usdg.clone(),
+        suite.user.clone(),
+    );
+    let receiver = Addr::unchecked("receiver");
+    // with dynamic fees the sell fee depends on the USDG debt left after the burn
+    suite
+        .set_fees(GOV, 50, 5, 30, 30, 4, 10, Uint128::new(5), 0, true)
+        .unwrap();
+
+    let buy: SimulateBuyUsdgResponse = suite
+        .query(QueryMsg::SimulateBuyUsdg {
+            token: btc.clone(),
+            token_amount: Uint128::new(200_000_000),
+        })
+        .unwrap();
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+    assert_eq!(suite.balance(&usdg, &user), buy.mint_amount);
+    assert_eq!(suite.amount(FEERESERVED, &btc), buy.fee_amount);
+    suite
+        .buy_usdg(USER, &usdc, 100 * HUNDRED_USDC, USER)
+        .unwrap();
+
+    let swap: SimulateSwapResponse = suite
+        .query(QueryMsg::SimulateSwap {
+            token_in: usdc.clone(),
+            token_out: btc.clone(),
+            amount_in: Uint128::new(30 * HUNDRED_USDC),
+        })
+        .unwrap();
+    suite
+        .swap(USER, &usdc, &btc, 30 * HUNDRED_USDC, "receiver")
+        .unwrap();
+    assert_eq!(suite.balance(&btc, &receiver), swap.amount_out);
+    assert_eq!(
+        suite.amount(FEERESERVED, &btc),
+        buy.fee_amount + swap.fee_amount
+    );
+
+    let usdg_amount = Uint128::new(50 * HUNDRED_USDC);
+    let sell: SimulateSellUsdgResponse = suite
+        .query(QueryMsg::SimulateSellUsdg {
+            token: usdc.clone(),
+            usdg_amount,
+        })
+        .unwrap();
+    suite
+        .sell_usdg(USER, &usdc, usdg_amount.u128(), "receiver")
+        .unwrap();
+    assert_eq!(suite.balance(&usdc, &receiver), sell.amount_out);
+    assert_eq!(sell.redemption_amount, sell.amount_out + sell.fee_amount);
+
+    // more than the pool holds is rejected up front
+    suite
+        .query::<SimulateSellUsdgResponse>(QueryMsg::SimulateSellUsdg {
+            token: btc.clone(),
+            usdg_amount: suite.balance(&usdg, &user),
+        })
+        .unwrap_err();
+}
+
+#[test]
+fn simulations_match_long_position_changes() {
+    let mut suite = Suite::new();
+    let (btc, user) = (suite.btc.clone(), suite.user.clone());
+    let receiver = Addr::unchecked("receiver");
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+
+    // $60k of size on 0.1 BTC ($6k) of collateral
+    let size = Uint128::new(60_000) * PRICE_PRECISION;
+    let increase: SimulateIncreasePositionResponse = suite
+        .query(QueryMsg::SimulateIncreasePosition {
+            account: user.clone(),
+            collateral_token: btc.clone(),
+            index_token: btc.clone(),
+            collateral_amount: Uint128::new(10_000_000),
+            size_delta: size,
+            is_long: true,
+        })
+        .unwrap();
+    suite
+        .increase_position(USER, &btc, &btc, 10_000_000, size, true)
+        .unwrap();
+
+    let position = suite.position(USER, &btc, &btc, true).unwrap();
+    assert_eq!(increase.size, position.size);
+    assert_eq!(increase.collateral, position.collateral);
+    assert_eq!(increase.average_price, position.averagePrice);
+    // 10 bps margin fee on the size comes out of the collateral
+    assert_eq!(increase.fee_usd, Uint128::new(60) * PRICE_PRECISION);
+    assert_eq!(increase.leverage, Uint128::new(101_010));
+    assert!(increase.liquidation_price.unwrap() < position.averagePrice);
+
+    // funding accrues for an interval that no transaction has applied yet
+    suite.advance_time(8 * 60 * 60);
+    suite.set_price(&btc, 66_000).unwrap();
+
+    let decrease: SimulateDecreasePositionResponse = suite
+        .query(QueryMsg::SimulateDecreasePosition {
+            account: user.clone(),
+            collateral_token: btc.clone(),
+            index_token: btc.clone(),
+            collateral_delta: Uint128::zero(),
+            size_delta: size / Uint128::new(2),
+            is_long: true,
+        })
+        .unwrap();
+    suite
+        .decrease_position(
+            USER,
+            &btc,
+            &btc,
+            Uint128::zero(),
+            size / Uint128::new(2),
+            true,
+            "receiver",
+        )
+        .unwrap();
+
+    let position = suite.position(USER, &btc, &btc, true).unwrap();
+    assert!(decrease.has_profit);
+    assert_eq!(
+        decrease.realised_delta,
+        Uint128::new(3_000) * PRICE_PRECISION
+    );
+    assert!(decrease.fee_usd > Uint128::new(30) * PRICE_PRECISION);
+    assert_eq!(suite.balance(&btc, &receiver), decrease.amount_out);
+    assert_eq!(decrease.size, position.size);
+    assert_eq!(decrease.collateral, position.collateral);
+
+    // growing an existing position moves its average price
+    let increase: SimulateIncreasePositionResponse = suite
+        .query(QueryMsg::SimulateIncreasePosition {
+            account: user.clone(),
+            collateral_token: btc.clone(),
+            index_token: btc.clone(),
+            collateral_amount: Uint128::zero(),
+            size_delta: size / Uint128::new(2),
+            is_long: true,
+        })
+        .unwrap();
+    suite
+        .increase_position(USER, &btc, &btc, 0, size / Uint128::new(2), true)
+        .unwrap();
+    let position = suite.position(USER, &btc, &btc, true).unwrap();
+    assert_eq!(increase.average_price, position.averagePrice);
+    assert_eq!(increase.collateral, position.collateral);
+}
+
+#[test]
+fn simulated_short_close_matches_payout() {
+    let mut suite = Suite::new();
+    let (usdc, btc, user) = (suite.usdc.clone(), suite.btc.clone(), suite.user.clone());
+    let receiver = Addr::unchecked("receiver");
+    suite
+        .buy_usdg(USER, &usdc, 1_000 * HUNDRED_USDC, USER)
+        .unwrap();
+
+    let size = Uint128::new(5_000) * PRICE_PRECISION;
+    suite
+        .increase_position(USER, &usdc, &btc, 10 * HUNDRED_USDC, size, false)
+        .unwrap();
+    suite.set_price(&btc, 61_000).unwrap();
+
+    let decrease: SimulateDecreasePositionResponse = suite
+        .query(QueryMsg::SimulateDecreasePosition {
+            account: user,
+            collateral_token: usdc.clone(),
+            index_token: btc.clone(),
+            collateral_delta: Uint128::zero(),
+            size_delta: size,
+            is_long: false,
+        })
+        .unwrap();
+    suite
+        .decrease_position(USER, &usdc, &btc, Uint128::zero(), size, false, "receiver")
+        .unwrap();
+
+    assert!(!decrease.has_profit);
+    assert_eq!(decrease.size, Uint128::zero());
+    assert_eq!(decrease.liquidation_price, None);
+    assert_eq!(suite.balance(&usdc, &receiver), decrease.amount_out);
+    assert!(suite.position(USER, &usdc, &btc, false).is_none());
+}
+
+#[test]
+fn liquidation_price_is_where_liquidation_flips() {
+    let mut suite = Suite::new();
+    let (usdc, btc, user) = (suite.usdc.clone(), suite.btc.clone(), suite.user.clone());
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+    suite
+        .buy_usdg(USER, &usdc, 1_000 * HUNDRED_USDC, USER)
+        .unwrap();
+
+    // 10x long on 0.1 BTC and 5x short on $1k, with a funding interval accrued
+    suite
+        .increase_position(
+            USER,
+            &btc,
+            &btc,
+            10_000_000,
+            Uint128::new(60_000) * PRICE_PRECISION,
+            true,
+        )
+        .unwrap();
+    suite
+        .increase_position(
+            USER,
+            &usdc,
+            &btc,
+            10 * HUNDRED_USDC,
+            Uint128::new(5_000) * PRICE_PRECISION,
+            false,
+        )
+        .unwrap();
+    suite.advance_time(8 * 60 * 60);
+
+    for (collateral_token, is_long) in [(&btc, true), (&usdc, false)] {
+        let query = QueryMsg::LiquidationPrice {
+            account: user.clone(),
+            collateral_token: collateral_token.clone(),
+            index_token: btc.clone(),
+            is_long,
+        };
+        let res: LiquidationPriceResponse = suite.query(query.clone()).unwrap();
+        let price = res.liquidation_price.unwrap();
+        assert!(res.margin_fees > Uint128::zero());
+
+        // one unit on the safe side the position survives
+        let safe = if is_long {
+            price + Uint128::one()
+        } else {
+            price - Uint128::one()
+        };
+        suite.set_prices(&btc, safe, safe).unwrap();
+        suite
+            .liquidate_position(USER, USER, collateral_token, &btc, is_long, USER)
+            .unwrap_err();
+
+        suite.set_prices(&btc, price, price).unwrap();
+        let res: LiquidationPriceResponse = suite.query(query).unwrap();
+        assert_eq!(res.mark_price, price);
+        suite
+            .liquidate_position(USER, USER, collateral_token, &btc, is_long, USER)
+            .unwrap();
+        assert!(suite
+            .position(USER, collateral_token, &btc, is_long)
+            .is_none());
+    }
+}
+
+#[test]
+fn position_delta_applies_min_profit_window() {
+    let mut suite = Suite::new();
+    let (btc, user) = (suite.btc.clone(), suite.user.clone());
+    suite
+        .set_fees(GOV, 50, 5, 30, 30, 4, 10, Uint128::new(5), 3_600, false)
+        .unwrap();
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+    suite
+        .increase_position(
+            USER,
+            &btc,
+            &btc,
+            10_000_000,
+            Uint128::new(60_000) * PRICE_PRECISION,
+            true,
+        )
+        .unwrap();
+    let query = QueryMsg::PositionDelta {
+        account: user,
+        collateral_token: btc.clone(),
+        index_token: btc.clone(),
+        is_long: true,
+    };
+
+    // a 0.5% move is inside BTC's 75 bps min profit
+    suite.set_price(&btc, 60_300).unwrap();
+    let delta: PositionDeltaResponse = suite.query(query.clone()).unwrap();
+    assert!(delta.has_profit);
+    assert_eq!(delta.mark_price, Uint128::new(60_300) * PRICE_PRECISION);
+    assert_eq!(delta.delta, Uint128::new(300) * PRICE_PRECISION);
+    assert_eq!(delta.adjusted_delta, Uint128::zero());
+
+    suite.set_price(&btc, 60_600).unwrap();
+    let delta: PositionDeltaResponse = suite.query(query.clone()).unwrap();
+    assert_eq!(delta.adjusted_delta, Uint128::new(600) * PRICE_PRECISION);
+
+    // losses are never adjusted
+    suite.set_price(&btc, 59_700).unwrap();
+    let delta: PositionDeltaResponse = suite.query(query.clone()).unwrap();
+    assert!(!delta.has_profit);
+    assert_eq!(delta.adjusted_delta, Uint128::new(300) * PRICE_PRECISION);
+
+    // once min_profit_time has passed small profits count
+    suite.advance_time(3_601);
+    suite.set_price(&btc, 60_300).unwrap();
+    let delta: PositionDeltaResponse = suite.query(query).unwrap();
+    assert_eq!(delta.adjusted_delta, delta.delta);
+}
+
+#[test]
+fn liquidation_states_and_liquidator_stats() {
+    let mut suite = Suite::new();
+    let (btc, user) = (suite.btc.clone(), suite.user.clone());
+    let liquidation_fee_usd = Uint128::new(5);
+    suite
+        .set_fees(GOV, 50, 5, 30, 30, 4, 10, liquidation_fee_usd, 0, false)
+        .unwrap();
+    suite.set_in_private_liq_mode(GOV, true).unwrap();
+    suite.set_liquidator(GOV, "keeper", true).unwrap();
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+    let open = |suite: &mut Suite| {
+        suite
+            .increase_position(
+                USER,
+                &btc,
+                &btc,
+                10_000_000,
+                Uint128::new(60_000) * PRICE_PRECISION,
+                true,
+            )
+            .unwrap();
+    };
+    let stats = |suite: &Suite| -> LiquidatorStats {
+        suite
+            .query(QueryMsg::LiquidatorStats {
+                address: Addr::unchecked("keeper"),
+            })
+            .unwrap()
+    };
+
+    // private mode only lets registered keepers in
+    open(&mut suite);
+    suite
+        .liquidate_position(USER, USER, &btc, &btc, true, USER)
+        .unwrap_err();
+
+    // state 0: a healthy position can't be liquidated
+    suite
+        .liquidate_position("keeper", USER, &btc, &btc, true, "keeper")
+        .unwrap_err();
+    assert_eq!(stats(&suite), LiquidatorStats::default());
+
+    // state 2: over max leverage but solvent, the trader is paid out and the
+    // keeper earns nothing
+    suite.set_max_leverage(GOV, 50_000).unwrap();
+    let before = suite.balance(&btc, &user);
+    suite
+        .liquidate_position("keeper", USER, &btc, &btc, true, "keeper")
+        .unwrap();
+    assert!(suite.position(USER, &btc, &btc, true).is_none());
+    assert!(suite.balance(&btc, &user) > before);
+    assert_eq!(
+        suite.balance(&btc, &Addr::unchecked("keeper")),
+        Uint128::zero()
+    );
+    assert_eq!(stats(&suite), LiquidatorStats::default());
+
+    // state 1: losses exceed the collateral, the keeper is credited the
+    // liquidation fee
+    suite.set_max_leverage(GOV, 500_000).unwrap();
+    suite.set_price(&btc, 60_000).unwrap();
+    open(&mut suite);
+    suite.set_price(&btc, 50_000).unwrap();
+    suite
+        .liquidate_position("keeper", USER, &btc, &btc, true, "keeper")
+        .unwrap();
+    assert!(suite.position(USER, &btc, &btc, true).is_none());
+
+    let expected = LiquidatorStats {
+        liquidation_count: 1,
+        fees_earned_usd: liquidation_fee_usd,
+    };
+    assert_eq!(stats(&suite), expected);
+
+    let res: LiquidatorsResponse = suite
+        .query(QueryMsg::Liquidators {
+            start_after: None,
+            limit: None,
+        })
+        .unwrap();
+    assert!(res.in_private_liquidation_mode);
+    assert_eq!(
+        res.liquidators,
+        vec![LiquidatorInfo {
+            address: Addr::unchecked("keeper"),
+            is_active: true,
+            stats: expected,
+        }]
+    );
+}
+
+#[test]
+fn leverage_is_bounded_per_token() {
+    let mut suite = Suite::new();
+    let btc = suite.btc.clone();
+    suite
+        .set_fees(GOV, 0, 0, 0, 0, 0, 0, Uint128::new(5), 0, false)
+        .unwrap();
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+    suite
+        .set_token_max_leverage(GOV, &btc, Some(50_000))
+        .unwrap();
+
+    // $6k of collateral: $3k is under 1x, $36k is over the 5x override
+    let err = suite
+        .increase_position(
+            USER,
+            &btc,
+            &btc,
+            10_000_000,
+            Uint128::new(3_000) * PRICE_PRECISION,
+            true,
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::LeverageTooLow { leverage, min_leverage }
+            if leverage == Uint128::new(5_000) && min_leverage == Uint128::new(10_000)
+    ));
+    let err = suite
+        .increase_position(
+            USER,
+            &btc,
+            &btc,
+            10_000_000,
+            Uint128::new(36_000) * PRICE_PRECISION,
+            true,
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::LeverageTooHigh { leverage, max_leverage }
+            if leverage == Uint128::new(60_000) && max_leverage == Uint128::new(50_000)
+    ));
+
+    // the config-wide 50x applies again once the override is cleared
+    suite.set_token_max_leverage(GOV, &btc, None).unwrap();
+    suite
+        .increase_position(
+            USER,
+            &btc,
+            &btc,
+            10_000_000,
+            Uint128::new(36_000) * PRICE_PRECISION,
+            true,
+        )
+        .unwrap();
+}
+
+#[test]
+fn liquidation_uses_token_max_leverage() {
+    let mut suite = Suite::new();
+    let (btc, user) = (suite.btc.clone(), suite.user.clone());
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+    suite
+        .increase_position(
+            USER,
+            &btc,
+            &btc,
+            10_000_000,
+            Uint128::new(30_000) * PRICE_PRECISION,
+            true,
+        )
+        .unwrap();
+    let query = QueryMsg::LiquidationPrice {
+        account: user,
+        collateral_token: btc.clone(),
+        index_token: btc.clone(),
+        is_long: true,
+    };
+    let res: LiquidationPriceResponse = suite.query(query.clone()).unwrap();
+    let default_price = res.liquidation_price.unwrap();
+
+    // a 10x cap on BTC leaves less room than the config-wide 50x
+    suite
+        .set_token_max_leverage(GOV, &btc, Some(100_000))
+        .unwrap();
+    let res: LiquidationPriceResponse = suite.query(query).unwrap();
+    let price = res.liquidation_price.unwrap();
+    assert!(price > default_price);
+
+    let safe = price + Uint128::one();
+    suite.set_prices(&btc, safe, safe).unwrap();
+    suite
+        .liquidate_position(USER, USER, &btc, &btc, true, USER)
+        .unwrap_err();
+    suite.set_prices(&btc, price, price).unwrap();
+    suite
+        .liquidate_position(USER, USER, &btc, &btc, true, USER)
+        .unwrap();
+    assert!(suite.position(USER, &btc, &btc, true).is_none());
+}
+
+#[test]
+fn only_the_trader_reduces_collateral() {
+    let mut suite = Suite::new();
+    let (btc, user) = (suite.btc.clone(), suite.user.clone());
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+    suite
+        .increase_position(
+            USER,
+            &btc,
+            &btc,
+            10_000_000,
+            Uint128::new(30_000) * PRICE_PRECISION,
+            true,
+        )
+        .unwrap();
+    let collateral_delta = Uint128::new(1_000) * PRICE_PRECISION;
+
+    let err = suite
+        .execute(
+            GOV,
+            ExecuteMsg::ReduceCollateral {
+                _account: user.clone(),
+                _collateralToken: btc.clone(),
+                _indexToken: btc.clone(),
+                _collateralDelta: collateral_delta,
+                _sizeDelta: Uint128::zero(),
+                _isLong: true,
+            },
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::Unauthorized {}
+    ));
+
+    let collateral = suite.position(USER, &btc, &btc, true).unwrap().collateral;
+    let before = suite.balance(&btc, &user);
+    suite
+        .reduce_collateral(USER, &btc, &btc, collateral_delta, Uint128::zero(), true)
+        .unwrap();
+    let position = suite.position(USER, &btc, &btc, true).unwrap();
+    assert_eq!(position.collateral, collateral - collateral_delta);
+    // $1k at $60k per BTC
+    assert_eq!(suite.balance(&btc, &user), before + Uint128::new(1_666_666));
+}
+
+#[test]
+fn only_the_trader_decreases_a_position() {
+    let mut suite = Suite::new();
+    let (btc, user) = (suite.btc.clone(), suite.user.clone());
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+    let size = Uint128::new(30_000) * PRICE_PRECISION;
+    suite
+        .increase_position(USER, &btc, &btc, 10_000_000, size, true)
+        .unwrap();
+
+    // a third party can't close the position and take the payout
+    let err = suite
+        .execute(
+            GOV,
+            ExecuteMsg::DecreasePosition {
+                _account: user.clone(),
+                _collateralToken: btc.clone(),
+                _indexToken: btc.clone(),
+                _collateralDelta: Uint128::zero(),
+                _sizeDelta: size,
+                _isLong: true,
+                _receiver: Addr::unchecked(GOV),
+            },
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::Unauthorized {}
+    ));
+    assert_eq!(suite.position(USER, &btc, &btc, true).unwrap().size, size);
+    assert!(suite.balance(&btc, &Addr::unchecked(GOV)).is_zero());
+
+    suite
+        .decrease_position(USER, &btc, &btc, Uint128::zero(), size, true, USER)
+        .unwrap();
+    assert!(suite.position(USER, &btc, &btc, true).is_none());
+}
+
+#[test]
+fn reserves_follow_positions_within_pool_capacity() {
+    let mut suite = Suite::new();
+    let btc = suite.btc.clone();
+    suite
+        .set_fees(GOV, 0, 0, 0, 0, 0, 0, Uint128::new(5), 0, false)
+        .unwrap();
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+    let size = Uint128::new(42_000) * PRICE_PRECISION;
+
+    // 2 BTC pooled and 1.5 BTC buffered: a 0.7 BTC reserve doesn't fit
+    suite.set_buffer_amount(GOV, &btc, 150_000_000).unwrap();
+    let err = suite
+        .increase_position(USER, &btc, &btc, 10_000_000, size, true)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::InsufficientPoolCapacity { reserved, available, .. }
+            if reserved == Uint128::new(70_000_000) && available == Uint128::new(50_000_000)
+    ));
+
+    suite.set_buffer_amount(GOV, &btc, 100_000_000).unwrap();
+    suite
+        .increase_position(USER, &btc, &btc, 10_000_000, size, true)
+        .unwrap();
+    assert_eq!(
+        suite.amount(RESERVEDAMOUNTS, &btc),
+        Uint128::new(70_000_000)
+    );
+
+    suite
+        .decrease_position(
+            USER,
+            &btc,
+            &btc,
+            Uint128::zero(),
+            size / Uint128::new(2),
+            true,
+            USER,
+        )
+        .unwrap();
+    assert_eq!(
+        suite.amount(RESERVEDAMOUNTS, &btc),
+        Uint128::new(35_000_000)
+    );
+
+    // a liquidation releases the rest and deletes the position
+    suite.set_price(&btc, 30_000).unwrap();
+    suite
+        .liquidate_position(USER, USER, &btc, &btc, true, USER)
+        .unwrap();
+    assert!(suite.position(USER, &btc, &btc, true).is_none());
+    assert_eq!(suite.amount(RESERVEDAMOUNTS, &btc), Uint128::zero());
+}
+
+#[test]
+fn global_short_size_follows_positions() {
+    let mut suite = Suite::new();
+    let (usdc, btc) = (suite.usdc.clone(), suite.btc.clone());
+    suite
+        .set_fees(GOV, 0, 0, 0, 0, 0, 0, Uint128::new(5), 0, false)
+        .unwrap();
+    suite
+        .buy_usdg(USER, &usdc, 1_000 * HUNDRED_USDC, USER)
+        .unwrap();
+    let size = Uint128::new(5_000) * PRICE_PRECISION;
+    let short_pnl = |suite: &Suite| -> GlobalShortPnlResponse {
+        suite
+            .query(QueryMsg::GlobalShortPnl { token: btc.clone() })
+            .unwrap()
+    };
+
+    suite
+        .set_max_global_short_size(USER, &btc, 8_000 * PRICE_PRECISION.u128())
+        .unwrap_err();
+    suite
+        .set_max_global_short_size(GOV, &btc, 8_000 * PRICE_PRECISION.u128())
+        .unwrap();
+    suite
+        .increase_position(USER, &usdc, &btc, 10 * HUNDRED_USDC, size, false)
+        .unwrap();
+    let pnl = short_pnl(&suite);
+    assert_eq!(pnl.size, Uint256::from(size));
+    assert_eq!(pnl.average_price, Uint128::new(60_000) * PRICE_PRECISION);
+    assert_eq!(pnl.delta, Uint256::zero());
+
+    let err = suite
+        .increase_position(USER, &usdc, &btc, 10 * HUNDRED_USDC, size, false)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::MaxGlobalShortSizeExceeded { .. }
+    ));
+
+    // a 10% drop is a 10% profit for shorts and a loss for the pool
+    suite.set_price(&btc, 54_000).unwrap();
+    let pnl = short_pnl(&suite);
+    assert!(pnl.has_profit);
+    assert_eq!(
+        pnl.delta,
+        Uint256::from(Uint128::new(500) * PRICE_PRECISION)
+    );
+
+    suite
+        .decrease_position(
+            USER,
+            &usdc,
+            &btc,
+            Uint128::zero(),
+            size / Uint128::new(2),
+            false,
+            USER,
+        )
+        .unwrap();
+    assert_eq!(
+        short_pnl(&suite).size,
+        Uint256::from(size / Uint128::new(2))
+    );
+    suite
+        .decrease_position(
+            USER,
+            &usdc,
+            &btc,
+            Uint128::zero(),
+            size / Uint128::new(2),
+            false,
+            USER,
+        )
+        .unwrap();
+    assert_eq!(short_pnl(&suite).size, Uint256::zero());
+}
+
+#[test]
+fn long_open_interest_follows_positions() {
+    let mut suite = Suite::new();
+    let btc = suite.btc.clone();
+    suite
+        .set_fees(GOV, 0, 0, 0, 0, 0, 0, Uint128::new(5), 0, false)
+        .unwrap();
+    suite.buy_usdg(USER, &btc, 200_000_000, USER).unwrap();
+    let size = Uint128::new(36_000) * PRICE_PRECISION;
+    let open_interest = |suite: &Suite| -> OpenInterestResponse {
+        suite
+            .query(QueryMsg::OpenInterest { token: btc.clone() })
+            .unwrap()
+    };
+
+    suite
+        .set_max_global_long_size(USER, &btc, 50_000 * PRICE_PRECISION.u128())
+        .unwrap_err();
+    suite
+        .set_max_global_long_size(GOV, &btc, 50_000 * PRICE_PRECISION.u128())
+        .unwrap();
+    suite
+        .increase_position(USER, &btc, &btc, 10_000_000, size, true)
+        .unwrap();
+    let oi = open_interest(&suite);
+    assert_eq!(oi.long_size, Uint256::from(size));
+    assert_eq!(
+        oi.long_average_price,
+        Uint128::new(60_000) * PRICE_PRECISION
+    );
+    assert_eq!(
+        oi.max_long_size,
+        Uint256::from(Uint128::new(50_000) * PRICE_PRECISION)
+    );
+    assert_eq!(oi.short_size, Uint256::zero());
+
+    let err = suite
+        .increase_position(USER, &btc, &btc, 10_000_000, size, true)
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast::<ContractError>().unwrap(),
+        ContractError::MaxGlobalLongSizeExceeded { .. }
+    ));
+
+    // adding at a higher price moves the average up
+    suite.set_price(&btc, 66_000).unwrap();
+    suite
+        .increase_position(
+            USER,
+            &btc,
+            &btc,
+            0,
+            Uint128::new(6_000) * PRICE_PRECISION,
+            true,
+        )
+        .unwrap();
+    let oi = open_interest(&suite);
+    assert_eq!(
+        oi.long_size,
+        Uint256::from(Uint128::new(42_000) * PRICE_PRECISION)
+    );
+    assert!(oi.long_average_price > Uint128::new(60_000) * PRICE_PRECISION);
+    assert!(oi.long_average_price < Uint128::new(66_000) * PRICE_PRECISION);
+
+    suite
+        .decrease_position(
+            USER,
+            &btc,
+            &btc,
+            Uint128::zero(),
+            Uint128::new(42_000) * PRICE_PRECISION,
+            true,
+            USER,
+        )
+        .unwrap();
+    assert_eq!(open_interest(&suite).long_size, Uint256::zero());
+}
+
+#[test]
+fn aum_values_pools_guaranteed_usd_and_short_pnl() {
+    let mut suite = Suite::new();
+    let (usdc, btc) = (suite.usdc.clone(), suite.btc.clone());
+    suite
+        .set_fees(GOV, 0, 0, 0, 0, 0, 0, Uint128::new(5), 0, false)
+        .unwrap();
+    let aum = |suite: &Suite| -> Uint256 { suite.query(QueryMsg::Aum { maximise: true }).unwrap() };
+    let usd = |amount: u128| Uint256::from(Uint128::new(amount) * PRICE_PRECISION);
+
+    suite
+        .buy_usdg(USER, &usdc, 10 * HUNDRED_USDC, USER)
+        .unwrap();
+    suite.buy_usdg(USER, &btc, 10_000_000, USER).unwrap();
+    assert_eq!(aum(&suite), usd(7_000));
+
+    // non-stable pools are marked to price
+    suite.set_price(&btc, 66_000).unwrap();
+    assert_eq!(aum(&suite), usd(7_600));
+
+    // a long's collateral and reserve leave the pool value where it was
+    suite
+        .increase_position(
+            USER,
+            &btc,
+            &btc,
+            5_000_000,
+            Uint128::new(6_600) * PRICE_PRECISION,
+            true,
+        )
+        .unwrap();
+    assert_eq!(aum(&suite), usd(7_600));
+
+    // short profits are owed by the pool
+    suite
+        .increase_position(
+            USER,
+            &usdc,
+            &btc,
+            HUNDRED_USDC,
+            Uint128::new(1_000) * PRICE_PRECISION,
+            false,
+        )
+        .unwrap();
+    suite.set_price(&btc, 59_400).unwrap();
+    // $3.3k guaranteed on the long plus 0.05 unreserved BTC, less $100 owed
+    // to the short
+    assert_eq!(aum(&suite), usd(1_000 + 3_300 + 2_970 - 100));
+}
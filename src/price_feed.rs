@@ -0,0 +1,36 @@
+use cosmwasm_std::{to_binary, Addr, Deps, QueryRequest, StdResult, Uint128, WasmQuery};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryPriceFeedMsg {
+    GetPrice {
+        token: String,
+        maximise: bool,
+        include_amm_price: bool,
+        use_swap_pricing: bool,
+    },
+}
+
+/// Gets a token price scaled by PRICE_PRECISION from the price feed contract
+pub fn get_price(
+    deps: Deps,
+    price_feed: Addr,
+    token: Addr,
+    maximise: bool,
+    include_amm_price: bool,
+    use_swap_pricing: bool,
+) -> StdResult<Uint128> {
+    let query_msg = QueryPriceFeedMsg::GetPrice {
+        token: token.to_string(),
+        maximise,
+        include_amm_price,
+        use_swap_pricing,
+    };
+
+    deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: price_feed.to_string(),
+        msg: to_binary(&query_msg)?,
+    }))
+}
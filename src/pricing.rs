@@ -0,0 +1,1046 @@
+//! Read-only pricing, fee, funding and position valuation.
+//!
+//! Everything here takes `Deps`, so the query entry point, the Simulate*
+//! quotes and the execute handlers share one implementation. State changes
+//! stay in `helpers` and `contract`.
+
+use cosmwasm_std::{Addr, Deps, Env, StdResult, Uint128, Uint256};
+
+use crate::{
+    contract::{BASIS_POINTS_DIVISOR, FUNDING_RATE_PRECISION, PRICE_PRECISION, USDG_DECIMALS},
+    helpers::{cw20_balance_of, total_supply_cw20_tokens, validate},
+    math::{mul_div, mul_div_256, Rounding},
+    msg::PositionDeltaResponse,
+    price_feed,
+    query::{get_position, get_position_key, query_max_leverage},
+    state::{
+        Position, CONFIG, CUMULATIVEFUNDINGRATE, GLOBALLONGAVERAGEPRICE, GLOBALLONGSIZE,
+        GLOBALSHORTAVERAGEPRICE, GLOBALSHORTSIZE, GUARANTEEUSD, LASTFUNDINTIME,
+        MINPROFITBASISPOINT, POOLAMOUNT, RESERVEDAMOUNTS, STABLETOKEN, TOKENDECIMAL, TOKENWEIGHT,
+        USDGAMOUNT, WHITELISTEDTOKEN,
+    },
+    ContractError,
+};
+
+pub fn get_token_price(
+    _deps: Deps,
+    _token: Addr,
+    _maximise: bool,
+) -> Result<Uint128, ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+    query_price(_deps, _token, _maximise, config.use_swap_pricing)
+}
+
+/// Price for swaps and USDG mints and burns. Passed to the feed per call
+/// instead of being toggled in CONFIG, so queries can price the same way.
+pub fn get_swap_price(
+    _deps: Deps,
+    _token: Addr,
+    _maximise: bool,
+) -> Result<Uint128, ContractError> {
+    query_price(_deps, _token, _maximise, true)
+}
+
+fn query_price(
+    _deps: Deps,
+    _token: Addr,
+    _maximise: bool,
+    _use_swap_pricing: bool,
+) -> Result<Uint128, ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+    let price = price_feed::get_price(
+        _deps,
+        config.price_feed,
+        _token,
+        _maximise,
+        config.include_amm_price,
+        _use_swap_pricing,
+    )?;
+    validate(price > Uint128::zero(), "ERR_INVALID_PRICE")?;
+
+    Ok(price)
+}
+
+pub fn get_min_price(_deps: Deps, _token: Addr) -> Result<Uint128, ContractError> {
+    get_token_price(_deps, _token, false)
+}
+
+pub fn get_max_price(_deps: Deps, _token: Addr) -> Result<Uint128, ContractError> {
+    get_token_price(_deps, _token, true)
+}
+
+pub fn adjust_decimal(
+    _deps: Deps,
+    _tokenDev: Addr,
+    _tokenMul: Addr,
+    _amount: Uint128,
+) -> Result<Uint128, ContractError> {
+    let res = mul_div(
+        _amount,
+        token_unit(_deps, &_tokenMul)?,
+        token_unit(_deps, &_tokenDev)?,
+        Rounding::Down,
+    )?;
+
+    Ok(res)
+}
+
+/// One whole `_token` in its smallest unit: 10^TOKENDECIMAL, or
+/// 10^USDG_DECIMALS for USDG. Every token amount is valued through this, so
+/// it has to match the decimals the cw20 reports.
+pub fn token_unit(_deps: Deps, _token: &Addr) -> Result<Uint128, ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+    let decimals = if *_token == config.usdg {
+        USDG_DECIMALS
+    } else {
+        TOKENDECIMAL.load(_deps.storage, _token.clone())?
+    };
+
+    Ok(Uint128::new(10).pow(decimals.u128() as u32))
+}
+
+pub fn usd_to_token_min(
+    deps: Deps,
+    _token: Addr,
+    _usd_amount: u128,
+) -> Result<u128, ContractError> {
+    if _usd_amount == 0 {
+        return Ok(0);
+    }
+    let max_price = get_token_price(deps, _token.clone(), true)?;
+    let result = usd_to_token(deps, &_token, _usd_amount, max_price.u128())?;
+    Ok(result)
+}
+
+pub fn usd_to_token(
+    _deps: Deps,
+    _token: &Addr,
+    _usd_amount: u128,
+    _price: u128,
+) -> Result<u128, ContractError> {
+    if _usd_amount == 0 {
+        return Ok(0);
+    }
+    let result = mul_div(
+        Uint128::new(_usd_amount),
+        token_unit(_deps, _token)?,
+        Uint128::new(_price),
+        Rounding::Down,
+    )?;
+    Ok(result.u128())
+}
+
+/// Value in USD of `_token_amount` at `_price`; the inverse of usd_to_token.
+pub fn token_to_usd(
+    _deps: Deps,
+    _token: &Addr,
+    _token_amount: u128,
+    _price: u128,
+) -> Result<u128, ContractError> {
+    if _token_amount == 0 {
+        return Ok(0);
+    }
+    let result = mul_div(
+        Uint128::new(_token_amount),
+        Uint128::new(_price),
+        token_unit(_deps, _token)?,
+        Rounding::Down,
+    )?;
+    Ok(result.u128())
+}
+
+pub fn usdToTokenMax(
+    _deps: Deps,
+    _token: Addr,
+    _usdAmount: Uint128,
+) -> Result<Uint128, ContractError> {
+    if _usdAmount == Uint128::zero() {
+        return Ok(Uint128::zero());
+    } else {
+        let price = get_token_price(_deps, _token.clone(), false)?;
+        let res = usd_to_token(_deps, &_token, _usdAmount.u128(), price.u128())?;
+        Ok(Uint128::new(res))
+    }
+}
+
+pub fn token_to_usd_min(
+    deps: Deps,
+    _token: Addr,
+    _token_amount: u128,
+) -> Result<Uint128, ContractError> {
+    if _token_amount == 0 {
+        return Ok(Uint128::zero());
+    }
+    let price: Uint128 = get_token_price(deps, _token.clone(), false)?;
+    let result = token_to_usd(deps, &_token, _token_amount, price.u128())?;
+    Ok(Uint128::new(result))
+}
+
+/// Splits `_amount` into what is left after `_fee_basis_points` and the fee.
+pub fn apply_fee(
+    _amount: Uint128,
+    _fee_basis_points: Uint128,
+) -> Result<(Uint128, Uint128), ContractError> {
+    // the fee is the remainder, so it rounds up
+    let afterFeeAmount = mul_div(
+        _amount,
+        BASIS_POINTS_DIVISOR.checked_sub(_fee_basis_points)?,
+        BASIS_POINTS_DIVISOR,
+        Rounding::Down,
+    )?;
+
+    Ok((afterFeeAmount, _amount - afterFeeAmount))
+}
+
+pub fn getBuyUsdgFeeBasisPoints(
+    _deps: Deps,
+    _token: Addr,
+    _usdgAmount: Uint128,
+) -> Result<Uint128, ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+    getFeeBasisPoints(
+        _deps,
+        _token,
+        _usdgAmount,
+        config.mint_burn_fee_basis_points,
+        config.tax_basis_points,
+        true,
+    )
+}
+
+pub fn getSellUsdgFeeBasisPoints(
+    _deps: Deps,
+    _token: Addr,
+    _usdgAmount: Uint128,
+) -> Result<Uint128, ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+    getFeeBasisPoints(
+        _deps,
+        _token,
+        _usdgAmount,
+        config.mint_burn_fee_basis_points,
+        config.tax_basis_points,
+        false,
+    )
+}
+
+pub fn getSwapFeeBasisPoints(
+    _deps: Deps,
+    _tokenin: Addr,
+    _tokenout: Addr,
+    _usdgAmount: Uint128,
+) -> Result<Uint128, ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+    let isStableSwap = STABLETOKEN
+        .may_load(_deps.storage, _tokenin.clone())?
+        .unwrap_or(false)
+        && STABLETOKEN
+            .may_load(_deps.storage, _tokenout.clone())?
+            .unwrap_or(false);
+
+    let (baseBps, taxBps) = if isStableSwap {
+        (
+            config.stable_swap_fee_basis_points,
+            config.stable_tax_basis_points,
+        )
+    } else {
+        (config.swap_fee_basis_points, config.tax_basis_points)
+    };
+
+    let feesBasisPoints0 = getFeeBasisPoints(_deps, _tokenin, _usdgAmount, baseBps, taxBps, true)?;
+    let feesBasisPoints1 =
+        getFeeBasisPoints(_deps, _tokenout, _usdgAmount, baseBps, taxBps, false)?;
+
+    // use the higher of the two fee basis points
+    Ok(feesBasisPoints0.max(feesBasisPoints1))
+}
+
+/// Base fee plus a tax for moving the token's USDG debt away from its target
+/// weight, or minus a rebate for moving it closer.
+pub fn getFeeBasisPoints(
+    _deps: Deps,
+    _token: Addr,
+    _usdgDelta: Uint128,
+    _feeBasisPoints: Uint128,
+    _taxBasisPoints: Uint128,
+    _increment: bool,
+) -> Result<Uint128, ContractError> {
+    let initialAmount = USDGAMOUNT
+        .may_load(_deps.storage, _token.clone())?
+        .unwrap_or_default();
+
+    getFeeBasisPointsFrom(
+        _deps,
+        _token,
+        initialAmount,
+        _usdgDelta,
+        _feeBasisPoints,
+        _taxBasisPoints,
+        _increment,
+    )
+}
+
+/// getFeeBasisPoints against an explicit starting USDG debt, for callers
+/// pricing a trade whose debt change has already been applied.
+pub fn getFeeBasisPointsFrom(
+    _deps: Deps,
+    _token: Addr,
+    initialAmount: Uint128,
+    _usdgDelta: Uint128,
+    _feeBasisPoints: Uint128,
+    _taxBasisPoints: Uint128,
+    _increment: bool,
+) -> Result<Uint128, ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+    if !config.has_dynamic_fees {
+        return Ok(_feeBasisPoints);
+    }
+
+    let nextAmount = if _increment {
+        initialAmount + _usdgDelta
+    } else {
+        initialAmount.saturating_sub(_usdgDelta)
+    };
+
+    let targetAmount = getTargetUsdgAmount(_deps, _token)?;
+    if targetAmount.is_zero() {
+        return Ok(_feeBasisPoints);
+    }
+
+    let initialDiff = initialAmount.max(targetAmount) - initialAmount.min(targetAmount);
+    let nextDiff = nextAmount.max(targetAmount) - nextAmount.min(targetAmount);
+
+    // action improves relative asset balance
+    if nextDiff < initialDiff {
+        let rebateBps = _taxBasisPoints.multiply_ratio(initialDiff, targetAmount);
+        return Ok(_feeBasisPoints.saturating_sub(rebateBps));
+    }
+
+    let averageDiff = ((initialDiff + nextDiff) / Uint128::new(2)).min(targetAmount);
+    let taxBps = _taxBasisPoints.multiply_ratio(averageDiff, targetAmount);
+
+    Ok(_feeBasisPoints + taxBps)
+}
+
+/// USDG debt the token should carry given its share of the total token weight.
+pub fn getTargetUsdgAmount(_deps: Deps, _token: Addr) -> Result<Uint128, ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+    let supply = total_supply_cw20_tokens(_deps, config.usdg)?;
+    if supply.is_zero() || config.total_token_weights.is_zero() {
+        return Ok(Uint128::zero());
+    }
+
+    let weight = TOKENWEIGHT
+        .may_load(_deps.storage, _token)?
+        .unwrap_or_default();
+
+    Ok(supply.multiply_ratio(weight, config.total_token_weights))
+}
+
+/// Margin fee in USD charged on `_size_delta`.
+pub fn get_position_fee(
+    _deps: Deps,
+    _account: Addr,
+    _collateral_token: Addr,
+    _index_token: Addr,
+    _is_long: bool,
+    _size_delta: u128,
+) -> Result<Uint128, ContractError> {
+    if _size_delta == 0 {
+        return Ok(Uint128::zero());
+    }
+    let config = CONFIG.load(_deps.storage)?;
+
+    mul_div(
+        Uint128::new(_size_delta),
+        config.margin_fee_basis_points,
+        BASIS_POINTS_DIVISOR,
+        Rounding::Up,
+    )
+}
+
+pub fn getPositionFee(
+    _deps: Deps,
+    _account: Addr,
+    _collateralToken: Addr,
+    _indexToken: Addr,
+    _isLong: bool,
+    _sizeDelta: Uint128,
+) -> Result<Uint128, ContractError> {
+    get_position_fee(
+        _deps,
+        _account,
+        _collateralToken,
+        _indexToken,
+        _isLong,
+        _sizeDelta.u128(),
+    )
+}
+
+/// Margin fee on `_size_delta` plus the funding accrued on `_size`, in USD.
+pub fn get_margin_fees(
+    deps: Deps,
+    _env: &Env,
+    _account: Addr,
+    _collateral_token: Addr,
+    _index_token: Addr,
+    _is_long: bool,
+    _size_delta: u128,
+    _size: u128,
+    _entry_funding_rate: u128,
+) -> Result<Uint128, ContractError> {
+    let feeUsd = get_position_fee(
+        deps,
+        _account.clone(),
+        _collateral_token.clone(),
+        _index_token.clone(),
+        _is_long,
+        _size_delta,
+    )?;
+    let fundingFee = get_funding_fee(
+        deps,
+        _env,
+        _account,
+        _collateral_token,
+        _index_token,
+        _is_long,
+        _size,
+        _entry_funding_rate,
+    )?;
+
+    Ok(feeUsd.checked_add(fundingFee)?)
+}
+
+pub fn getNextFundingRate(
+    _deps: Deps,
+    _env: &Env,
+    _collateralToken: Addr,
+) -> Result<Uint128, ContractError> {
+    let lastFundingTimes = LASTFUNDINTIME.load(_deps.storage, _collateralToken.clone())?;
+    let config = CONFIG.load(_deps.storage)?;
+    let fundinginterval = config.funding_interval;
+
+    if lastFundingTimes + fundinginterval > _env.block.time.seconds() as u128 {
+        return Ok(Uint128::zero());
+    }
+
+    let intervals: u128 =
+        ((_env.block.time.seconds() as u128) - lastFundingTimes) / fundinginterval;
+    let poolAmount = POOLAMOUNT.load(_deps.storage, _collateralToken.clone())?;
+    if poolAmount == Uint128::zero() {
+        return Ok(Uint128::zero());
+    }
+
+    let _fundingRateFactor: u128;
+
+    let stableToken = STABLETOKEN.load(_deps.storage, _collateralToken.clone())?;
+
+    if stableToken {
+        _fundingRateFactor = config.stable_funding_rate_factor;
+    } else {
+        _fundingRateFactor = config.funding_rate_factor
+    }
+    let reserve_amount = RESERVEDAMOUNTS.load(_deps.storage, _collateralToken.clone())?;
+
+    // funding is owed by traders, so the rate rounds up
+    mul_div(
+        reserve_amount,
+        Uint128::new(_fundingRateFactor).checked_mul(Uint128::new(intervals))?,
+        poolAmount,
+        Rounding::Up,
+    )
+}
+
+/// CUMULATIVEFUNDINGRATE as it will be once the pending funding intervals
+/// are applied. A token whose funding clock hasn't started has none pending.
+pub fn get_cumulative_funding_rate(
+    _deps: Deps,
+    _env: &Env,
+    _collateral_token: Addr,
+) -> Result<Uint128, ContractError> {
+    let cumulative_funding_rate = CUMULATIVEFUNDINGRATE
+        .may_load(_deps.storage, _collateral_token.clone())?
+        .unwrap_or_default();
+    let last_funding_time = LASTFUNDINTIME
+        .may_load(_deps.storage, _collateral_token.clone())?
+        .unwrap_or_default();
+    if last_funding_time == 0 {
+        return Ok(cumulative_funding_rate);
+    }
+
+    let pending = getNextFundingRate(_deps, _env, _collateral_token)?;
+    Ok(cumulative_funding_rate.checked_add(pending)?)
+}
+
+pub fn get_entry_funding_rate(
+    _deps: Deps,
+    _collateral_token: Addr,
+    _index_token: Addr,
+    _is_long: bool,
+) -> StdResult<Uint128> {
+    Ok(CUMULATIVEFUNDINGRATE
+        .may_load(_deps.storage, _collateral_token)?
+        .unwrap_or_default())
+}
+
+/// Funding in USD accrued on `_size` since the position's entry funding rate,
+/// including intervals not yet folded into CUMULATIVEFUNDINGRATE.
+pub fn get_funding_fee(
+    _deps: Deps,
+    _env: &Env,
+    _account: Addr,
+    _collateral_token: Addr,
+    _index_token: Addr,
+    _is_long: bool,
+    _size: u128,
+    _entry_funding_rate: u128,
+) -> Result<Uint128, ContractError> {
+    if _size == 0 {
+        return Ok(Uint128::zero());
+    }
+    let cumulative_funding_rate = get_cumulative_funding_rate(_deps, _env, _collateral_token)?;
+    let funding_rate = cumulative_funding_rate.saturating_sub(Uint128::new(_entry_funding_rate));
+    if funding_rate.is_zero() {
+        return Ok(Uint128::zero());
+    }
+
+    mul_div(
+        Uint128::new(_size),
+        funding_rate,
+        FUNDING_RATE_PRECISION,
+        Rounding::Up,
+    )
+}
+
+/// Share of the token's pool reserved for positions, in FUNDING_RATE_PRECISION.
+pub fn get_utilisation(_deps: Deps, _token: Addr) -> Result<Uint128, ContractError> {
+    let poolAmount = POOLAMOUNT.load(_deps.storage, _token.clone())?;
+
+    if poolAmount.is_zero() {
+        return Ok(Uint128::zero());
+    }
+    let reservedAmounts = RESERVEDAMOUNTS.load(_deps.storage, _token)?;
+    let res = mul_div(
+        reservedAmounts,
+        FUNDING_RATE_PRECISION,
+        poolAmount,
+        Rounding::Down,
+    )?;
+
+    Ok(res)
+}
+
+pub fn get_delta(
+    _deps: Deps,
+    _env: &Env,
+    _index_token: Addr,
+    _size: Uint128,
+    _average_price: Uint128,
+    _is_long: bool,
+    _last_increased_time: u64,
+) -> Result<(bool, Uint128), ContractError> {
+    let delta = get_position_delta(
+        _deps,
+        _env,
+        _index_token,
+        _size,
+        _average_price,
+        _is_long,
+        _last_increased_time,
+    )?;
+
+    Ok((delta.has_profit, delta.adjusted_delta))
+}
+
+/// Profit or loss of `_size` opened at `_average_price`, marked at the min
+/// price for longs and the max price for shorts. Profits of at most the
+/// token's MINPROFITBASISPOINT are ignored until `min_profit_time` has passed
+/// since `_last_increased_time`.
+pub fn get_position_delta(
+    _deps: Deps,
+    _env: &Env,
+    _index_token: Addr,
+    _size: Uint128,
+    _average_price: Uint128,
+    _is_long: bool,
+    _last_increased_time: u64,
+) -> Result<PositionDeltaResponse, ContractError> {
+    validate(_average_price > Uint128::zero(), "ERR_AVERAGE_PRICE_ZERO")?;
+    let config = CONFIG.load(_deps.storage)?;
+
+    let price = get_token_price(_deps, _index_token.clone(), !_is_long)?;
+
+    let price_delta = if _average_price > price {
+        _average_price - price
+    } else {
+        price - _average_price
+    };
+
+    let delta: Uint128 = _size.multiply_ratio(price_delta, _average_price);
+
+    let has_profit = if _is_long {
+        price > _average_price
+    } else {
+        _average_price > price
+    };
+
+    let min_profit_ends =
+        Uint128::from(_last_increased_time).saturating_add(config.min_profit_time);
+    let min_bps = if Uint128::from(_env.block.time.seconds()) > min_profit_ends {
+        Uint128::zero()
+    } else {
+        MINPROFITBASISPOINT
+            .may_load(_deps.storage, _index_token)?
+            .unwrap_or_default()
+    };
+
+    let adjusted_delta =
+        if has_profit && delta.full_mul(BASIS_POINTS_DIVISOR) <= _size.full_mul(min_bps) {
+            Uint128::zero()
+        } else {
+            delta
+        };
+
+    Ok(PositionDeltaResponse {
+        has_profit,
+        delta,
+        adjusted_delta,
+        mark_price: price,
+    })
+}
+
+pub fn get_next_average_price(
+    _deps: Deps,
+    _env: &Env,
+    _index_token: Addr,
+    _size: Uint128,
+    _average_price: Uint128,
+    _is_long: bool,
+    _next_price: Uint128,
+    _size_delta: Uint128,
+    _last_increased_time: u64,
+) -> Result<Uint128, ContractError> {
+    let (has_profit, delta) = get_delta(
+        _deps,
+        _env,
+        _index_token,
+        _size,
+        _average_price,
+        _is_long,
+        _last_increased_time,
+    )?;
+
+    let next_size = _size.checked_add(_size_delta)?;
+    let divisor = if _is_long == has_profit {
+        next_size.checked_add(delta)?
+    } else {
+        next_size.checked_sub(delta)?
+    };
+
+    // a higher long or lower short average price leaves less profit to pay out
+    let rounding = if _is_long {
+        Rounding::Up
+    } else {
+        Rounding::Down
+    };
+    let next_average_price = mul_div(_next_price, next_size, divisor, rounding)?;
+
+    Ok(next_average_price)
+}
+
+/// Returns the liquidation state (0 healthy, 1 liquidatable, 2 over max
+/// leverage and only needs closing) and the margin fees owed. With `_raise`
+/// an unhealthy position is an error instead.
+pub fn validLiquidation(
+    _deps: Deps,
+    _env: &Env,
+    _account: Addr,
+    _collateralToken: Addr,
+    _indexToken: Addr,
+    _isLong: bool,
+    _raise: bool,
+) -> Result<(Uint128, Uint128), ContractError> {
+    let key = get_position_key(
+        _account.clone(),
+        _collateralToken.clone(),
+        _indexToken.clone(),
+        _isLong,
+    );
+    let position = get_position(_deps, key)?;
+
+    validate_liquidation(
+        _deps,
+        _env,
+        &position,
+        _account,
+        _collateralToken,
+        _indexToken,
+        _isLong,
+        _raise,
+    )
+}
+
+/// validLiquidation for a position that may not be stored yet.
+pub fn validate_liquidation(
+    _deps: Deps,
+    _env: &Env,
+    position: &Position,
+    _account: Addr,
+    _collateralToken: Addr,
+    _indexToken: Addr,
+    _isLong: bool,
+    _raise: bool,
+) -> Result<(Uint128, Uint128), ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+
+    let (hasProfit, delta) = get_delta(
+        _deps,
+        _env,
+        _indexToken.clone(),
+        position.size,
+        position.averagePrice,
+        _isLong,
+        position.lastIncreasedTime,
+    )?;
+
+    let marginFees = get_margin_fees(
+        _deps,
+        _env,
+        _account,
+        _collateralToken,
+        _indexToken.clone(),
+        _isLong,
+        position.size.u128(),
+        position.size.u128(),
+        position.entryFundingRate.u128(),
+    )?;
+
+    if !hasProfit && position.collateral < delta {
+        validate(!_raise, "ERR_LOSSES_EXCEED_COLLATERAL")?;
+        return Ok((Uint128::one(), marginFees));
+    }
+
+    let remainingCollateral = if hasProfit {
+        position.collateral
+    } else {
+        position.collateral - delta
+    };
+
+    if remainingCollateral < marginFees {
+        validate(!_raise, "ERR_FEES_EXCEED_COLLATERAL")?;
+        // cap the fees to the remaining collateral
+        return Ok((Uint128::one(), remainingCollateral));
+    }
+
+    if remainingCollateral < marginFees + config.liquidation_fee_usd {
+        validate(!_raise, "ERR_LIQUIDATION_FEES_EXCEED_COLLATERAL")?;
+        return Ok((Uint128::one(), marginFees));
+    }
+
+    let max_leverage = query_max_leverage(_deps, _indexToken)?;
+    if remainingCollateral.full_mul(max_leverage) < position.size.full_mul(BASIS_POINTS_DIVISOR) {
+        validate(!_raise, "ERR_MAX_LEVERAGE_EXCEEDED")?;
+        return Ok((Uint128::new(2), marginFees));
+    }
+
+    Ok((Uint128::zero(), marginFees))
+}
+
+/// Mark price at which validate_liquidation stops returning 0 for
+/// `position`: longs are liquidatable at or below it, shorts at or above it.
+/// Marks are the min price for longs and the max price for shorts. `None`
+/// when the collateral can't cover the margin fees, liquidation fee and
+/// max-leverage requirement at any price.
+pub fn get_liquidation_price(
+    _deps: Deps,
+    _env: &Env,
+    position: &Position,
+    _account: Addr,
+    _collateralToken: Addr,
+    _indexToken: Addr,
+    _isLong: bool,
+) -> Result<Option<Uint128>, ContractError> {
+    if position.size.is_zero() {
+        return Ok(None);
+    }
+    let config = CONFIG.load(_deps.storage)?;
+    let max_leverage = query_max_leverage(_deps, _indexToken.clone())?;
+
+    let margin_fees = get_margin_fees(
+        _deps,
+        _env,
+        _account,
+        _collateralToken,
+        _indexToken,
+        _isLong,
+        position.size.u128(),
+        position.size.u128(),
+        position.entryFundingRate.u128(),
+    )?;
+    // collateral that must remain after losses, from either the fee or the
+    // leverage check, whichever is stricter
+    let min_remaining = margin_fees
+        .checked_add(config.liquidation_fee_usd)?
+        .max(mul_div(
+            position.size,
+            BASIS_POINTS_DIVISOR,
+            max_leverage,
+            Rounding::Up,
+        )?);
+    if position.collateral < min_remaining {
+        return Ok(None);
+    }
+
+    // losses are floored, so the position flips once they exceed the
+    // allowed amount by a whole unit
+    let liquidating_loss = position.collateral - min_remaining + Uint128::one();
+    let price = if _isLong {
+        mul_div(
+            position.averagePrice,
+            position.size.saturating_sub(liquidating_loss),
+            position.size,
+            Rounding::Down,
+        )?
+    } else {
+        mul_div(
+            position.averagePrice,
+            position.size.checked_add(liquidating_loss)?,
+            position.size,
+            Rounding::Up,
+        )?
+    };
+
+    Ok(Some(price))
+}
+
+pub fn get_next_global_long_average_price(
+    deps: Deps,
+    _index_token: Addr,
+    _next_price: Uint128,
+    _size_delta: Uint128,
+) -> Result<Uint128, ContractError> {
+    let size = GLOBALLONGSIZE
+        .may_load(deps.storage, _index_token.clone())?
+        .unwrap_or_default();
+    let average_price = GLOBALLONGAVERAGEPRICE
+        .may_load(deps.storage, _index_token)?
+        .unwrap_or_default();
+    if size.is_zero() || average_price.is_zero() {
+        return Ok(_next_price);
+    }
+
+    let price_delta = if average_price > _next_price {
+        average_price - _next_price
+    } else {
+        _next_price - average_price
+    };
+    let delta = size.multiply_ratio(price_delta, average_price);
+    let has_profit = _next_price > average_price;
+
+    let next_size = size.checked_add(Uint256::from(_size_delta))?;
+    let divisor = if has_profit {
+        next_size.checked_add(delta)?
+    } else {
+        next_size.checked_sub(delta)?
+    };
+
+    let result = mul_div_256(
+        Uint256::from(_next_price),
+        next_size,
+        divisor,
+        Rounding::Down,
+    )?;
+    Ok(result.try_into()?)
+}
+
+pub fn get_next_global_short_average_price(
+    deps: Deps,
+    _index_token: Addr,
+    _next_price: Uint128,
+    _size_delta: Uint128,
+) -> Result<Uint128, ContractError> {
+    let size = GLOBALSHORTSIZE
+        .may_load(deps.storage, _index_token.clone())?
+        .unwrap_or_default();
+    let average_price = GLOBALSHORTAVERAGEPRICE
+        .may_load(deps.storage, _index_token)?
+        .unwrap_or_default();
+    if size.is_zero() || average_price.is_zero() {
+        return Ok(_next_price);
+    }
+
+    let price_delta = if average_price > _next_price {
+        average_price - _next_price
+    } else {
+        _next_price - average_price
+    };
+    let delta = size.multiply_ratio(price_delta, average_price);
+    let has_profit = average_price > _next_price;
+
+    let next_size = size.checked_add(Uint256::from(_size_delta))?;
+    let divisor = if has_profit {
+        next_size.checked_sub(delta)?
+    } else {
+        next_size.checked_add(delta)?
+    };
+
+    let result = mul_div_256(
+        Uint256::from(_next_price),
+        next_size,
+        divisor,
+        Rounding::Down,
+    )?;
+    Ok(result.try_into()?)
+}
+
+/// Unrealised PnL of all shorts on `_index_token`, from the traders' point of view.
+pub fn get_global_short_delta(
+    _deps: Deps,
+    _index_token: Addr,
+) -> Result<(bool, Uint256), ContractError> {
+    let next_price = get_token_price(_deps, _index_token.clone(), true)?;
+
+    get_global_short_delta_at(_deps, _index_token, next_price)
+}
+
+pub fn get_global_short_delta_at(
+    _deps: Deps,
+    _index_token: Addr,
+    next_price: Uint128,
+) -> Result<(bool, Uint256), ContractError> {
+    let size = GLOBALSHORTSIZE
+        .may_load(_deps.storage, _index_token.clone())?
+        .unwrap_or_default();
+    if size.is_zero() {
+        return Ok((false, Uint256::zero()));
+    }
+
+    let average_price = GLOBALSHORTAVERAGEPRICE
+        .may_load(_deps.storage, _index_token)?
+        .unwrap_or_default();
+    validate(average_price > Uint128::zero(), "ERR_AVERAGE_PRICE_ZERO")?;
+
+    let price_delta = if average_price > next_price {
+        average_price - next_price
+    } else {
+        next_price - average_price
+    };
+    let delta = size.multiply_ratio(price_delta, average_price);
+
+    Ok((average_price > next_price, delta))
+}
+
+/// Assets under management in USD (PRICE_PRECISION): stable pools at face value,
+/// non-stable pools net of reserves plus guaranteed USD, minus unrealised short profits.
+pub fn get_aum(_deps: Deps, _maximise: bool) -> Result<Uint256, ContractError> {
+    let config = CONFIG.load(_deps.storage)?;
+
+    let mut aum = Uint256::zero();
+    let mut short_profits = Uint256::zero();
+
+    for token in config.all_whitelisted_tokens {
+        if !WHITELISTEDTOKEN
+            .may_load(_deps.storage, token.clone())?
+            .unwrap_or(false)
+        {
+            continue;
+        }
+
+        let price = get_token_price(_deps, token.clone(), _maximise)?;
+        let pool_amount = POOLAMOUNT
+            .may_load(_deps.storage, token.clone())?
+            .unwrap_or_default();
+        let unit = token_unit(_deps, &token)?;
+
+        if STABLETOKEN.load(_deps.storage, token.clone())? {
+            aum += Uint256::from(pool_amount).multiply_ratio(price, unit);
+            continue;
+        }
+
+        let (has_profit, delta) = get_global_short_delta_at(_deps, token.clone(), price)?;
+        if has_profit {
+            short_profits += delta;
+        } else {
+            aum += delta;
+        }
+
+        aum += GUARANTEEUSD
+            .may_load(_deps.storage, token.clone())?
+            .unwrap_or_default();
+
+        let reserved_amount = RESERVEDAMOUNTS
+            .may_load(_deps.storage, token.clone())?
+            .unwrap_or_default();
+        aum +=
+            Uint256::from(pool_amount.saturating_sub(reserved_amount)).multiply_ratio(price, unit);
+    }
+
+    Ok(aum.saturating_sub(short_profits))
+}
+
+/// AUM converted from PRICE_PRECISION to USDG units.
+pub fn get_aum_in_usdg(_deps: Deps, _maximise: bool) -> Result<Uint128, ContractError> {
+    let aum = get_aum(_deps, _maximise)?;
+
+    Ok(aum
+        .multiply_ratio(
+            Uint128::new(10).pow(USDG_DECIMALS.u128() as u32),
+            PRICE_PRECISION,
+        )
+        .try_into()?)
+}
+
+/// AUM owned by LP shares, in USDG units. USDG held outside `vault` was minted
+/// against pool deposits too, so the AUM backing it is left out.
+pub fn get_lp_aum_in_usdg(
+    _deps: Deps,
+    vault: Addr,
+    _maximise: bool,
+) -> Result<Uint128, ContractError> {
+    let aum_in_usdg = get_aum_in_usdg(_deps, _maximise)?;
+    let usdg = CONFIG.load(_deps.storage)?.usdg;
+    let usdg_supply = total_supply_cw20_tokens(_deps, usdg.clone())?;
+    let usdg_held = cw20_balance_of(_deps, usdg, vault)?;
+
+    Ok(aum_in_usdg.saturating_sub(usdg_supply.saturating_sub(usdg_held)))
+}
+
+pub fn getRedemptionAmount(
+    _deps: Deps,
+    _token: Addr,
+    _usdgAmount: Uint128,
+) -> Result<Uint128, ContractError> {
+    let price: Uint128 = get_swap_price(_deps, _token.clone(), true)?;
+    let redemptionAmount = mul_div(_usdgAmount, PRICE_PRECISION, price, Rounding::Down)?;
+    let config = CONFIG.load(_deps.storage)?;
+
+    adjust_decimal(_deps, config.usdg, _token, redemptionAmount)
+}
+
+pub fn getRedemptionCollateral(_deps: Deps, _token: Addr) -> Result<Uint128, ContractError> {
+    let stableTokens = STABLETOKEN.load(_deps.storage, _token.clone())?;
+    let amount = POOLAMOUNT.load(_deps.storage, _token.clone())?;
+    if stableTokens {
+        return Ok(amount);
+    }
+    let grantedusd = GUARANTEEUSD.load(_deps.storage, _token.clone())?;
+
+    let collateral =
+        usd_to_token_min(_deps, _token.clone(), Uint128::try_from(grantedusd)?.u128())?;
+
+    let _collateral = Uint128::new(collateral);
+
+    let reservedAmounts = RESERVEDAMOUNTS.load(_deps.storage, _token.clone())?;
+    let res: Uint128 = _collateral
+        .checked_add(amount)?
+        .checked_sub(reservedAmounts)?;
+
+    Ok(res)
+}
+
+pub fn getRedemptionCollateralUsd(_deps: Deps, _token: Addr) -> Result<Uint128, ContractError> {
+    let RedemptionCollateral = getRedemptionCollateral(_deps, _token.clone())?;
+
+    let res = token_to_usd_min(_deps, _token, RedemptionCollateral.u128())?;
+    Ok(res)
+}
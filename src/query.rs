@@ -1,13 +1,36 @@
-use cosmwasm_std::{
-    Addr, Deps, DepsMut, Int128, StdError, StdResult, Uint128,
-};
+use cosmwasm_std::{Addr, Deps, Env, Int128, Order, StdError, StdResult, Uint128, Uint256};
+use cw_storage_plus::{Bound, Map};
 
 use crate::contract::BASIS_POINTS_DIVISOR;
+use crate::error::ContractError;
+use crate::msg::{
+    BalanceDriftResponse, CheckInvariantsResponse, FeeRecipientInfoResponse, FeeRecipientsResponse,
+    GlobalShortPnlResponse, Invariant, InvariantViolation, LiquidationPriceResponse,
+    LiquidatorInfo, LiquidatorsResponse, LiquidityInfoResponse, ManagersResponse,
+    OpenInterestResponse, PauseStatusResponse, PausedOperation, PendingFeesResponse,
+    PositionDeltaResponse, RoleMembersResponse, SimulateBuyUsdgResponse,
+    SimulateDecreasePositionResponse, SimulateIncreasePositionResponse, SimulateSellUsdgResponse,
+    SimulateSwapResponse, TokenAmount, TokenDrift, UsdgSupplyResponse,
+};
 use crate::state::{
-    Config, Position, CONFIG, ISMANAGER, POSITION, WHITELISTEDTOKEN,
+    Config, LiquidatorStats, PauseTarget, Position, Role, ADMIN, CONFIG, FEERECIPIENTS,
+    FEERECIPIENTSTATS, FEERESERVED, FEESDISTRIBUTED, FEETOKEN, GLOBALLONGAVERAGEPRICE,
+    GLOBALLONGSIZE, GLOBALSHORTAVERAGEPRICE, GLOBALSHORTSIZE, ISLIQUIDATOR, ISMANAGER, LASTADDEDAT,
+    LIQUIDATORSTATS, LIQUIDITYCONFIG, MAXGLOBALLONGSIZE, MAXGLOBALSHORTSIZE, PAUSED, POOLAMOUNT,
+    POSITION, RESERVEDAMOUNTS, ROLES, TOKENBALANCE, TOKENMAXLEVERAGE, UNCLEAREDUSDGDEBT,
+    USDGAMOUNT, WHITELISTEDTOKEN,
 };
 
-use crate::{helpers::validate};
+use crate::custody::load_custody;
+use crate::helpers::total_supply_cw20_tokens;
+use crate::pricing::{
+    get_aum, get_global_short_delta, get_liquidation_price, get_lp_aum_in_usdg, get_margin_fees,
+    get_position_delta, get_token_price,
+};
+use crate::simulate::{
+    quote_buy_usdg, quote_decrease_position, quote_increase_position, quote_sell_usdg, quote_swap,
+};
+use cw20::{BalanceResponse, Cw20QueryMsg, MinterResponse, TokenInfoResponse};
 
 pub fn query_config(_deps: Deps) -> StdResult<Config> {
     let res = CONFIG.may_load(_deps.storage)?;
@@ -23,22 +46,401 @@ pub fn query_config(_deps: Deps) -> StdResult<Config> {
 pub fn query_manager(_deps: Deps, address: Addr) -> StdResult<bool> {
     let res = ISMANAGER.may_load(_deps.storage, address)?;
 
-    match res {
-        Some(val) => Ok(val),
-        None => Err(StdError::NotFound {
-            kind: format!("Unable to load token state"),
-        }),
+    Ok(res.unwrap_or(false))
+}
+
+pub fn query_managers(
+    _deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ManagersResponse> {
+    let config = query_config(_deps)?;
+    let managers = query_role_members(_deps, Role::Manager, start_after, limit)?.members;
+
+    Ok(ManagersResponse {
+        in_manager_mode: config.in_manager_mode,
+        managers,
+    })
+}
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+pub fn has_role(_deps: Deps, role: Role, account: Addr) -> StdResult<bool> {
+    let res = match role {
+        Role::Gov => return ADMIN.is_admin(_deps, &account),
+        Role::Manager => ISMANAGER.may_load(_deps.storage, account)?,
+        Role::Liquidator => ISLIQUIDATOR.may_load(_deps.storage, account)?,
+        _ => ROLES.may_load(_deps.storage, (role.as_str().to_string(), account))?,
+    };
+
+    Ok(res.unwrap_or(false))
+}
+
+pub fn query_role_members(
+    _deps: Deps,
+    role: Role,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<RoleMembersResponse> {
+    if role == Role::Gov {
+        let members = ADMIN.get(_deps)?.into_iter().collect();
+        return Ok(RoleMembersResponse { role, members });
+    }
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|addr| Bound::exclusive(Addr::unchecked(addr)));
+
+    let members = match role {
+        Role::Manager => ISMANAGER.range(_deps.storage, start, None, Order::Ascending),
+        Role::Liquidator => ISLIQUIDATOR.range(_deps.storage, start, None, Order::Ascending),
+        _ => ROLES.prefix(role.as_str().to_string()).range(
+            _deps.storage,
+            start,
+            None,
+            Order::Ascending,
+        ),
+    }
+    .filter(|item| !matches!(item, Ok((_, false))))
+    .take(limit)
+    .map(|item| item.map(|(addr, _)| addr))
+    .collect::<StdResult<Vec<Addr>>>()?;
+
+    Ok(RoleMembersResponse { role, members })
+}
+
+pub fn query_pause_status(_deps: Deps) -> StdResult<PauseStatusResponse> {
+    let mut paused = vec![];
+    for target in PauseTarget::all() {
+        if let Some(pause_info) = PAUSED.may_load(_deps.storage, target.as_str().to_string())? {
+            paused.push(PausedOperation {
+                target,
+                reason: pause_info.reason,
+                paused_by: pause_info.paused_by,
+                paused_at: pause_info.paused_at,
+            });
+        }
+    }
+
+    Ok(PauseStatusResponse { paused })
+}
+
+pub fn query_liquidators(
+    _deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<LiquidatorsResponse> {
+    let config = query_config(_deps)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|addr| Bound::exclusive(Addr::unchecked(addr)));
+
+    let liquidators = ISLIQUIDATOR
+        .range(_deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (address, is_active) = item?;
+            let stats = query_liquidator_stats(_deps, address.clone())?;
+            Ok(LiquidatorInfo {
+                address,
+                is_active,
+                stats,
+            })
+        })
+        .collect::<StdResult<Vec<LiquidatorInfo>>>()?;
+
+    Ok(LiquidatorsResponse {
+        in_private_liquidation_mode: config.in_private_liquidation_mode,
+        liquidators,
+    })
+}
+
+pub fn query_liquidator_stats(_deps: Deps, address: Addr) -> StdResult<LiquidatorStats> {
+    let res = LIQUIDATORSTATS.may_load(_deps.storage, address)?;
+
+    Ok(res.unwrap_or_default())
+}
+
+pub fn query_global_short_pnl(_deps: Deps, token: Addr) -> StdResult<GlobalShortPnlResponse> {
+    let size = GLOBALSHORTSIZE
+        .may_load(_deps.storage, token.clone())?
+        .unwrap_or_default();
+    let average_price = GLOBALSHORTAVERAGEPRICE
+        .may_load(_deps.storage, token.clone())?
+        .unwrap_or_default();
+    let (has_profit, delta) = get_global_short_delta(_deps, token.clone())
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+
+    Ok(GlobalShortPnlResponse {
+        token,
+        size,
+        average_price,
+        has_profit,
+        delta,
+    })
+}
+
+pub fn query_aum(_deps: Deps, maximise: bool) -> StdResult<Uint256> {
+    get_aum(_deps, maximise).map_err(|err| StdError::generic_err(err.to_string()))
+}
+
+pub fn query_fee_recipients(_deps: Deps) -> StdResult<FeeRecipientsResponse> {
+    let recipients = FEERECIPIENTS.may_load(_deps.storage)?.unwrap_or_default();
+    let fee_token = FEETOKEN.may_load(_deps.storage)?;
+
+    Ok(FeeRecipientsResponse {
+        recipients,
+        fee_token,
+    })
+}
+
+pub fn query_fee_recipient_info(
+    _deps: Deps,
+    recipient: Addr,
+) -> StdResult<FeeRecipientInfoResponse> {
+    let weight = FEERECIPIENTS
+        .may_load(_deps.storage)?
+        .unwrap_or_default()
+        .into_iter()
+        .find(|r| r.address == recipient)
+        .map(|r| r.weight)
+        .unwrap_or_default();
+    let stats = FEERECIPIENTSTATS
+        .may_load(_deps.storage, recipient.clone())?
+        .unwrap_or_default();
+    let distributed = FEESDISTRIBUTED
+        .prefix(recipient.clone())
+        .range(_deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(token, amount)| TokenAmount { token, amount }))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(FeeRecipientInfoResponse {
+        recipient,
+        weight,
+        usd_received: stats.usd_received,
+        distributed,
+    })
+}
+
+pub fn query_pending_fees(_deps: Deps) -> StdResult<PendingFeesResponse> {
+    let config = query_config(_deps)?;
+
+    let fees = config
+        .all_whitelisted_tokens
+        .into_iter()
+        .map(|token| {
+            let amount = FEERESERVED
+                .may_load(_deps.storage, token.clone())?
+                .unwrap_or_default();
+            Ok(TokenAmount { token, amount })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(PendingFeesResponse { fees })
+}
+
+pub fn query_balance_drift(
+    _deps: Deps,
+    _env: Env,
+    tokens: Option<Vec<Addr>>,
+) -> StdResult<BalanceDriftResponse> {
+    let tokens = match tokens {
+        Some(tokens) => tokens,
+        None => query_config(_deps)?.all_whitelisted_tokens,
+    };
+
+    let drifts = tokens
+        .into_iter()
+        .map(|token| {
+            let cached_balance = TOKENBALANCE
+                .may_load(_deps.storage, token.clone())?
+                .unwrap_or_default();
+            let actual_balance = vault_balance(_deps, &_env, &token)?;
+            Ok(TokenDrift {
+                token,
+                cached_balance,
+                actual_balance,
+                surplus: actual_balance.saturating_sub(cached_balance),
+                deficit: cached_balance.saturating_sub(actual_balance),
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(BalanceDriftResponse { drifts })
+}
+
+pub fn query_check_invariants(
+    _deps: Deps,
+    _env: Env,
+    tokens: Option<Vec<Addr>>,
+) -> StdResult<CheckInvariantsResponse> {
+    let tokens = match tokens {
+        Some(tokens) => tokens,
+        None => query_config(_deps)?.all_whitelisted_tokens,
+    };
+
+    let custody = load_custody(_deps.storage)?;
+    let mut violations = vec![];
+    for token in tokens {
+        let amount = |map: Map<Addr, Uint128>| -> StdResult<Uint128> {
+            Ok(map
+                .may_load(_deps.storage, token.clone())?
+                .unwrap_or_default())
+        };
+        let pool = amount(POOLAMOUNT)?;
+        let reserved = amount(RESERVEDAMOUNTS)?;
+        let fees = amount(FEERESERVED)?;
+        let cached_balance = amount(TOKENBALANCE)?;
+        let balance = vault_balance(_deps, &_env, &token)?;
+        let custody_balance = custody.balance(_deps, &_env, &token)?;
+
+        // no balance covers a pool and fees that overflow when summed. The
+        // buffer is left out: it only bounds swaps out of the pool, so a pool
+        // still filling up to it is not a violation
+        let checks = [
+            (Invariant::PoolCoversReserved, pool, Some(reserved)),
+            (
+                Invariant::BalanceCoversPoolAndFees,
+                custody_balance,
+                pool.checked_add(fees).ok(),
+            ),
+            (
+                Invariant::BalanceCoversCached,
+                balance,
+                Some(cached_balance),
+            ),
+        ];
+        for (invariant, actual, required) in checks {
+            if required.is_none_or(|required| actual < required) {
+                violations.push(InvariantViolation {
+                    token: token.clone(),
+                    invariant,
+                    actual,
+                    required: required.unwrap_or(Uint128::MAX),
+                });
+            }
+        }
+    }
+
+    Ok(CheckInvariantsResponse { violations })
+}
+
+/// Live cw20 balance of the vault, as opposed to the cached TOKENBALANCE.
+fn vault_balance(_deps: Deps, _env: &Env, token: &Addr) -> StdResult<Uint128> {
+    let res: BalanceResponse = _deps.querier.query_wasm_smart(
+        token,
+        &Cw20QueryMsg::Balance {
+            address: _env.contract.address.to_string(),
+        },
+    )?;
+    Ok(res.balance)
+}
+
+pub fn query_usdg_supply(_deps: Deps, _env: Env) -> StdResult<UsdgSupplyResponse> {
+    let config = query_config(_deps)?;
+
+    let token_info: TokenInfoResponse = _deps
+        .querier
+        .query_wasm_smart(config.usdg.clone(), &Cw20QueryMsg::TokenInfo {})?;
+    let minter: Option<MinterResponse> = _deps
+        .querier
+        .query_wasm_smart(config.usdg.clone(), &Cw20QueryMsg::Minter {})?;
+    let held_by_vault: BalanceResponse = _deps.querier.query_wasm_smart(
+        config.usdg,
+        &Cw20QueryMsg::Balance {
+            address: _env.contract.address.to_string(),
+        },
+    )?;
+
+    let total_usdg_amount = USDGAMOUNT
+        .range(_deps.storage, None, None, Order::Ascending)
+        .try_fold(Uint128::zero(), |total, item| {
+            item.map(|(_, amount)| total + amount)
+        })?;
+
+    let uncleared_usdg_debt = UNCLEAREDUSDGDEBT
+        .may_load(_deps.storage)?
+        .unwrap_or_default();
+
+    let minter = minter.map(|m| Addr::unchecked(m.minter));
+    Ok(UsdgSupplyResponse {
+        total_supply: token_info.total_supply,
+        total_usdg_amount,
+        held_by_vault: held_by_vault.balance,
+        vault_is_minter: minter.as_ref() == Some(&_env.contract.address),
+        minter,
+        uncleared_usdg_debt,
+        is_reconciled: token_info
+            .total_supply
+            .checked_add(uncleared_usdg_debt)
+            .ok()
+            == Some(total_usdg_amount),
+    })
+}
+
+pub fn query_liquidity_info(
+    _deps: Deps,
+    _env: Env,
+    account: Option<Addr>,
+) -> StdResult<LiquidityInfoResponse> {
+    let liquidity = LIQUIDITYCONFIG.may_load(_deps.storage)?.ok_or_else(|| {
+        StdError::generic_err(ContractError::LiquidityNotConfigured {}.to_string())
+    })?;
+
+    let lp_supply = total_supply_cw20_tokens(_deps, liquidity.lp_token.clone())
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    let aum_in_usdg_max = get_lp_aum_in_usdg(_deps, _env.contract.address.clone(), true)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    let aum_in_usdg_min = get_lp_aum_in_usdg(_deps, _env.contract.address.clone(), false)
+        .map_err(|err| StdError::generic_err(err.to_string()))?;
+    let last_added_at = match account {
+        Some(account) => LASTADDEDAT.may_load(_deps.storage, account)?,
+        None => None,
+    };
+
+    Ok(LiquidityInfoResponse {
+        lp_token: liquidity.lp_token,
+        cooldown_duration: liquidity.cooldown_duration,
+        lp_supply,
+        aum_in_usdg_max,
+        aum_in_usdg_min,
+        last_added_at,
+    })
+}
+
+pub fn query_open_interest(_deps: Deps, token: Addr) -> StdResult<OpenInterestResponse> {
+    let load = |map: Map<Addr, Uint256>| -> StdResult<Uint256> {
+        Ok(map
+            .may_load(_deps.storage, token.clone())?
+            .unwrap_or_default())
+    };
+    let price = |map: Map<Addr, Uint128>| -> StdResult<Uint128> {
+        Ok(map
+            .may_load(_deps.storage, token.clone())?
+            .unwrap_or_default())
+    };
+
+    Ok(OpenInterestResponse {
+        token: token.clone(),
+        long_size: load(GLOBALLONGSIZE)?,
+        long_average_price: price(GLOBALLONGAVERAGEPRICE)?,
+        max_long_size: load(MAXGLOBALLONGSIZE)?,
+        short_size: load(GLOBALSHORTSIZE)?,
+        short_average_price: price(GLOBALSHORTAVERAGEPRICE)?,
+        max_short_size: load(MAXGLOBALSHORTSIZE)?,
+    })
+}
+
+pub fn query_max_leverage(_deps: Deps, token: Addr) -> StdResult<Uint128> {
+    match TOKENMAXLEVERAGE.may_load(_deps.storage, token)? {
+        Some(max_leverage) => Ok(max_leverage),
+        None => Ok(query_config(_deps)?.max_leverage),
     }
 }
 
 pub fn check_whitelisted_token(_deps: Deps, address: Addr) -> StdResult<bool> {
     let res = WHITELISTEDTOKEN.may_load(_deps.storage, address)?;
-    match res {
-        Some(val) => Ok(val),
-        None => Err(StdError::NotFound {
-            kind: format!("Unable to load token state"),
-        }),
-    }
+
+    Ok(res.unwrap_or(false))
 }
 
 pub fn all_whiteListed_token(_deps: Deps) -> StdResult<Vec<Addr>> {
@@ -95,19 +497,200 @@ pub fn get_position_key(
     key
 }
 
-pub fn get_position_leverage(
-    _deps: DepsMut,
+pub fn query_simulate_swap(
+    _deps: Deps,
+    token_in: Addr,
+    token_out: Addr,
+    amount_in: Uint128,
+) -> StdResult<SimulateSwapResponse> {
+    quote_swap(_deps, token_in, token_out, amount_in)
+        .map_err(|err| StdError::generic_err(err.to_string()))
+}
+
+pub fn query_simulate_buy_usdg(
+    _deps: Deps,
+    token: Addr,
+    token_amount: Uint128,
+) -> StdResult<SimulateBuyUsdgResponse> {
+    quote_buy_usdg(_deps, token, token_amount).map_err(|err| StdError::generic_err(err.to_string()))
+}
+
+pub fn query_simulate_sell_usdg(
+    _deps: Deps,
+    token: Addr,
+    usdg_amount: Uint128,
+) -> StdResult<SimulateSellUsdgResponse> {
+    quote_sell_usdg(_deps, token, usdg_amount).map_err(|err| StdError::generic_err(err.to_string()))
+}
+
+pub fn query_simulate_increase_position(
+    _deps: Deps,
+    _env: Env,
+    account: Addr,
+    collateral_token: Addr,
+    index_token: Addr,
+    collateral_amount: Uint128,
+    size_delta: Uint128,
+    is_long: bool,
+) -> StdResult<SimulateIncreasePositionResponse> {
+    let simulate = || -> Result<SimulateIncreasePositionResponse, ContractError> {
+        let quote = quote_increase_position(
+            _deps,
+            &_env,
+            account.clone(),
+            collateral_token.clone(),
+            index_token.clone(),
+            collateral_amount,
+            size_delta,
+            is_long,
+        )?;
+        let position = quote.position;
+
+        Ok(SimulateIncreasePositionResponse {
+            price: quote.price,
+            size: position.size,
+            collateral: position.collateral,
+            average_price: position.averagePrice,
+            fee_usd: quote.fee_usd,
+            leverage: position
+                .size
+                .multiply_ratio(BASIS_POINTS_DIVISOR, position.collateral),
+            liquidation_price: get_liquidation_price(
+                _deps,
+                &_env,
+                &position,
+                account,
+                collateral_token,
+                index_token,
+                is_long,
+            )?,
+        })
+    };
+
+    simulate().map_err(|err| StdError::generic_err(err.to_string()))
+}
+
+pub fn query_simulate_decrease_position(
+    _deps: Deps,
+    _env: Env,
     account: Addr,
     collateral_token: Addr,
     index_token: Addr,
+    collateral_delta: Uint128,
+    size_delta: Uint128,
     is_long: bool,
-) -> StdResult<Uint128> {
-    let key = get_position_key(account, collateral_token, index_token, is_long);
+) -> StdResult<SimulateDecreasePositionResponse> {
+    let simulate = || -> Result<SimulateDecreasePositionResponse, ContractError> {
+        let quote = quote_decrease_position(
+            _deps,
+            &_env,
+            account.clone(),
+            collateral_token.clone(),
+            index_token.clone(),
+            collateral_delta,
+            size_delta,
+            is_long,
+        )?;
+
+        let mut response = SimulateDecreasePositionResponse {
+            price: quote.price,
+            has_profit: quote.reduce.has_profit,
+            realised_delta: quote.reduce.adjusted_delta,
+            fee_usd: quote.reduce.fee_usd,
+            usd_out: quote.reduce.usd_out_after_fee,
+            amount_out: quote.amount_out,
+            size: Uint128::zero(),
+            collateral: Uint128::zero(),
+            leverage: Uint128::zero(),
+            liquidation_price: None,
+        };
+        if let Some(position) = quote.position {
+            response.size = position.size;
+            response.collateral = position.collateral;
+            response.leverage = position
+                .size
+                .multiply_ratio(BASIS_POINTS_DIVISOR, position.collateral);
+            response.liquidation_price = get_liquidation_price(
+                _deps,
+                &_env,
+                &position,
+                account,
+                collateral_token,
+                index_token,
+                is_long,
+            )?;
+        }
+
+        Ok(response)
+    };
 
+    simulate().map_err(|err| StdError::generic_err(err.to_string()))
+}
+
+pub fn query_liquidation_price(
+    _deps: Deps,
+    _env: Env,
+    account: Addr,
+    collateral_token: Addr,
+    index_token: Addr,
+    is_long: bool,
+) -> StdResult<LiquidationPriceResponse> {
+    let key = get_position_key(
+        account.clone(),
+        collateral_token.clone(),
+        index_token.clone(),
+        is_long,
+    );
     let position = POSITION.load(_deps.storage, key)?;
-    validate(position.collateral > Uint128::zero(), "err");
 
-    let res: Uint128 = position.size * BASIS_POINTS_DIVISOR / position.collateral;
+    let query = || -> Result<LiquidationPriceResponse, ContractError> {
+        Ok(LiquidationPriceResponse {
+            mark_price: get_token_price(_deps, index_token.clone(), !is_long)?,
+            margin_fees: get_margin_fees(
+                _deps,
+                &_env,
+                account.clone(),
+                collateral_token.clone(),
+                index_token.clone(),
+                is_long,
+                position.size.u128(),
+                position.size.u128(),
+                position.entryFundingRate.u128(),
+            )?,
+            liquidation_price: get_liquidation_price(
+                _deps,
+                &_env,
+                &position,
+                account,
+                collateral_token,
+                index_token,
+                is_long,
+            )?,
+        })
+    };
+
+    query().map_err(|err| StdError::generic_err(err.to_string()))
+}
+
+pub fn query_position_delta(
+    _deps: Deps,
+    _env: Env,
+    account: Addr,
+    collateral_token: Addr,
+    index_token: Addr,
+    is_long: bool,
+) -> StdResult<PositionDeltaResponse> {
+    let key = get_position_key(account, collateral_token, index_token.clone(), is_long);
+    let position = POSITION.load(_deps.storage, key)?;
 
-    Ok(res)
+    get_position_delta(
+        _deps,
+        &_env,
+        index_token,
+        position.size,
+        position.averagePrice,
+        is_long,
+        position.lastIncreasedTime,
+    )
+    .map_err(|err| StdError::generic_err(err.to_string()))
 }
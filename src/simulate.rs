@@ -0,0 +1,456 @@
+//! Read-only quotes for swaps, USDG mints and burns and position changes.
+//!
+//! The execute handlers take their amounts from these quotes before applying
+//! them, so a Simulate* query and the transaction it previews price and charge
+//! the same way within a block. Quotes check pool capacity and position
+//! health; the caps on USDG debt and open interest are only enforced when the
+//! trade executes.
+
+use cosmwasm_std::{Addr, Deps, Env, Int128, Uint128};
+
+use crate::{
+    contract::{_validateLeverage, validate_swap, PRICE_PRECISION},
+    helpers::{_validatePoolDecrease, _validateReserveCapacityWith, _validateTokens, validate},
+    math::{mul_div, Rounding},
+    msg::{SimulateBuyUsdgResponse, SimulateSellUsdgResponse, SimulateSwapResponse},
+    pricing::{
+        adjust_decimal, apply_fee, getBuyUsdgFeeBasisPoints, getFeeBasisPointsFrom,
+        getRedemptionAmount, getSwapFeeBasisPoints, get_cumulative_funding_rate, get_delta,
+        get_margin_fees, get_next_average_price, get_swap_price, get_token_price, token_to_usd_min,
+        usdToTokenMax, usd_to_token_min, validate_liquidation,
+    },
+    query::{get_position, get_position_key},
+    state::{Position, CONFIG, POOLAMOUNT, RESERVEDAMOUNTS, USDGAMOUNT, WHITELISTEDTOKEN},
+    ContractError,
+};
+
+pub fn quote_buy_usdg(
+    deps: Deps,
+    token: Addr,
+    token_amount: Uint128,
+) -> Result<SimulateBuyUsdgResponse, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    validate(WHITELISTEDTOKEN.load(deps.storage, token.clone())?, "err")?;
+    validate(token_amount > Uint128::zero(), "err")?;
+
+    let price = get_swap_price(deps, token.clone(), false)?;
+    let usdg_amount = adjust_decimal(
+        deps,
+        token.clone(),
+        config.usdg.clone(),
+        mul_div(token_amount, price, PRICE_PRECISION, Rounding::Down)?,
+    )?;
+    validate(usdg_amount > Uint128::zero(), "err")?;
+
+    let fee_basis_points = getBuyUsdgFeeBasisPoints(deps, token.clone(), usdg_amount)?;
+    let (amount_after_fees, fee_amount) = apply_fee(token_amount, fee_basis_points)?;
+    let mint_amount = adjust_decimal(
+        deps,
+        token,
+        config.usdg,
+        mul_div(amount_after_fees, price, PRICE_PRECISION, Rounding::Down)?,
+    )?;
+
+    Ok(SimulateBuyUsdgResponse {
+        price,
+        usdg_amount,
+        fee_basis_points,
+        fee_amount,
+        mint_amount,
+    })
+}
+
+pub fn quote_sell_usdg(
+    deps: Deps,
+    token: Addr,
+    usdg_amount: Uint128,
+) -> Result<SimulateSellUsdgResponse, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    validate(WHITELISTEDTOKEN.load(deps.storage, token.clone())?, "err")?;
+    validate(usdg_amount > Uint128::zero(), "err")?;
+
+    let price = get_swap_price(deps, token.clone(), true)?;
+    let redemption_amount = getRedemptionAmount(deps, token.clone(), usdg_amount)?;
+    validate(redemption_amount > Uint128::zero(), "errr")?;
+    _validatePoolDecrease(deps, token.clone(), redemption_amount)?;
+
+    // the fee is priced against the USDG debt left once this sale is burnt
+    let usdg_debt = USDGAMOUNT
+        .load(deps.storage, token.clone())?
+        .saturating_sub(usdg_amount);
+    let fee_basis_points = getFeeBasisPointsFrom(
+        deps,
+        token,
+        usdg_debt,
+        usdg_amount,
+        config.mint_burn_fee_basis_points,
+        config.tax_basis_points,
+        false,
+    )?;
+    let (amount_out, fee_amount) = apply_fee(redemption_amount, fee_basis_points)?;
+    validate(amount_out > Uint128::zero(), "err")?;
+
+    Ok(SimulateSellUsdgResponse {
+        price,
+        redemption_amount,
+        fee_basis_points,
+        fee_amount,
+        amount_out,
+    })
+}
+
+pub fn quote_swap(
+    deps: Deps,
+    token_in: Addr,
+    token_out: Addr,
+    amount_in: Uint128,
+) -> Result<SimulateSwapResponse, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    validate_swap(deps, token_in.clone(), token_out.clone())?;
+    validate(amount_in > Uint128::zero(), "err")?;
+
+    let price_in = get_swap_price(deps, token_in.clone(), false)?;
+    let price_out = get_swap_price(deps, token_out.clone(), true)?;
+
+    let amount_out = adjust_decimal(
+        deps,
+        token_in.clone(),
+        token_out.clone(),
+        mul_div(amount_in, price_in, price_out, Rounding::Down)?,
+    )?;
+    let usdg_amount = adjust_decimal(
+        deps,
+        token_in.clone(),
+        config.usdg,
+        mul_div(amount_in, price_in, PRICE_PRECISION, Rounding::Down)?,
+    )?;
+
+    let fee_basis_points = getSwapFeeBasisPoints(deps, token_in, token_out.clone(), usdg_amount)?;
+    let (amount_out_after_fees, fee_amount) = apply_fee(amount_out, fee_basis_points)?;
+    _validatePoolDecrease(deps, token_out, amount_out)?;
+
+    Ok(SimulateSwapResponse {
+        price_in,
+        price_out,
+        usdg_amount,
+        fee_basis_points,
+        fee_amount,
+        amount_out: amount_out_after_fees,
+    })
+}
+
+pub struct IncreasePositionQuote {
+    /// Mark price the size is added at.
+    pub price: Uint128,
+    /// The position as it will be stored.
+    pub position: Position,
+    pub fee_usd: Uint128,
+    pub collateral_delta_usd: Uint128,
+    /// Collateral tokens to reserve for the added size.
+    pub reserve_delta: Uint128,
+}
+
+/// Quotes adding `size_delta` to a position while depositing
+/// `collateral_delta` collateral tokens.
+pub fn quote_increase_position(
+    deps: Deps,
+    env: &Env,
+    account: Addr,
+    collateral_token: Addr,
+    index_token: Addr,
+    collateral_delta: Uint128,
+    size_delta: Uint128,
+    is_long: bool,
+) -> Result<IncreasePositionQuote, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    validate(config.is_leverage_enabled, "err")?;
+    _validateTokens(deps, collateral_token.clone(), index_token.clone(), is_long)?;
+
+    let price = get_token_price(deps, index_token.clone(), is_long)?;
+    let key = get_position_key(
+        account.clone(),
+        collateral_token.clone(),
+        index_token.clone(),
+        is_long,
+    );
+    let mut position = get_position(deps, key)?;
+
+    if position.size == Uint128::zero() {
+        position.averagePrice = price;
+    }
+    if position.size > Uint128::zero() && size_delta > Uint128::zero() {
+        position.averagePrice = get_next_average_price(
+            deps,
+            env,
+            index_token.clone(),
+            position.size,
+            position.averagePrice,
+            is_long,
+            price,
+            size_delta,
+            position.lastIncreasedTime,
+        )?;
+    }
+
+    let fee_usd = get_margin_fees(
+        deps,
+        env,
+        account.clone(),
+        collateral_token.clone(),
+        index_token.clone(),
+        is_long,
+        size_delta.u128(),
+        position.size.u128(),
+        position.entryFundingRate.u128(),
+    )?;
+
+    let collateral_delta_usd =
+        token_to_usd_min(deps, collateral_token.clone(), collateral_delta.u128())?;
+    position.collateral = position.collateral.checked_add(collateral_delta_usd)?;
+    validate(
+        position.collateral >= fee_usd,
+        "ERR_INSUFFICIENT_COLLATERAL_FOR_FEES",
+    )?;
+    position.collateral = position.collateral - fee_usd;
+
+    position.entryFundingRate = get_cumulative_funding_rate(deps, env, collateral_token.clone())?;
+    position.size = position.size.checked_add(size_delta)?;
+    position.lastIncreasedTime = env.block.time.seconds();
+    validate(position.size > Uint128::zero(), "err")?;
+
+    // checked first so an over-levered position reports its leverage
+    _validateLeverage(
+        deps,
+        index_token.clone(),
+        position.size,
+        position.collateral,
+    )?;
+    validate_liquidation(
+        deps,
+        env,
+        &position,
+        account,
+        collateral_token.clone(),
+        index_token,
+        is_long,
+        true,
+    )?;
+
+    let reserve_delta = usdToTokenMax(deps, collateral_token.clone(), size_delta)?;
+    let reserved = RESERVEDAMOUNTS
+        .may_load(deps.storage, collateral_token.clone())?
+        .unwrap_or_default()
+        .checked_add(reserve_delta)?;
+    let pool = POOLAMOUNT
+        .may_load(deps.storage, collateral_token.clone())?
+        .unwrap_or_default();
+    _validateReserveCapacityWith(deps, collateral_token, reserved, pool)?;
+    position.reserveAmount = position.reserveAmount.checked_add(reserve_delta)?;
+
+    Ok(IncreasePositionQuote {
+        price,
+        position,
+        fee_usd,
+        collateral_delta_usd,
+        reserve_delta,
+    })
+}
+
+pub struct ReduceCollateralQuote {
+    /// The position after realising PnL and fees, size not yet reduced.
+    pub position: Position,
+    pub fee_usd: Uint128,
+    pub has_profit: bool,
+    /// PnL realised on the closed size.
+    pub adjusted_delta: Uint128,
+    pub usd_out: Uint128,
+    pub usd_out_after_fee: Uint128,
+    /// Set when the payout didn't cover the fees and they came out of the
+    /// collateral left in the position instead.
+    pub fee_from_collateral: bool,
+}
+
+/// Quotes realising PnL and fees on `size_delta` of `position` and taking
+/// `collateral_delta` USD out of it.
+pub fn quote_reduce_collateral(
+    deps: Deps,
+    env: &Env,
+    mut position: Position,
+    account: Addr,
+    collateral_token: Addr,
+    index_token: Addr,
+    collateral_delta: Uint128,
+    size_delta: Uint128,
+    is_long: bool,
+) -> Result<ReduceCollateralQuote, ContractError> {
+    let fee_usd = get_margin_fees(
+        deps,
+        env,
+        account,
+        collateral_token,
+        index_token.clone(),
+        is_long,
+        size_delta.u128(),
+        position.size.u128(),
+        position.entryFundingRate.u128(),
+    )?;
+
+    let (has_profit, delta) = get_delta(
+        deps,
+        env,
+        index_token,
+        position.size,
+        position.averagePrice,
+        is_long,
+        position.lastIncreasedTime,
+    )?;
+    let adjusted_delta = mul_div(delta, size_delta, position.size, Rounding::Down)?;
+
+    let mut usd_out = Uint128::zero();
+    if has_profit && adjusted_delta > Uint128::zero() {
+        usd_out = adjusted_delta;
+        position.realisedPnL = position
+            .realisedPnL
+            .checked_add(Int128::new(adjusted_delta.u128() as i128))?;
+    }
+
+    if !has_profit && adjusted_delta > Uint128::zero() {
+        validate(
+            position.collateral >= adjusted_delta,
+            "ERR_LOSSES_EXCEED_COLLATERAL",
+        )?;
+        position.collateral = position.collateral - adjusted_delta;
+        position.realisedPnL = position
+            .realisedPnL
+            .checked_sub(Int128::new(adjusted_delta.u128() as i128))?;
+    }
+
+    if collateral_delta > Uint128::zero() {
+        validate(
+            position.collateral >= collateral_delta,
+            "ERR_INSUFFICIENT_COLLATERAL",
+        )?;
+        usd_out = usd_out + collateral_delta;
+        position.collateral = position.collateral - collateral_delta;
+    }
+
+    if position.size == size_delta {
+        usd_out = usd_out + position.collateral;
+        position.collateral = Uint128::zero();
+    }
+
+    // fees come out of the payout when it covers them, otherwise out of the
+    // collateral left in the position
+    let mut usd_out_after_fee = usd_out;
+    let fee_from_collateral = usd_out <= fee_usd;
+    if fee_from_collateral {
+        validate(position.collateral >= fee_usd, "ERR_FEES_EXCEED_COLLATERAL")?;
+        position.collateral = position.collateral - fee_usd;
+    } else {
+        usd_out_after_fee = usd_out - fee_usd;
+    }
+
+    Ok(ReduceCollateralQuote {
+        position,
+        fee_usd,
+        has_profit,
+        adjusted_delta,
+        usd_out,
+        usd_out_after_fee,
+        fee_from_collateral,
+    })
+}
+
+pub struct DecreasePositionQuote {
+    /// Mark price the size is closed at.
+    pub price: Uint128,
+    pub reserve_delta: Uint128,
+    pub reduce: ReduceCollateralQuote,
+    /// The position as it will be stored, `None` once it is closed.
+    pub position: Option<Position>,
+    /// `usd_out_after_fee` in collateral tokens.
+    pub amount_out: Uint128,
+}
+
+pub fn quote_decrease_position(
+    deps: Deps,
+    env: &Env,
+    account: Addr,
+    collateral_token: Addr,
+    index_token: Addr,
+    collateral_delta: Uint128,
+    size_delta: Uint128,
+    is_long: bool,
+) -> Result<DecreasePositionQuote, ContractError> {
+    let key = get_position_key(
+        account.clone(),
+        collateral_token.clone(),
+        index_token.clone(),
+        is_long,
+    );
+    let mut position = get_position(deps, key)?;
+    validate(position.size > Uint128::zero(), "err")?;
+    validate(position.size >= size_delta, "err")?;
+    validate(position.collateral >= collateral_delta, "err")?;
+
+    let reserve_delta = mul_div(
+        position.reserveAmount,
+        size_delta,
+        position.size,
+        Rounding::Down,
+    )?;
+    position.reserveAmount = position.reserveAmount - reserve_delta;
+
+    let reduce = quote_reduce_collateral(
+        deps,
+        env,
+        position,
+        account.clone(),
+        collateral_token.clone(),
+        index_token.clone(),
+        collateral_delta,
+        size_delta,
+        is_long,
+    )?;
+
+    let mut position = reduce.position;
+    let next_position = if position.size != size_delta {
+        position.entryFundingRate =
+            get_cumulative_funding_rate(deps, env, collateral_token.clone())?;
+        position.size = position.size - size_delta;
+        _validateLeverage(
+            deps,
+            index_token.clone(),
+            position.size,
+            position.collateral,
+        )?;
+        validate_liquidation(
+            deps,
+            env,
+            &position,
+            account,
+            collateral_token.clone(),
+            index_token.clone(),
+            is_long,
+            true,
+        )?;
+        Some(position)
+    } else {
+        None
+    };
+
+    let price = get_token_price(deps, index_token, !is_long)?;
+    let amount_out = Uint128::new(usd_to_token_min(
+        deps,
+        collateral_token,
+        reduce.usd_out_after_fee.u128(),
+    )?);
+
+    Ok(DecreasePositionQuote {
+        price,
+        reserve_delta,
+        reduce,
+        position: next_position,
+        amount_out,
+    })
+}
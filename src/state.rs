@@ -1,4 +1,4 @@
-use cosmwasm_std::{Addr, Int128, Uint128};
+use cosmwasm_std::{Addr, Int128, Uint128, Uint256};
 use cw_controllers::Admin;
 use cw_storage_plus::{Item, Map};
 use schemars::JsonSchema;
@@ -17,9 +17,21 @@ pub struct Position {
 
 pub const ADMIN: Admin = Admin::new("admin");
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CustodyMode {
+    /// The vault holds its cw20 tokens itself.
+    #[default]
+    SelfCustody,
+    /// `State.bank_addr` holds the funds and pays out for the vault.
+    Bank,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
     pub bank_addr: Addr,
+    #[serde(default)]
+    pub custody: CustodyMode,
 }
 
 pub const STATE: Item<State> = Item::new("state");
@@ -60,7 +72,85 @@ pub struct Config {
 
 pub const CONFIG: Item<Config> = Item::new("config");
 
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Gov,
+    RiskAdmin,
+    FeeKeeper,
+    Pauser,
+    Manager,
+    Liquidator,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Gov => "gov",
+            Role::RiskAdmin => "risk_admin",
+            Role::FeeKeeper => "fee_keeper",
+            Role::Pauser => "pauser",
+            Role::Manager => "manager",
+            Role::Liquidator => "liquidator",
+        }
+    }
+}
+
+// Gov is held by ADMIN and the manager/liquidator roles live in ISMANAGER and
+// ISLIQUIDATOR; every other role is keyed here by (role, member).
+pub const ROLES: Map<(String, Addr), bool> = Map::new("roles");
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseTarget {
+    Swap,
+    MintBurn,
+    IncreasePosition,
+    DecreasePosition,
+    Liquidation,
+}
+
+impl PauseTarget {
+    pub fn all() -> Vec<PauseTarget> {
+        vec![
+            PauseTarget::Swap,
+            PauseTarget::MintBurn,
+            PauseTarget::IncreasePosition,
+            PauseTarget::DecreasePosition,
+            PauseTarget::Liquidation,
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PauseTarget::Swap => "swap",
+            PauseTarget::MintBurn => "mint_burn",
+            PauseTarget::IncreasePosition => "increase_position",
+            PauseTarget::DecreasePosition => "decrease_position",
+            PauseTarget::Liquidation => "liquidation",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PauseInfo {
+    pub reason: String,
+    pub paused_by: Addr,
+    pub paused_at: u64,
+}
+
+// An operation is paused while it has an entry here.
+pub const PAUSED: Map<String, PauseInfo> = Map::new("paused");
+
 pub const ISLIQUIDATOR: Map<Addr, bool> = Map::new("is-liquidator");
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct LiquidatorStats {
+    pub liquidation_count: u64,
+    pub fees_earned_usd: Uint128,
+}
+
+pub const LIQUIDATORSTATS: Map<Addr, LiquidatorStats> = Map::new("liquidator-stats");
 pub const ISMANAGER: Map<Addr, bool> = Map::new("is-manager");
 pub const WHITELISTEDTOKEN: Map<Addr, bool> = Map::new("white-listed-token");
 pub const TOKENDECIMAL: Map<Addr, Uint128> = Map::new("token-decimal");
@@ -69,19 +159,93 @@ pub const STABLETOKEN: Map<Addr, bool> = Map::new("stable-token");
 pub const SHORTABLETOKEN: Map<Addr, bool> = Map::new("shortable-token");
 
 pub const TOKENBALANCE: Map<Addr, Uint128> = Map::new("token-balance");
+// In bank custody, what the vault has paid into the bank and not yet had paid
+// out. The bank's own balance also holds other clients' funds.
+pub const BANKDEPOSITS: Map<Addr, Uint128> = Map::new("bank-deposits");
 pub const TOKENWEIGHT: Map<Addr, Uint128> = Map::new("tokenWeights");
 pub const USDGAMOUNT: Map<Addr, Uint128> = Map::new("usdg-amount");
+// USDG debt decreases beyond the debt a token had, which leave the tokens' debt
+// above the USDG supply by this much.
+pub const UNCLEAREDUSDGDEBT: Item<Uint128> = Item::new("uncleared-usdg-debt");
 pub const MAXUSDGAMOUNT: Map<Addr, Uint128> = Map::new("max-USDG-amount");
 pub const POOLAMOUNT: Map<Addr, Uint128> = Map::new("pool-amount");
 pub const RESERVEDAMOUNTS: Map<Addr, Uint128> = Map::new("reserve - amount");
+// Per index token override of Config.max_leverage.
+pub const TOKENMAXLEVERAGE: Map<Addr, Uint128> = Map::new("token-max-leverage");
 pub const BUFFERAMOUNT: Map<Addr, Uint128> = Map::new("buffer-amount");
-pub const GUARANTEEUSD: Map<Addr, Uint128> = Map::new("guarantee-usdt");
+// USD totals summed over every position are kept in Uint256; per-position
+// values stay Uint128. Both serialise as decimal strings, so values written
+// as Uint128 load unchanged.
+pub const GUARANTEEUSD: Map<Addr, Uint256> = Map::new("guarantee-usdt");
 pub const CUMULATIVEFUNDINGRATE: Map<Addr, Uint128> = Map::new("cumulative-funding-rate");
 pub const LASTFUNDINTIME: Map<Addr, u128> = Map::new("lastFundingTimes");
 
 pub const POSITION: Map<Vec<u8>, Position> = Map::new("position");
 
 pub const FEERESERVED: Map<Addr, Uint128> = Map::new("fee-reserved");
-pub const GLOBALSHORTSIZE: Map<Addr, Uint128> = Map::new("global-short-size");
+pub const GLOBALSHORTSIZE: Map<Addr, Uint256> = Map::new("global-short-size");
 pub const GLOBALSHORTAVERAGEPRICE: Map<Addr, Uint128> = Map::new("global-short-average-price");
-pub const MAXGLOBALSHORTSIZE: Map<Addr, Uint128> = Map::new("max-global-short-size");
+pub const MAXGLOBALSHORTSIZE: Map<Addr, Uint256> = Map::new("max-global-short-size");
+pub const GLOBALLONGSIZE: Map<Addr, Uint256> = Map::new("global-long-size");
+pub const GLOBALLONGAVERAGEPRICE: Map<Addr, Uint128> = Map::new("global-long-average-price");
+pub const MAXGLOBALLONGSIZE: Map<Addr, Uint256> = Map::new("max-global-long-size");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LiquidityConfig {
+    pub lp_token: Addr,
+    pub cooldown_duration: u64,
+}
+
+// Unset until gov points the vault at an LP token it can mint.
+pub const LIQUIDITYCONFIG: Item<LiquidityConfig> = Item::new("liquidity-config");
+pub const LASTADDEDAT: Map<Addr, u64> = Map::new("last-added-at");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TokenBurn {
+    pub token: Addr,
+    /// Burnt through an allowance, or from the vault's own balance when unset.
+    pub owner: Option<Addr>,
+    pub amount: Uint128,
+}
+
+/// Vault accounting a payout applied ahead of its submessage, undone if it fails.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingPayout {
+    pub token: Addr,
+    pub receiver: Addr,
+    pub amount: Uint128,
+    pub pool_decrease: Uint128,
+    pub usdg_decrease: Uint128,
+    pub fee_reserved_increase: Uint128,
+    pub token_balance_decrease: Uint128,
+    pub bank_deposit_decrease: Uint128,
+    pub uncleared_usdg_debt_increase: Uint128,
+    /// Tokens burnt ahead of the payout, minted back on rollback.
+    pub burns: Vec<TokenBurn>,
+}
+
+// Keyed by the payout's reply id and removed in `reply` whichever way the
+// payout ends, so no entry outlives its transaction.
+pub const PENDINGPAYOUTS: Map<u64, PendingPayout> = Map::new("pending-payouts");
+pub const NEXTPAYOUTID: Item<u64> = Item::new("next-payout-id");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeeRecipient {
+    pub address: Addr,
+    /// Share of distributed fees in basis points; weights sum to 10000.
+    pub weight: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct FeeRecipientStats {
+    /// USD value (PRICE_PRECISION) of fees received, priced at distribution.
+    pub usd_received: Uint128,
+}
+
+pub const FEERECIPIENTS: Item<Vec<FeeRecipient>> = Item::new("fee-recipients");
+/// Token every fee reserve is converted into before it is split; reserves are
+/// split in kind when unset.
+pub const FEETOKEN: Item<Addr> = Item::new("fee-token");
+pub const FEERECIPIENTSTATS: Map<Addr, FeeRecipientStats> = Map::new("fee-recipient-stats");
+// Token amounts paid out, keyed by (recipient, token).
+pub const FEESDISTRIBUTED: Map<(Addr, Addr), Uint128> = Map::new("fees-distributed");